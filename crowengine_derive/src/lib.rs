@@ -0,0 +1,182 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Fields, Index};
+
+// Implements `Bundle` for a struct by inserting and removing each of its fields
+// as a bundle in declaration order. Fields can be components or other bundles.
+#[proc_macro_derive(Bundle)]
+pub fn derive_bundle(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return syn::Error::new_spanned(
+                &input.ident,
+                "Bundle can only be derived for structs!",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+
+    let bundle = quote!(crate::crowengine::bundle::Bundle);
+    let members: Vec<TokenStream2> = match fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(|field| {
+                let name = &field.ident;
+                quote!(#name)
+            })
+            .collect(),
+        Fields::Unnamed(fields) => (0..fields.unnamed.len())
+            .map(|index| {
+                let index = Index::from(index);
+                quote!(#index)
+            })
+            .collect(),
+        Fields::Unit => Vec::new(),
+    };
+    let types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+
+    let name = &input.ident;
+    let mut generics = input.generics.clone();
+    let where_clause = generics.make_where_clause();
+    for ty in &types {
+        where_clause
+            .predicates
+            .push(syn::parse_quote!(#ty: #bundle));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #bundle for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn insert_all(
+                self,
+                world: &mut crate::crowengine::world::World,
+                entity: crate::crowengine::entity::EntityId,
+            ) {
+                #(#bundle::insert_all(self.#members, world, entity);)*
+            }
+
+            #[allow(unused_variables)]
+            fn remove_all(
+                world: &mut crate::crowengine::world::World,
+                entity: crate::crowengine::entity::EntityId,
+            ) {
+                #(<#types as #bundle>::remove_all(world, entity);)*
+            }
+        }
+    }
+    .into()
+}
+
+// Implements `Reflect` and `FromReflect` for a struct. Fields are addressed by
+// name, or by index for tuple structs, and all of them have to be reflectable.
+#[proc_macro_derive(Reflect)]
+pub fn derive_reflect(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => {
+            return syn::Error::new_spanned(
+                &input.ident,
+                "Reflect can only be derived for structs!",
+            )
+            .to_compile_error()
+            .into();
+        }
+    };
+
+    let reflect = quote!(crate::crowengine::reflect::traits);
+    let value = quote!(crate::crowengine::reflect::value::Value);
+    let members: Vec<TokenStream2> = match fields {
+        Fields::Named(fields) => fields
+            .named
+            .iter()
+            .map(|field| {
+                let name = &field.ident;
+                quote!(#name)
+            })
+            .collect(),
+        _ => (0..fields.len())
+            .map(|index| {
+                let index = Index::from(index);
+                quote!(#index)
+            })
+            .collect(),
+    };
+    let names: Vec<String> = members.iter().map(ToString::to_string).collect();
+    let types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+
+    let name = &input.ident;
+    let mut generics = input.generics.clone();
+    let where_clause = generics.make_where_clause();
+    for ty in &types {
+        where_clause
+            .predicates
+            .push(syn::parse_quote!(#ty: #reflect::FromReflect));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #reflect::Reflect for #name #ty_generics #where_clause {
+            fn type_info() -> #reflect::TypeInfo {
+                #reflect::TypeInfo::Struct {
+                    fields: vec![#(#reflect::FieldInfo {
+                        name: #names,
+                        type_name: ::std::any::type_name::<#types>(),
+                    }),*],
+                }
+            }
+
+            fn reflect_type_name(&self) -> &'static str {
+                ::std::any::type_name::<Self>()
+            }
+
+            fn as_any(&self) -> &dyn ::std::any::Any {
+                self
+            }
+
+            fn as_any_mut(&mut self) -> &mut dyn ::std::any::Any {
+                self
+            }
+
+            fn field_names(&self) -> Vec<String> {
+                vec![#(#names.to_string()),*]
+            }
+
+            fn field(&self, name: &str) -> Option<&dyn #reflect::Reflect> {
+                match name {
+                    #(#names => Some(&self.#members),)*
+                    _ => None,
+                }
+            }
+
+            fn field_mut(&mut self, name: &str) -> Option<&mut dyn #reflect::Reflect> {
+                match name {
+                    #(#names => Some(&mut self.#members),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl #impl_generics #reflect::FromReflect for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn from_value(value: &#value) -> Result<Self, #reflect::ReflectError> {
+                if !matches!(value, #value::Struct(_)) {
+                    return Err(value.mismatch(::std::any::type_name::<Self>()));
+                }
+
+                Ok(Self {
+                    #(#members: #reflect::from_field(value, ::std::any::type_name::<Self>(), #names)?,)*
+                })
+            }
+        }
+    }
+    .into()
+}
@@ -0,0 +1,316 @@
+use super::events::{Event, Events, ManualEventReader};
+use super::resources::Resource;
+use super::state::{self, StateSchedule, StateSchedules, States};
+use super::systems::schedule::{IntoSystemConfig, Schedule, Stage};
+use super::time::{FixedUpdateSchedule, Time};
+use super::world::World;
+
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashSet;
+use std::mem;
+
+// Asks the runner to stop at the end of the frame.
+#[derive(Copy, Clone, Debug)]
+pub struct AppExit;
+
+impl Event for AppExit {}
+
+// A piece of engine or game setup, such as creating the window or adding the
+// render systems. Plugins are built as soon as they are added, in order.
+pub trait Plugin: Any {
+    fn build(&self, app: &mut App);
+
+    fn name(&self) -> &'static str {
+        type_name::<Self>()
+    }
+}
+
+// Plugins added together that can still be changed one by one before they are
+// built, e.g. `default_plugins().disable::<RenderPlugin>()`.
+pub struct PluginGroup {
+    plugins: Vec<Box<dyn Plugin>>,
+}
+
+#[allow(dead_code)]
+impl PluginGroup {
+    pub fn new() -> Self {
+        Self {
+            plugins: Vec::new(),
+        }
+    }
+
+    pub fn add<P: Plugin>(mut self, plugin: P) -> Self {
+        self.plugins.push(Box::new(plugin));
+        self
+    }
+
+    // Replaces the plugin of the same type, keeping its place in the group.
+    pub fn set<P: Plugin>(mut self, plugin: P) -> Self {
+        let index = self
+            .index_of::<P>()
+            .unwrap_or_else(|| panic!("Plugin {} is not part of the group!", type_name::<P>()));
+        self.plugins[index] = Box::new(plugin);
+        self
+    }
+
+    pub fn disable<P: Plugin>(mut self) -> Self {
+        if let Some(index) = self.index_of::<P>() {
+            self.plugins.remove(index);
+        }
+        self
+    }
+
+    fn index_of<P: Plugin>(&self) -> Option<usize> {
+        self.plugins
+            .iter()
+            .position(|plugin| (**plugin).type_id() == TypeId::of::<P>())
+    }
+}
+
+type Runner = Box<dyn FnOnce(App)>;
+
+// Owns the world and the schedule, and hands both to a runner that decides
+// when frames happen. Without a runner set by a plugin, `run` updates once.
+pub struct App {
+    pub world: World,
+    pub schedule: Schedule,
+    startup: Option<Schedule>,
+    runner: Runner,
+    plugins: HashSet<TypeId>,
+    exit_reader: ManualEventReader<AppExit>,
+}
+
+#[allow(dead_code)]
+impl App {
+    pub fn new() -> Self {
+        let mut world = World::empty();
+        world.add_event::<AppExit>();
+
+        Self {
+            world,
+            schedule: Schedule::new(),
+            startup: Some(Schedule::new()),
+            runner: Box::new(run_once),
+            plugins: HashSet::new(),
+            exit_reader: ManualEventReader::new(),
+        }
+    }
+
+    // Each plugin type can only be added once.
+    pub fn add_plugin<P: Plugin>(&mut self, plugin: P) -> &mut Self {
+        self.add_boxed_plugin(Box::new(plugin));
+        self
+    }
+
+    pub fn add_plugins(&mut self, group: PluginGroup) -> &mut Self {
+        for plugin in group.plugins {
+            self.add_boxed_plugin(plugin);
+        }
+        self
+    }
+
+    fn add_boxed_plugin(&mut self, plugin: Box<dyn Plugin>) {
+        if !self.plugins.insert((*plugin).type_id()) {
+            panic!("Plugin {} was already added!", plugin.name());
+        }
+        plugin.build(self);
+    }
+
+    pub fn is_plugin_added<P: Plugin>(&self) -> bool {
+        self.plugins.contains(&TypeId::of::<P>())
+    }
+
+    pub fn add_system<M>(&mut self, stage: Stage, system: impl IntoSystemConfig<M>) -> &mut Self {
+        self.schedule.add_system(stage, system);
+        self
+    }
+
+    // Runs once, before the first frame. Startup systems all share one stage
+    // and can be ordered with labels like any other system.
+    pub fn add_startup_system<M>(&mut self, system: impl IntoSystemConfig<M>) -> &mut Self {
+        self.startup
+            .as_mut()
+            .expect("Startup systems can't be added once the app has started!")
+            .add_system(Stage::Update, system);
+        self
+    }
+
+    // Adds a system to the `FixedUpdate` schedule of the `TimePlugin`.
+    pub fn add_fixed_system<M>(&mut self, system: impl IntoSystemConfig<M>) -> &mut Self {
+        self.world
+            .get_resource_mut::<FixedUpdateSchedule>()
+            .expect("Fixed systems need the TimePlugin!")
+            .add_system(system);
+        self
+    }
+
+    pub fn add_state<S: States>(&mut self, initial: S) -> &mut Self {
+        state::add_state(&mut self.world, &mut self.schedule, initial);
+        self
+    }
+
+    // Adds a system to the `OnEnter` or `OnExit` schedule of a state.
+    pub fn add_state_system<S: States, M>(
+        &mut self,
+        schedule: impl StateSchedule<S>,
+        system: impl IntoSystemConfig<M>,
+    ) -> &mut Self {
+        self.world
+            .get_resource_mut::<StateSchedules<S>>()
+            .unwrap_or_else(|| panic!("State {} was never added!", type_name::<S>()))
+            .add_system(schedule, system);
+        self
+    }
+
+    pub fn insert_resource<R: Resource>(&mut self, resource: R) -> &mut Self {
+        self.world.insert_resource(resource);
+        self
+    }
+
+    pub fn add_event<E: Event>(&mut self) -> &mut Self {
+        self.world.add_event::<E>();
+        self
+    }
+
+    pub fn set_runner(&mut self, runner: impl FnOnce(App) + 'static) -> &mut Self {
+        self.runner = Box::new(runner);
+        self
+    }
+
+    // One frame: advances `Time`, runs startup systems the first time, then
+    // the schedule.
+    pub fn update(&mut self) {
+        if let Some(time) = self.world.get_resource_mut::<Time>() {
+            time.update();
+        }
+
+        if let Some(mut startup) = self.startup.take() {
+            startup.run(&mut self.world);
+        }
+
+        self.world.update_events();
+        self.schedule.run(&mut self.world);
+        self.world.clear_trackers();
+    }
+
+    // Whether an `AppExit` was sent since the last call.
+    pub fn should_exit(&mut self) -> bool {
+        self.exit_reader
+            .read(self.world.resource::<Events<AppExit>>())
+            .count()
+            > 0
+    }
+
+    pub fn run(&mut self) {
+        let mut app = mem::replace(self, App::new());
+        let runner = mem::replace(&mut app.runner, Box::new(run_once));
+        runner(app);
+    }
+}
+
+pub fn run_once(mut app: App) {
+    app.update();
+}
+
+// Updates as fast as possible until something sends `AppExit`. Useful for
+// servers and tools that don't open a window.
+#[allow(dead_code)]
+pub fn run_until_exit(mut app: App) {
+    loop {
+        app.update();
+        if app.should_exit() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::events::EventWriter;
+    use crate::crowengine::systems::system_param::ResMut;
+    use std::sync::{Arc, Mutex};
+
+    struct Turn(u32);
+    impl Resource for Turn {}
+
+    struct TurnPlugin(u32);
+
+    impl Plugin for TurnPlugin {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(Turn(self.0));
+        }
+    }
+
+    struct CheatPlugin;
+
+    impl Plugin for CheatPlugin {
+        fn build(&self, app: &mut App) {
+            app.insert_resource(Turn(99));
+        }
+    }
+
+    #[test]
+    fn plugin_groups_can_be_changed_before_building() {
+        let mut app = App::new();
+        app.add_plugins(
+            PluginGroup::new()
+                .add(TurnPlugin(1))
+                .add(CheatPlugin)
+                .set(TurnPlugin(5))
+                .disable::<CheatPlugin>(),
+        );
+
+        assert!(app.is_plugin_added::<TurnPlugin>());
+        assert!(!app.is_plugin_added::<CheatPlugin>());
+        assert_eq!(app.world.resource::<Turn>().0, 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "was already added")]
+    fn plugins_can_only_be_added_once() {
+        App::new()
+            .add_plugin(TurnPlugin(1))
+            .add_plugin(TurnPlugin(2));
+    }
+
+    #[test]
+    fn runners_drive_frames_until_exit() {
+        let started = Arc::new(Mutex::new(0));
+        let finished = Arc::new(Mutex::new(false));
+        let (startup_count, runner_done) = (started.clone(), finished.clone());
+
+        let mut app = App::new();
+        app.add_plugin(TurnPlugin(0))
+            .add_startup_system(move |_: &mut World| *startup_count.lock().unwrap() += 1)
+            .add_system(
+                Stage::Update,
+                |mut turn: ResMut<Turn>, mut exit: EventWriter<AppExit>| {
+                    turn.0 += 1;
+                    if turn.0 == 8 {
+                        exit.send(AppExit);
+                    }
+                },
+            )
+            .set_runner(move |app| {
+                run_until_exit(app);
+                *runner_done.lock().unwrap() = true;
+            });
+        app.run();
+
+        assert_eq!(*started.lock().unwrap(), 1);
+        assert!(*finished.lock().unwrap());
+    }
+
+    #[test]
+    fn apps_without_a_runner_update_once() {
+        let frames = Arc::new(Mutex::new(0));
+        let counter = frames.clone();
+        let mut app = App::new();
+        app.add_system(Stage::Update, move |_: &mut World| {
+            *counter.lock().unwrap() += 1
+        });
+        app.run();
+        assert_eq!(*frames.lock().unwrap(), 1);
+    }
+}
@@ -0,0 +1,46 @@
+use super::app::{App, Plugin};
+use super::backends::render_backend::Renderer;
+use super::loaders::load_texture::load_texture;
+use super::resources::Resource;
+
+use std::collections::HashMap;
+
+// Textures by file path, so every file is only uploaded once.
+pub struct Textures {
+    loaded: HashMap<String, u32>,
+}
+
+impl Resource for Textures {}
+
+#[allow(dead_code)]
+impl Textures {
+    pub fn new() -> Self {
+        Self {
+            loaded: HashMap::new(),
+        }
+    }
+
+    // Returns the backend's id of the texture.
+    pub fn load(&mut self, renderer: &mut Renderer, path: &str) -> u32 {
+        *self
+            .loaded
+            .entry(path.to_string())
+            .or_insert_with(|| load_texture(renderer.backend_mut(), path))
+    }
+
+    // Deletes the texture from the backend. Entities still using its id draw
+    // with whatever texture gets that id next.
+    pub fn unload(&mut self, renderer: &mut Renderer, path: &str) {
+        if let Some(texture) = self.loaded.remove(path) {
+            renderer.backend_mut().delete_texture(texture);
+        }
+    }
+}
+
+pub struct AssetsPlugin;
+
+impl Plugin for AssetsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Textures::new());
+    }
+}
@@ -0,0 +1,308 @@
+extern crate gl;
+
+use super::render_backend::{PolygonMode, RenderBackend};
+
+use nalgebra_glm::{Mat4, Vec3};
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::thread::{self, ThreadId};
+
+// Renders through the OpenGL context that is current on the calling thread.
+// The `Renderer` is a resource, so nothing stops a parallel system from using
+// it on a worker thread, where there is no context. Every call checks that it
+// runs on the thread the backend was created on instead.
+pub struct GlBackend {
+    // The vertex and index buffers of every vertex array, to delete them along
+    // with it.
+    buffers: HashMap<u32, [u32; 2]>,
+    owner: ThreadId,
+}
+
+impl GlBackend {
+    // Create it on the thread the context is current on.
+    pub fn new() -> Self {
+        Self {
+            buffers: HashMap::new(),
+            owner: thread::current().id(),
+        }
+    }
+
+    fn check_thread(&self) {
+        if thread::current().id() != self.owner {
+            panic!(
+                "OpenGL can only be used on the thread owning the context, use the Renderer from exclusive systems!"
+            );
+        }
+    }
+
+    fn uniform_location(program: u32, name: &str) -> i32 {
+        let name = CString::new(name).unwrap();
+        unsafe { gl::GetUniformLocation(program, name.as_ptr()) }
+    }
+}
+
+impl RenderBackend for GlBackend {
+    fn create_mesh(&mut self, vertices: &[f32], indices: &[u32]) -> u32 {
+        self.check_thread();
+        let mut vao = 0;
+        let mut vbo = 0;
+        let mut ebo = 0;
+
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+            gl::GenBuffers(1, &mut ebo);
+
+            gl::BindVertexArray(vao);
+
+            // VBO
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(vertices) as isize,
+                vertices.as_ptr().cast(),
+                gl::STATIC_DRAW,
+            );
+
+            // EBO
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+            gl::BufferData(
+                gl::ELEMENT_ARRAY_BUFFER,
+                std::mem::size_of_val(indices) as isize,
+                indices.as_ptr().cast(),
+                gl::STATIC_DRAW,
+            );
+
+            // Vertex attribute
+
+            let stride = (8 * std::mem::size_of::<f32>()) as i32;
+
+            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
+            gl::EnableVertexAttribArray(0);
+
+            gl::VertexAttribPointer(
+                1,
+                2,
+                gl::FLOAT,
+                gl::FALSE,
+                stride,
+                (3 * std::mem::size_of::<f32>()) as *const _,
+            );
+            gl::EnableVertexAttribArray(1);
+
+            gl::VertexAttribPointer(
+                2,
+                3,
+                gl::FLOAT,
+                gl::FALSE,
+                stride,
+                (5 * std::mem::size_of::<f32>()) as *const _,
+            );
+            gl::EnableVertexAttribArray(2);
+            gl::BindVertexArray(0);
+        }
+
+        self.buffers.insert(vao, [vbo, ebo]);
+        vao
+    }
+
+    fn create_texture(&mut self, width: u32, height: u32, pixels: &[u8]) -> u32 {
+        self.check_thread();
+        let mut texture = 0;
+
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+
+            gl::TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::RGBA as i32,
+                width as i32,
+                height as i32,
+                0,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_ptr() as *const _,
+            );
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+
+            // Texture parameters (wrap, filter)
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
+            gl::TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MIN_FILTER,
+                gl::LINEAR_MIPMAP_LINEAR as i32,
+            );
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+        }
+
+        texture
+    }
+
+    fn create_shader(&mut self, vertex_source: &str, fragment_source: &str) -> u32 {
+        self.check_thread();
+        let vertex_shader = unsafe { gl::CreateShader(gl::VERTEX_SHADER) };
+        unsafe {
+            gl::ShaderSource(
+                vertex_shader,
+                1,
+                &vertex_source.as_bytes().as_ptr().cast(),
+                &(vertex_source.len() as i32),
+            );
+            gl::CompileShader(vertex_shader);
+        }
+
+        let fragment_shader = unsafe { gl::CreateShader(gl::FRAGMENT_SHADER) };
+        unsafe {
+            gl::ShaderSource(
+                fragment_shader,
+                1,
+                &fragment_source.as_bytes().as_ptr().cast(),
+                &(fragment_source.len() as i32),
+            );
+            gl::CompileShader(fragment_shader);
+        }
+
+        let shader_program = unsafe { gl::CreateProgram() };
+        unsafe {
+            gl::AttachShader(shader_program, vertex_shader);
+            gl::AttachShader(shader_program, fragment_shader);
+            gl::LinkProgram(shader_program);
+
+            gl::DetachShader(shader_program, vertex_shader);
+            gl::DetachShader(shader_program, fragment_shader);
+            gl::DeleteShader(vertex_shader);
+            gl::DeleteShader(fragment_shader);
+        }
+
+        shader_program
+    }
+
+    fn delete_mesh(&mut self, mesh: u32) {
+        self.check_thread();
+        let Some(buffers) = self.buffers.remove(&mesh) else {
+            return;
+        };
+        unsafe {
+            gl::DeleteVertexArrays(1, &mesh);
+            gl::DeleteBuffers(2, buffers.as_ptr());
+        }
+    }
+
+    fn delete_texture(&mut self, texture: u32) {
+        self.check_thread();
+        unsafe {
+            gl::DeleteTextures(1, &texture);
+        }
+    }
+
+    fn delete_shader(&mut self, program: u32) {
+        self.check_thread();
+        unsafe {
+            gl::DeleteProgram(program);
+        }
+    }
+
+    fn use_program(&mut self, program: u32) {
+        self.check_thread();
+        unsafe {
+            gl::UseProgram(program);
+        }
+    }
+
+    fn set_uniform_int(&mut self, program: u32, name: &str, value: i32) {
+        self.check_thread();
+        unsafe {
+            gl::Uniform1i(Self::uniform_location(program, name), value);
+        }
+    }
+
+    fn set_uniform_vec3(&mut self, program: u32, name: &str, value: &Vec3) {
+        self.check_thread();
+        unsafe {
+            gl::Uniform3f(
+                Self::uniform_location(program, name),
+                value.x,
+                value.y,
+                value.z,
+            );
+        }
+    }
+
+    fn set_uniform_mat4(&mut self, program: u32, name: &str, value: &Mat4) {
+        self.check_thread();
+        unsafe {
+            gl::UniformMatrix4fv(
+                Self::uniform_location(program, name),
+                1,
+                gl::FALSE,
+                value.as_ptr(),
+            );
+        }
+    }
+
+    fn bind_texture(&mut self, slot: u32, texture: u32) {
+        self.check_thread();
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + slot);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+        }
+    }
+
+    fn set_viewport(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        self.check_thread();
+        unsafe {
+            gl::Viewport(x, y, width, height);
+        }
+    }
+
+    fn set_polygon_mode(&mut self, mode: PolygonMode) {
+        self.check_thread();
+        let mode = match mode {
+            PolygonMode::Fill => gl::FILL,
+            PolygonMode::Line => gl::LINE,
+        };
+        unsafe {
+            gl::PolygonMode(gl::FRONT_AND_BACK, mode);
+        }
+    }
+
+    fn clear(&mut self, [red, green, blue, alpha]: [f32; 4]) {
+        self.check_thread();
+        unsafe {
+            gl::ClearColor(red, green, blue, alpha);
+
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        }
+    }
+
+    fn draw_indexed(&mut self, mesh: u32, index_count: i32) {
+        self.check_thread();
+        unsafe {
+            gl::BindVertexArray(mesh);
+            gl::DrawElements(
+                gl::TRIANGLES,
+                index_count,
+                gl::UNSIGNED_INT,
+                std::ptr::null(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The check comes before any GL call, so this doesn't need a context.
+    #[test]
+    fn calls_off_the_owning_thread_panic() {
+        let mut backend = GlBackend::new();
+        let result =
+            thread::scope(|scope| scope.spawn(|| backend.set_viewport(0, 0, 800, 600)).join());
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,3 @@
+pub mod gl_backend;
+pub mod recording_backend;
+pub mod render_backend;
@@ -0,0 +1,241 @@
+use super::render_backend::{PolygonMode, RenderBackend};
+
+use nalgebra_glm::{Mat4, Vec3};
+
+use std::collections::HashMap;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Uniform {
+    Int(i32),
+    Vec3(Vec3),
+    Mat4(Mat4),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum RenderCommand {
+    CreateMesh {
+        mesh: u32,
+        vertex_count: usize,
+        index_count: usize,
+    },
+    CreateTexture {
+        texture: u32,
+        width: u32,
+        height: u32,
+    },
+    CreateShader {
+        program: u32,
+    },
+    DeleteMesh(u32),
+    DeleteTexture(u32),
+    DeleteShader(u32),
+    UseProgram(u32),
+    SetUniform {
+        program: u32,
+        name: String,
+        value: Uniform,
+    },
+    BindTexture {
+        slot: u32,
+        texture: u32,
+    },
+    SetViewport {
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    },
+    SetPolygonMode(PolygonMode),
+    Clear([f32; 4]),
+    DrawIndexed {
+        mesh: u32,
+        index_count: i32,
+    },
+}
+
+// A draw call together with the state it was made in.
+#[derive(Clone, PartialEq, Debug)]
+pub struct DrawCall {
+    pub program: u32,
+    pub mesh: u32,
+    pub index_count: i32,
+    pub textures: HashMap<u32, u32>,
+    pub uniforms: HashMap<String, Uniform>,
+}
+
+#[allow(dead_code)]
+impl DrawCall {
+    pub fn mat4(&self, name: &str) -> Option<&Mat4> {
+        match self.uniforms.get(name) {
+            Some(Uniform::Mat4(value)) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn vec3(&self, name: &str) -> Option<&Vec3> {
+        match self.uniforms.get(name) {
+            Some(Uniform::Vec3(value)) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+// Logs every command instead of rendering, so tests can check what would have
+// been drawn without a GPU. Ids start at 1 and count up per kind of object.
+pub struct RecordingBackend {
+    commands: Vec<RenderCommand>,
+    meshes: u32,
+    textures: u32,
+    programs: u32,
+}
+
+#[allow(dead_code)]
+impl RecordingBackend {
+    pub fn new() -> Self {
+        Self {
+            commands: Vec::new(),
+            meshes: 0,
+            textures: 0,
+            programs: 0,
+        }
+    }
+
+    pub fn commands(&self) -> &[RenderCommand] {
+        &self.commands
+    }
+
+    pub fn clear_commands(&mut self) {
+        self.commands.clear();
+    }
+
+    // Replays the log and returns every draw call with the program, textures
+    // and uniforms that were in effect for it.
+    pub fn draw_calls(&self) -> Vec<DrawCall> {
+        let mut program = 0;
+        let mut textures = HashMap::new();
+        let mut uniforms: HashMap<u32, HashMap<String, Uniform>> = HashMap::new();
+        let mut draw_calls = Vec::new();
+
+        for command in &self.commands {
+            match command {
+                RenderCommand::UseProgram(used) => program = *used,
+                RenderCommand::SetUniform {
+                    program,
+                    name,
+                    value,
+                } => {
+                    uniforms
+                        .entry(*program)
+                        .or_default()
+                        .insert(name.clone(), value.clone());
+                }
+                RenderCommand::BindTexture { slot, texture } => {
+                    textures.insert(*slot, *texture);
+                }
+                RenderCommand::DrawIndexed { mesh, index_count } => draw_calls.push(DrawCall {
+                    program,
+                    mesh: *mesh,
+                    index_count: *index_count,
+                    textures: textures.clone(),
+                    uniforms: uniforms.get(&program).cloned().unwrap_or_default(),
+                }),
+                _ => {}
+            }
+        }
+
+        draw_calls
+    }
+
+    fn set_uniform(&mut self, program: u32, name: &str, value: Uniform) {
+        self.commands.push(RenderCommand::SetUniform {
+            program,
+            name: name.to_string(),
+            value,
+        });
+    }
+}
+
+impl RenderBackend for RecordingBackend {
+    fn create_mesh(&mut self, vertices: &[f32], indices: &[u32]) -> u32 {
+        self.meshes += 1;
+        self.commands.push(RenderCommand::CreateMesh {
+            mesh: self.meshes,
+            vertex_count: vertices.len() / 8,
+            index_count: indices.len(),
+        });
+        self.meshes
+    }
+
+    fn create_texture(&mut self, width: u32, height: u32, _pixels: &[u8]) -> u32 {
+        self.textures += 1;
+        self.commands.push(RenderCommand::CreateTexture {
+            texture: self.textures,
+            width,
+            height,
+        });
+        self.textures
+    }
+
+    fn create_shader(&mut self, _vertex_source: &str, _fragment_source: &str) -> u32 {
+        self.programs += 1;
+        self.commands.push(RenderCommand::CreateShader {
+            program: self.programs,
+        });
+        self.programs
+    }
+
+    fn delete_mesh(&mut self, mesh: u32) {
+        self.commands.push(RenderCommand::DeleteMesh(mesh));
+    }
+
+    fn delete_texture(&mut self, texture: u32) {
+        self.commands.push(RenderCommand::DeleteTexture(texture));
+    }
+
+    fn delete_shader(&mut self, program: u32) {
+        self.commands.push(RenderCommand::DeleteShader(program));
+    }
+
+    fn use_program(&mut self, program: u32) {
+        self.commands.push(RenderCommand::UseProgram(program));
+    }
+
+    fn set_uniform_int(&mut self, program: u32, name: &str, value: i32) {
+        self.set_uniform(program, name, Uniform::Int(value));
+    }
+
+    fn set_uniform_vec3(&mut self, program: u32, name: &str, value: &Vec3) {
+        self.set_uniform(program, name, Uniform::Vec3(*value));
+    }
+
+    fn set_uniform_mat4(&mut self, program: u32, name: &str, value: &Mat4) {
+        self.set_uniform(program, name, Uniform::Mat4(*value));
+    }
+
+    fn bind_texture(&mut self, slot: u32, texture: u32) {
+        self.commands
+            .push(RenderCommand::BindTexture { slot, texture });
+    }
+
+    fn set_viewport(&mut self, x: i32, y: i32, width: i32, height: i32) {
+        self.commands.push(RenderCommand::SetViewport {
+            x,
+            y,
+            width,
+            height,
+        });
+    }
+
+    fn set_polygon_mode(&mut self, mode: PolygonMode) {
+        self.commands.push(RenderCommand::SetPolygonMode(mode));
+    }
+
+    fn clear(&mut self, color: [f32; 4]) {
+        self.commands.push(RenderCommand::Clear(color));
+    }
+
+    fn draw_indexed(&mut self, mesh: u32, index_count: i32) {
+        self.commands
+            .push(RenderCommand::DrawIndexed { mesh, index_count });
+    }
+}
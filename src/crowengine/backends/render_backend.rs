@@ -0,0 +1,139 @@
+use super::super::resources::Resource;
+
+use nalgebra_glm::{Mat4, Vec3};
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PolygonMode {
+    Fill,
+    Line,
+}
+
+// Everything the renderer asks of the graphics API. Ids handed out by the
+// `create_` methods are only meaningful to the backend that created them, and
+// stay valid until they are passed to the matching `delete_` method.
+//
+// Meshes are interleaved vertices of a position, texture coordinates and a
+// color (eight floats) with `u32` indices, and textures are RGBA8 with the
+// bottom row first, as OpenGL expects them.
+pub trait RenderBackend: Any + Send + Sync {
+    fn create_mesh(&mut self, vertices: &[f32], indices: &[u32]) -> u32;
+
+    fn create_texture(&mut self, width: u32, height: u32, pixels: &[u8]) -> u32;
+
+    fn create_shader(&mut self, vertex_source: &str, fragment_source: &str) -> u32;
+
+    fn delete_mesh(&mut self, mesh: u32);
+
+    fn delete_texture(&mut self, texture: u32);
+
+    fn delete_shader(&mut self, program: u32);
+
+    fn use_program(&mut self, program: u32);
+
+    // Uniforms the program doesn't have are ignored.
+    fn set_uniform_int(&mut self, program: u32, name: &str, value: i32);
+
+    fn set_uniform_vec3(&mut self, program: u32, name: &str, value: &Vec3);
+
+    fn set_uniform_mat4(&mut self, program: u32, name: &str, value: &Mat4);
+
+    fn bind_texture(&mut self, slot: u32, texture: u32);
+
+    fn set_viewport(&mut self, x: i32, y: i32, width: i32, height: i32);
+
+    fn set_polygon_mode(&mut self, mode: PolygonMode);
+
+    // Clears the color buffer to `color` and the depth buffer.
+    fn clear(&mut self, color: [f32; 4]);
+
+    fn draw_indexed(&mut self, mesh: u32, index_count: i32);
+}
+
+#[allow(dead_code)]
+impl dyn RenderBackend {
+    pub fn downcast_ref<T: RenderBackend>(&self) -> Option<&T> {
+        (self as &dyn Any).downcast_ref()
+    }
+
+    pub fn downcast_mut<T: RenderBackend>(&mut self) -> Option<&mut T> {
+        (self as &mut dyn Any).downcast_mut()
+    }
+}
+
+// Identifies a mesh or shader to every `Renderer`. The backend ids can't be
+// kept in the mesh itself, since they are only valid for the backend that
+// created them and would go stale when the renderer is replaced.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct RenderId(u64);
+
+impl RenderId {
+    pub fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+// The backend everything is rendered with, along with the backend ids of the
+// meshes and shaders uploaded to it. They are uploaded the first time they are
+// drawn, so a new renderer uploads everything again. Only use it from exclusive
+// systems, since the OpenGL backend has to stay on the thread owning the
+// context.
+pub struct Renderer {
+    backend: Box<dyn RenderBackend>,
+    meshes: HashMap<RenderId, u32>,
+    programs: HashMap<RenderId, u32>,
+}
+
+impl Resource for Renderer {}
+
+#[allow(dead_code)]
+impl Renderer {
+    pub fn new(backend: impl RenderBackend) -> Self {
+        Self {
+            backend: Box::new(backend),
+            meshes: HashMap::new(),
+            programs: HashMap::new(),
+        }
+    }
+
+    pub fn backend(&self) -> &dyn RenderBackend {
+        self.backend.as_ref()
+    }
+
+    pub fn backend_mut(&mut self) -> &mut dyn RenderBackend {
+        self.backend.as_mut()
+    }
+
+    // The backend id of mesh `id`, uploading the vertices if it has none yet.
+    pub fn mesh(&mut self, id: RenderId, vertices: &[f32], indices: &[u32]) -> u32 {
+        *self
+            .meshes
+            .entry(id)
+            .or_insert_with(|| self.backend.create_mesh(vertices, indices))
+    }
+
+    // The backend id of shader `id`, compiling the sources if it has none yet.
+    pub fn program(&mut self, id: RenderId, vertex_source: &str, fragment_source: &str) -> u32 {
+        *self
+            .programs
+            .entry(id)
+            .or_insert_with(|| self.backend.create_shader(vertex_source, fragment_source))
+    }
+
+    // Deletes the mesh from the backend if it was ever uploaded.
+    pub fn free_mesh(&mut self, id: RenderId) {
+        if let Some(mesh) = self.meshes.remove(&id) {
+            self.backend.delete_mesh(mesh);
+        }
+    }
+
+    pub fn free_program(&mut self, id: RenderId) {
+        if let Some(program) = self.programs.remove(&id) {
+            self.backend.delete_shader(program);
+        }
+    }
+}
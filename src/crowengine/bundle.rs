@@ -1,68 +1,149 @@
-use super::commands::Commands;
 use super::components::components::Component;
 use super::entity::EntityId;
+use super::world::World;
 
+pub use crowengine_derive::Bundle;
+
+// A group of components inserted and removed together. Every component is a
+// bundle of one, and tuples or `#[derive(Bundle)]` structs of bundles are
+// bundles as well, so bundles can be nested.
 pub trait Bundle {
-    fn insert_all(self, commands: &mut Commands, entity: EntityId);
+    fn insert_all(self, world: &mut World, entity: EntityId);
+
+    fn remove_all(world: &mut World, entity: EntityId);
 }
 
-impl<A: Component> Bundle for (A,) {
-    fn insert_all(self, commands: &mut Commands, entity: EntityId) {
-        let (a,) = self;
-        commands.insert(entity, a);
+impl<C: Component> Bundle for C {
+    fn insert_all(self, world: &mut World, entity: EntityId) {
+        world.insert(entity, self);
     }
-}
 
-impl<A: Component, B: Component> Bundle for (A, B) {
-    fn insert_all(self, commands: &mut Commands, entity: EntityId) {
-        let (a, b) = self;
-        commands.insert(entity, a);
-        commands.insert(entity, b);
+    fn remove_all(world: &mut World, entity: EntityId) {
+        world.remove::<C>(entity);
     }
 }
 
-impl<A: Component, B: Component, C: Component> Bundle for (A, B, C) {
-    fn insert_all(self, commands: &mut Commands, entity: EntityId) {
-        let (a, b, c) = self;
-        commands.insert(entity, a);
-        commands.insert(entity, b);
-        commands.insert(entity, c);
-    }
+macro_rules! impl_bundle {
+    ($($name:ident),*) => {
+        impl<$($name: Bundle),*> Bundle for ($($name,)*) {
+            #[allow(non_snake_case, unused_variables)]
+            fn insert_all(self, world: &mut World, entity: EntityId) {
+                let ($($name,)*) = self;
+                $($name.insert_all(world, entity);)*
+            }
+
+            #[allow(unused_variables)]
+            fn remove_all(world: &mut World, entity: EntityId) {
+                $($name::remove_all(world, entity);)*
+            }
+        }
+    };
 }
 
-impl<A: Component, B: Component, C: Component, D: Component> Bundle for (A, B, C, D) {
-    fn insert_all(self, commands: &mut Commands, entity: EntityId) {
-        let (a, b, c, d) = self;
-        commands.insert(entity, a);
-        commands.insert(entity, b);
-        commands.insert(entity, c);
-        commands.insert(entity, d);
+impl_bundle!();
+all_tuples!(impl_bundle; B0, B1, B2, B3, B4, B5, B6, B7, B8, B9, B10, B11, B12, B13, B14, B15);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::components::components::StorageType;
+
+    #[derive(Debug, PartialEq)]
+    struct Unit(u32);
+    impl Component for Unit {}
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+    impl Component for Health {}
+
+    #[derive(Debug, PartialEq)]
+    struct Morale(u32);
+    impl Component for Morale {}
+
+    #[derive(Debug, PartialEq)]
+    struct Selected;
+    impl Component for Selected {
+        const STORAGE: StorageType = StorageType::SparseSet;
     }
-}
 
-impl<A: Component, B: Component, C: Component, D: Component, E: Component> Bundle
-    for (A, B, C, D, E)
-{
-    fn insert_all(self, commands: &mut Commands, entity: EntityId) {
-        let (a, b, c, d, e) = self;
-        commands.insert(entity, a);
-        commands.insert(entity, b);
-        commands.insert(entity, c);
-        commands.insert(entity, d);
-        commands.insert(entity, e);
+    #[derive(Bundle)]
+    struct Soldier {
+        unit: Unit,
+        health: Health,
     }
-}
 
-impl<A: Component, B: Component, C: Component, D: Component, E: Component, F: Component> Bundle
-    for (A, B, C, D, E, F)
-{
-    fn insert_all(self, commands: &mut Commands, entity: EntityId) {
-        let (a, b, c, d, e, f) = self;
-        commands.insert(entity, a);
-        commands.insert(entity, b);
-        commands.insert(entity, c);
-        commands.insert(entity, d);
-        commands.insert(entity, e);
-        commands.insert(entity, f);
+    #[derive(Bundle)]
+    struct Veteran(Soldier, Morale);
+
+    #[test]
+    fn derived_bundles_nest() {
+        let mut world = World::empty();
+        let veteran = world.spawn((
+            Veteran(
+                Soldier {
+                    unit: Unit(1),
+                    health: Health(2),
+                },
+                Morale(3),
+            ),
+            Selected,
+        ));
+        assert_eq!(world.get::<Unit>(&veteran), Some(&Unit(1)));
+        assert_eq!(world.get::<Health>(&veteran), Some(&Health(2)));
+        assert_eq!(world.get::<Morale>(&veteran), Some(&Morale(3)));
+
+        world.entity_mut(veteran).remove_bundle::<Veteran>();
+        assert_eq!(world.get::<Unit>(&veteran), None);
+        assert_eq!(world.get::<Morale>(&veteran), None);
+        assert_eq!(world.get::<Selected>(&veteran), Some(&Selected));
+    }
+
+    #[test]
+    fn tuples_go_up_to_sixteen() {
+        let mut world = World::empty();
+        let entity = world.spawn((
+            Unit(0),
+            Unit(1),
+            Unit(2),
+            Unit(3),
+            Unit(4),
+            Unit(5),
+            Unit(6),
+            Unit(7),
+            Unit(8),
+            Unit(9),
+            Unit(10),
+            Unit(11),
+            Unit(12),
+            Unit(13),
+            Unit(14),
+            Unit(15),
+        ));
+        // Later components of the same type replace earlier ones.
+        assert_eq!(world.get::<Unit>(&entity), Some(&Unit(15)));
+
+        world.insert(entity, Health(4));
+        let healths: Vec<u32> = world
+            .query::<(
+                &Unit,
+                &Health,
+                &Health,
+                &Health,
+                &Health,
+                &Health,
+                &Health,
+                &Health,
+                &Health,
+                &Health,
+                &Health,
+                &Health,
+                &Health,
+                &Health,
+                &Health,
+                &Health,
+            )>()
+            .map(|item| item.15 .0)
+            .collect();
+        assert_eq!(healths, [4]);
     }
 }
@@ -0,0 +1,173 @@
+use super::bundle::Bundle;
+use super::components::components::Component;
+use super::entity::EntityId;
+use super::world::World;
+
+type Command = Box<dyn FnOnce(&mut World) + Send>;
+
+// Records world mutations so they can be issued while the world is borrowed,
+// e.g. from inside a `World::query` loop or a system, and applied later by
+// `World::apply`.
+pub struct CommandQueue {
+    commands: Vec<Command>,
+}
+
+#[allow(dead_code)]
+impl CommandQueue {
+    pub fn new() -> Self {
+        Self {
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn push<F: FnOnce(&mut World) + Send + 'static>(&mut self, command: F) {
+        self.commands.push(Box::new(command));
+    }
+
+    pub fn reserve(&mut self, world: &World) -> EntityId {
+        world.reserve_entity()
+    }
+
+    pub fn spawn<B: Bundle + Send + 'static>(&mut self, world: &World, bundle: B) -> EntityId {
+        let entity = world.reserve_entity();
+        self.insert_bundle(entity, bundle);
+        entity
+    }
+
+    // Commands targeting an entity that was despawned before the queue is
+    // applied are dropped instead of panicking.
+    pub fn insert<T: Component>(&mut self, entity: EntityId, component: T) {
+        self.push(move |world| {
+            if world.contains(entity) {
+                world.insert(entity, component);
+            }
+        });
+    }
+
+    pub fn insert_bundle<B: Bundle + Send + 'static>(&mut self, entity: EntityId, bundle: B) {
+        self.push(move |world| {
+            if world.contains(entity) {
+                bundle.insert_all(world, entity);
+            }
+        });
+    }
+
+    pub fn remove<T: Component>(&mut self, entity: EntityId) {
+        self.push(move |world| {
+            world.remove::<T>(entity);
+        });
+    }
+
+    // `f` gets the removed component once the queue is applied, and isn't
+    // called if the entity didn't have one by then.
+    pub fn take<T: Component, F: FnOnce(T, &mut World) + Send + 'static>(
+        &mut self,
+        entity: EntityId,
+        f: F,
+    ) {
+        self.push(move |world| {
+            if let Some(component) = world.remove::<T>(entity) {
+                f(component, world);
+            }
+        });
+    }
+
+    pub fn remove_bundle<B: Bundle + Send + 'static>(&mut self, entity: EntityId) {
+        self.push(move |world| {
+            if world.contains(entity) {
+                B::remove_all(world, entity);
+            }
+        });
+    }
+
+    pub fn despawn(&mut self, entity: EntityId) {
+        self.push(move |world| {
+            world.despawn(entity);
+        });
+    }
+
+    pub(super) fn apply(&mut self, world: &mut World) {
+        for command in self.commands.drain(..) {
+            command(world);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Settler(u32);
+    impl Component for Settler {}
+
+    #[derive(Debug, PartialEq)]
+    struct City(u32);
+    impl Component for City {}
+
+    #[test]
+    fn commands_issued_while_borrowing_apply_in_order() {
+        let mut world = World::empty();
+        let settlers: Vec<EntityId> = (0..3)
+            .map(|i| {
+                let entity = world.spawn_empty();
+                world.insert(entity, Settler(i));
+                entity
+            })
+            .collect();
+
+        let mut queue = CommandQueue::new();
+        for entity in &settlers {
+            let settler = world.get::<Settler>(entity).unwrap();
+            queue.insert(*entity, City(settler.0 + 10));
+            queue.remove::<Settler>(*entity);
+        }
+        let capital = queue.spawn(&world, (City(99),));
+        queue.push(move |world| world.get_mut::<City>(&capital).unwrap().0 += 1);
+        assert_eq!(queue.len(), 8);
+
+        world.apply(&mut queue);
+        assert!(queue.is_empty());
+        assert_eq!(world.query::<(&Settler,)>().count(), 0);
+        let mut cities: Vec<u32> = world.query::<(&City,)>().map(|(city,)| city.0).collect();
+        cities.sort();
+        assert_eq!(cities, [10, 11, 12, 100]);
+    }
+
+    #[test]
+    fn reserved_entities_exist_once_applied() {
+        let mut world = World::empty();
+        let mut queue = CommandQueue::new();
+        let entity = queue.reserve(&world);
+        let spawned = queue.spawn(&world, (Settler(1),));
+        assert_ne!(entity, spawned);
+        assert!(!world.contains(entity));
+
+        world.apply(&mut queue);
+        assert!(world.contains(entity) && world.contains(spawned));
+        assert_ne!(world.spawn_empty(), entity);
+    }
+
+    #[test]
+    fn commands_for_despawned_entities_are_dropped() {
+        let mut world = World::empty();
+        let entity = world.spawn_empty();
+        world.insert(entity, Settler(1));
+
+        let mut queue = CommandQueue::new();
+        queue.despawn(entity);
+        queue.insert(entity, City(1));
+        queue.insert_bundle(entity, (City(2),));
+        queue.remove_bundle::<(Settler,)>(entity);
+        world.apply(&mut queue);
+        assert!(!world.contains(entity));
+    }
+}
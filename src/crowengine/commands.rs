@@ -1,31 +1,219 @@
 use super::bundle::Bundle;
+use super::command_queue::CommandQueue;
 use super::components::components::Component;
+use super::components::hooks::Trigger;
 use super::entity::EntityId;
+use super::prefab;
 use super::world::World;
 
-pub struct Commands<'a> {
-    pub next_id: u32,
-    world: &'a mut World,
+// Deferred world mutations for systems, which only see the world through a
+// shared borrow. Everything is recorded into `queue` and applied once the
+// system's stage has finished.
+pub struct Commands<'w, 's> {
+    world: &'w World,
+    queue: &'s mut CommandQueue,
 }
 
-impl<'a> Commands<'a> {
-    pub fn new(world: &'a mut World) -> Self {
-        Commands { next_id: 0, world }
+#[allow(dead_code)]
+impl<'w, 's> Commands<'w, 's> {
+    pub fn new(queue: &'s mut CommandQueue, world: &'w World) -> Self {
+        Commands { world, queue }
     }
 
-    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> EntityId {
-        let entity = self.create_entity();
-        bundle.insert_all(self, entity);
+    // The returned id is reserved right away, so it can be stored in other
+    // components before the entity actually exists.
+    pub fn spawn<B: Bundle + Send + 'static>(&mut self, bundle: B) -> EntityId {
+        self.queue.spawn(self.world, bundle)
+    }
+
+    // If the prefab can't be spawned when the command is applied, the error is
+    // reported and the entity stays despawned, so a broken prefab file doesn't
+    // take the running game down.
+    pub fn spawn_prefab(&mut self, name: &str) -> EntityId {
+        let entity = self.spawn_empty();
+        let name = name.to_string();
+        self.add(move |world: &mut World| {
+            if let Err(error) = prefab::spawn_prefab(world, entity, &name) {
+                eprintln!("Could not spawn prefab '{}': {}!", name, error);
+            }
+        });
         entity
     }
 
+    pub fn spawn_empty(&mut self) -> EntityId {
+        self.queue.reserve(self.world)
+    }
+
+    pub fn entity(&mut self, entity: EntityId) -> EntityCommands<'_, 'w, 's> {
+        EntityCommands {
+            entity,
+            commands: self,
+        }
+    }
+
     pub fn insert<T: Component>(&mut self, entity: EntityId, component: T) {
-        self.world.insert(entity, component);
+        self.queue.insert(entity, component);
+    }
+
+    pub fn remove<T: Component>(&mut self, entity: EntityId) {
+        self.queue.remove::<T>(entity);
+    }
+
+    // Removes the component when the commands are applied and hands it to `f`,
+    // e.g. to move it to another entity.
+    pub fn take<T: Component, F: FnOnce(T, &mut World) + Send + 'static>(
+        &mut self,
+        entity: EntityId,
+        f: F,
+    ) {
+        self.queue.take::<T, F>(entity, f);
+    }
+
+    pub fn despawn(&mut self, entity: EntityId) {
+        self.queue.despawn(entity);
+    }
+
+    pub fn add<F: FnOnce(&mut World) + Send + 'static>(&mut self, command: F) {
+        self.queue.push(command);
     }
+}
+
+#[allow(dead_code)]
+pub struct EntityCommands<'c, 'w, 's> {
+    entity: EntityId,
+    commands: &'c mut Commands<'w, 's>,
+}
+
+#[allow(dead_code)]
+impl EntityCommands<'_, '_, '_> {
+    pub fn id(&self) -> EntityId {
+        self.entity
+    }
+
+    pub fn insert<T: Component>(&mut self, component: T) -> &mut Self {
+        self.commands.insert(self.entity, component);
+        self
+    }
+
+    pub fn remove<T: Component>(&mut self) -> &mut Self {
+        self.commands.remove::<T>(self.entity);
+        self
+    }
+
+    pub fn take<T: Component, F: FnOnce(T, &mut World) + Send + 'static>(
+        &mut self,
+        f: F,
+    ) -> &mut Self {
+        self.commands.take::<T, F>(self.entity, f);
+        self
+    }
+
+    pub fn insert_bundle<B: Bundle + Send + 'static>(&mut self, bundle: B) -> &mut Self {
+        self.commands.queue.insert_bundle(self.entity, bundle);
+        self
+    }
+
+    pub fn remove_bundle<B: Bundle + Send + 'static>(&mut self) -> &mut Self {
+        self.commands.queue.remove_bundle::<B>(self.entity);
+        self
+    }
+
+    pub fn set_parent(&mut self, parent: EntityId) -> &mut Self {
+        let entity = self.entity;
+        self.commands
+            .add(move |world: &mut World| world.set_parent(entity, parent));
+        self
+    }
+
+    pub fn remove_parent(&mut self) -> &mut Self {
+        let entity = self.entity;
+        self.commands
+            .add(move |world: &mut World| world.remove_parent(entity));
+        self
+    }
+
+    pub fn observe<T: Component>(
+        &mut self,
+        trigger: Trigger,
+        observer: impl FnMut(&mut World, EntityId) + Send + Sync + 'static,
+    ) -> &mut Self {
+        let entity = self.entity;
+        self.commands.add(move |world: &mut World| {
+            world.observe::<T>(entity, trigger, observer);
+        });
+        self
+    }
+
+    pub fn despawn(self) {
+        self.commands.despawn(self.entity);
+    }
+
+    pub fn despawn_recursive(self) {
+        let entity = self.entity;
+        self.commands.add(move |world: &mut World| {
+            world.despawn_recursive(entity);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Gold(u32);
+    impl Component for Gold {}
+
+    #[test]
+    fn take_hands_the_component_over_when_applied() {
+        let mut world = World::empty();
+        let looted = world.spawn(Gold(30));
+        let looter = world.spawn(Gold(5));
+
+        let mut queue = CommandQueue::new();
+        Commands::new(&mut queue, &world)
+            .entity(looted)
+            .take::<Gold, _>(move |gold, world| {
+                world.get_mut::<Gold>(&looter).unwrap().0 += gold.0
+            });
+        assert_eq!(world.get::<Gold>(&looted), Some(&Gold(30)));
+
+        world.apply(&mut queue);
+        assert_eq!(world.get::<Gold>(&looted), None);
+        assert_eq!(world.get::<Gold>(&looter), Some(&Gold(35)));
+    }
+
+    #[test]
+    fn take_skips_missing_components() {
+        let mut world = World::empty();
+        let entity = world.spawn_empty();
+
+        let mut queue = CommandQueue::new();
+        Commands::new(&mut queue, &world)
+            .take::<Gold, _>(entity, |_, _| panic!("No gold to take!"));
+        world.apply(&mut queue);
+    }
+
+    #[test]
+    fn entity_commands_wait_for_apply() {
+        let mut world = World::empty();
+        let treasury = world.spawn_empty();
+        world.insert(treasury, Gold(10));
+
+        let mut queue = CommandQueue::new();
+        let mut commands = Commands::new(&mut queue, &world);
+        let mine = commands.spawn((Gold(1),));
+        commands
+            .entity(treasury)
+            .remove::<Gold>()
+            .insert(Gold(20))
+            .remove_bundle::<(Gold,)>();
+        commands.entity(mine).insert(Gold(2));
+        assert!(!world.contains(mine));
+        assert_eq!(world.get::<Gold>(&treasury), Some(&Gold(10)));
 
-    pub fn create_entity(&mut self) -> EntityId {
-        let id = EntityId(self.next_id);
-        self.next_id += 1;
-        id
+        world.apply(&mut queue);
+        assert_eq!(world.get::<Gold>(&treasury), None);
+        assert_eq!(world.get::<Gold>(&mine), Some(&Gold(2)));
     }
 }
@@ -0,0 +1,196 @@
+use super::super::entity::EntityId;
+use super::change_detection::{ComponentTicks, Tick};
+use super::components::Component;
+
+use std::any::{Any, TypeId};
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+
+// Type-erased view of a single component column so rows can be moved between
+// archetypes without knowing the component types involved.
+pub trait Column: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn new_empty(&self) -> Box<dyn Column>;
+    fn swap_remove_drop(&mut self, row: usize);
+    fn move_row(&mut self, row: usize, dst: &mut dyn Column);
+}
+
+// Every value and its ticks sit in their own `UnsafeCell`, so validated queries
+// can hand out `&mut T` to several rows and columns of the same archetype at once
+// through a shared borrow of the column. `ticks[row]` records when `data[row]`
+// was added and last changed.
+pub struct TypedColumn<T> {
+    data: Vec<UnsafeCell<T>>,
+    ticks: Vec<UnsafeCell<ComponentTicks>>,
+}
+
+// Safety: the `Vec`s themselves are only changed through `&mut self`. Their
+// elements are written through shared borrows only by queries whose access was
+// checked against every other system running at the same time.
+unsafe impl<T: Component> Sync for TypedColumn<T> {}
+
+impl<T: Component> TypedColumn<T> {
+    pub fn new() -> Self {
+        Self {
+            data: Vec::new(),
+            ticks: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, row: usize) -> &T {
+        // Safety: mutable access through `get_ptr` is only handed out by
+        // queries whose access was validated not to overlap with this borrow.
+        unsafe { &*self.data[row].get() }
+    }
+
+    pub fn get_ptr(&self, row: usize) -> *mut T {
+        self.data[row].get()
+    }
+
+    pub fn get_ticks_ptr(&self, row: usize) -> *mut ComponentTicks {
+        self.ticks[row].get()
+    }
+
+    pub fn push(&mut self, component: T, ticks: ComponentTicks) {
+        self.data.push(UnsafeCell::new(component));
+        self.ticks.push(UnsafeCell::new(ticks));
+    }
+
+    pub fn replace(&mut self, row: usize, component: T, tick: Tick) {
+        *self.data[row].get_mut() = component;
+        self.ticks[row].get_mut().changed = tick;
+    }
+
+    pub fn get_mut(&mut self, row: usize, tick: Tick) -> &mut T {
+        self.ticks[row].get_mut().changed = tick;
+        self.data[row].get_mut()
+    }
+
+    pub fn swap_remove(&mut self, row: usize) -> T {
+        self.ticks.swap_remove(row);
+        self.data.swap_remove(row).into_inner()
+    }
+}
+
+impl<T: Component> Column for TypedColumn<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn new_empty(&self) -> Box<dyn Column> {
+        Box::new(TypedColumn::<T>::new())
+    }
+
+    fn swap_remove_drop(&mut self, row: usize) {
+        self.swap_remove(row);
+    }
+
+    fn move_row(&mut self, row: usize, dst: &mut dyn Column) {
+        let ticks = *self.ticks[row].get_mut();
+        let value = self.swap_remove(row);
+        dst.as_any_mut()
+            .downcast_mut::<TypedColumn<T>>()
+            .unwrap()
+            .push(value, ticks);
+    }
+}
+
+// A table of every entity sharing exactly the same set of component types.
+// `types` is kept sorted and `columns[i]` stores the components of `types[i]`.
+pub struct Archetype {
+    types: Vec<TypeId>,
+    columns: Vec<Box<dyn Column>>,
+    entities: Vec<EntityId>,
+    pub(super) add_edges: HashMap<TypeId, usize>,
+    pub(super) remove_edges: HashMap<TypeId, usize>,
+}
+
+impl Archetype {
+    pub fn new(types: Vec<TypeId>, columns: Vec<Box<dyn Column>>) -> Self {
+        Self {
+            types,
+            columns,
+            entities: Vec::new(),
+            add_edges: HashMap::new(),
+            remove_edges: HashMap::new(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn types(&self) -> &[TypeId] {
+        &self.types
+    }
+
+    pub fn entities(&self) -> &[EntityId] {
+        &self.entities
+    }
+
+    pub fn has<T: Component>(&self) -> bool {
+        self.column_index(TypeId::of::<T>()).is_some()
+    }
+
+    pub fn column<T: Component>(&self) -> Option<&TypedColumn<T>> {
+        let index = self.column_index(TypeId::of::<T>())?;
+        self.columns[index]
+            .as_any()
+            .downcast_ref::<TypedColumn<T>>()
+    }
+
+    pub fn column_mut<T: Component>(&mut self) -> Option<&mut TypedColumn<T>> {
+        let index = self.column_index(TypeId::of::<T>())?;
+        self.columns[index]
+            .as_any_mut()
+            .downcast_mut::<TypedColumn<T>>()
+    }
+
+    pub(super) fn columns(&self) -> &[Box<dyn Column>] {
+        &self.columns
+    }
+
+    pub(super) fn push_entity(&mut self, entity: EntityId) -> usize {
+        self.entities.push(entity);
+        self.entities.len() - 1
+    }
+
+    // Returns the entity that was swapped into `row`, if any.
+    fn swap_remove_entity(&mut self, row: usize) -> Option<EntityId> {
+        self.entities.swap_remove(row);
+        self.entities.get(row).copied()
+    }
+
+    pub(super) fn drop_row(&mut self, row: usize) -> Option<EntityId> {
+        for column in self.columns.iter_mut() {
+            column.swap_remove_drop(row);
+        }
+        self.swap_remove_entity(row)
+    }
+
+    // Moves every column of `row` that also exists in `dst`. Columns missing
+    // from `dst` must already have had the row removed by the caller.
+    pub(super) fn move_row(&mut self, row: usize, dst: &mut Archetype) -> Option<EntityId> {
+        for (type_id, column) in self.types.iter().zip(self.columns.iter_mut()) {
+            if let Some(index) = dst.column_index(*type_id) {
+                column.move_row(row, dst.columns[index].as_mut());
+            }
+        }
+        self.swap_remove_entity(row)
+    }
+
+    fn column_index(&self, type_id: TypeId) -> Option<usize> {
+        self.types.binary_search(&type_id).ok()
+    }
+}
@@ -1,6 +1,12 @@
+use super::super::reflect::traits::Reflect;
+use super::components::Component;
+
+#[derive(Reflect)]
 pub struct Camera {
     pub fov_y: f32,
     pub near: f32,
     pub far: f32,
     pub aspect_ratio: f32,
 }
+
+impl Component for Camera {}
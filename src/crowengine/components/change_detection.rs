@@ -0,0 +1,300 @@
+use super::super::entity::EntityId;
+use super::archetype::Archetype;
+use super::components::{ColumnRef, Component, ComponentStorage, StorageType};
+use super::filter::QueryFilter;
+use super::query::Access;
+
+use std::marker::PhantomData;
+
+// A point in the world's change history. Ticks wrap around, so they are only
+// compared relative to the tick of the system doing the comparison.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Tick(pub u32);
+
+impl Tick {
+    pub fn is_newer_than(self, last_run: Tick, this_run: Tick) -> bool {
+        this_run.0.wrapping_sub(self.0) < this_run.0.wrapping_sub(last_run.0)
+    }
+}
+
+// The window a query looks at: changes after `last_run` up to and including `this_run`.
+#[derive(Copy, Clone, Debug)]
+pub struct Ticks {
+    pub last_run: Tick,
+    pub this_run: Tick,
+}
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub struct ComponentTicks {
+    pub added: Tick,
+    pub changed: Tick,
+}
+
+impl ComponentTicks {
+    pub fn new(tick: Tick) -> Self {
+        Self {
+            added: tick,
+            changed: tick,
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub struct Added<T>(PhantomData<T>);
+
+#[allow(dead_code)]
+pub struct Changed<T>(PhantomData<T>);
+
+impl<T: Component> QueryFilter for Added<T> {
+    type Column<'a> = (ColumnRef<'a, T>, Ticks);
+
+    fn access(access: &mut Access) {
+        access.add_filter_read::<T>();
+    }
+
+    fn matches_archetype(archetype: &Archetype) -> bool {
+        T::STORAGE == StorageType::SparseSet || archetype.has::<T>()
+    }
+
+    fn column<'a>(
+        storage: &'a ComponentStorage,
+        archetype: &'a Archetype,
+        ticks: Ticks,
+    ) -> Self::Column<'a> {
+        (storage.column_ref::<T>(archetype), ticks)
+    }
+
+    fn filter((column, ticks): &Self::Column<'_>, entity: EntityId, row: usize) -> bool {
+        column.ticks(entity, row).is_some_and(|component| {
+            component
+                .added
+                .is_newer_than(ticks.last_run, ticks.this_run)
+        })
+    }
+}
+
+// Inserting a component counts as changing it, as does every mutable access
+// through `World::query_mut` or `World::get_mut`.
+impl<T: Component> QueryFilter for Changed<T> {
+    type Column<'a> = (ColumnRef<'a, T>, Ticks);
+
+    fn access(access: &mut Access) {
+        access.add_filter_read::<T>();
+    }
+
+    fn matches_archetype(archetype: &Archetype) -> bool {
+        T::STORAGE == StorageType::SparseSet || archetype.has::<T>()
+    }
+
+    fn column<'a>(
+        storage: &'a ComponentStorage,
+        archetype: &'a Archetype,
+        ticks: Ticks,
+    ) -> Self::Column<'a> {
+        (storage.column_ref::<T>(archetype), ticks)
+    }
+
+    fn filter((column, ticks): &Self::Column<'_>, entity: EntityId, row: usize) -> bool {
+        column.ticks(entity, row).is_some_and(|component| {
+            component
+                .changed
+                .is_newer_than(ticks.last_run, ticks.this_run)
+        })
+    }
+}
+
+// Entities that lost a `T` (through removal or despawn) inside the tick window.
+#[allow(dead_code)]
+pub struct RemovedComponents<'a, T> {
+    removed: &'a [(EntityId, Tick)],
+    ticks: Ticks,
+    marker: PhantomData<T>,
+}
+
+#[allow(dead_code)]
+impl<'a, T: Component> RemovedComponents<'a, T> {
+    pub fn new(removed: &'a [(EntityId, Tick)], ticks: Ticks) -> Self {
+        Self {
+            removed,
+            ticks,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = EntityId> + 'a {
+        let ticks = self.ticks;
+        self.removed
+            .iter()
+            .filter(move |(_, tick)| tick.is_newer_than(ticks.last_run, ticks.this_run))
+            .map(|(entity, _)| *entity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::components::filter::With;
+    use crate::crowengine::systems::schedule::{Schedule, Stage};
+    use crate::crowengine::systems::system_param::Query;
+    use crate::crowengine::world::World;
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[derive(Debug, PartialEq)]
+    struct Gold(u32);
+    impl Component for Gold {}
+
+    struct Capital;
+    impl Component for Capital {}
+
+    #[derive(Debug, PartialEq)]
+    struct Selected;
+    impl Component for Selected {
+        const STORAGE: StorageType = StorageType::SparseSet;
+    }
+
+    fn spawn_city(world: &mut World, gold: u32) -> EntityId {
+        let entity = world.spawn_empty();
+        world.insert(entity, Gold(gold));
+        entity
+    }
+
+    fn matching<F: QueryFilter>(world: &World) -> Vec<EntityId> {
+        let mut entities: Vec<EntityId> = world
+            .query_filtered::<(EntityId,), F>()
+            .map(|(entity,)| entity)
+            .collect();
+        entities.sort_by_key(|entity| entity.index);
+        entities
+    }
+
+    #[test]
+    fn ticks_compare_across_wrapping() {
+        let ticks = Ticks {
+            last_run: Tick(u32::MAX - 1),
+            this_run: Tick(2),
+        };
+        assert!(Tick(u32::MAX).is_newer_than(ticks.last_run, ticks.this_run));
+        assert!(Tick(1).is_newer_than(ticks.last_run, ticks.this_run));
+        assert!(!Tick(u32::MAX - 1).is_newer_than(ticks.last_run, ticks.this_run));
+        assert!(!Tick(3).is_newer_than(ticks.last_run, ticks.this_run));
+    }
+
+    #[test]
+    fn added_and_changed_last_one_frame() {
+        let mut world = World::empty();
+        let city = spawn_city(&mut world, 0);
+        let capital = spawn_city(&mut world, 1);
+        world.insert(capital, Capital);
+        world.insert(city, Selected);
+        assert_eq!(matching::<Added<Gold>>(&world), [city, capital]);
+        assert_eq!(matching::<Added<Selected>>(&world), [city]);
+
+        world.clear_trackers();
+        assert!(matching::<Added<Gold>>(&world).is_empty());
+        assert!(matching::<Changed<Gold>>(&world).is_empty());
+
+        for (gold,) in world.query_filtered_mut::<(&mut Gold,), With<Capital>>() {
+            gold.0 += 1;
+        }
+        world.get_mut::<Selected>(&city).unwrap();
+        assert_eq!(matching::<Changed<Gold>>(&world), [capital]);
+        assert_eq!(matching::<Changed<Selected>>(&world), [city]);
+        assert!(matching::<(Changed<Gold>, Added<Gold>)>(&world).is_empty());
+        assert_eq!(
+            world
+                .query_filtered_mut::<(&mut Gold,), Changed<Gold>>()
+                .count(),
+            1
+        );
+
+        world.clear_trackers();
+        assert!(matching::<Changed<Gold>>(&world).is_empty());
+    }
+
+    #[test]
+    fn removals_are_seen_until_the_frame_ends() {
+        let mut world = World::empty();
+        let city = spawn_city(&mut world, 0);
+        world.insert(city, Selected);
+        let capital = spawn_city(&mut world, 1);
+        world.insert(capital, Capital);
+        world.clear_trackers();
+
+        world.remove::<Capital>(capital);
+        world.despawn(city);
+        assert_eq!(
+            world.removed::<Capital>().iter().collect::<Vec<_>>(),
+            [capital]
+        );
+        assert_eq!(
+            world.removed::<Selected>().iter().collect::<Vec<_>>(),
+            [city]
+        );
+        assert_eq!(world.removed::<Gold>().iter().collect::<Vec<_>>(), [city]);
+
+        world.clear_trackers();
+        assert_eq!(world.removed::<Capital>().iter().count(), 0);
+    }
+
+    // Both readers wait for each other before looking at the ticks, so the
+    // filters of the two queries read the same columns at the same time.
+    #[test]
+    fn parallel_readers_share_ticks() {
+        type NewlySelected = (Changed<Gold>, Added<Selected>);
+
+        fn reader(
+            send: Sender<()>,
+            receive: Receiver<()>,
+            seen: Arc<Mutex<Vec<usize>>>,
+        ) -> impl FnMut(Query<(&Gold,), NewlySelected>) + Send + 'static {
+            let (send, receive) = (Mutex::new(send), Mutex::new(receive));
+            move |cities| {
+                send.lock().unwrap().send(()).unwrap();
+                let _ = receive.lock().unwrap().recv_timeout(Duration::from_secs(5));
+                seen.lock().unwrap().push(cities.iter().count());
+            }
+        }
+
+        let mut world = World::empty();
+        for gold in 0..8 {
+            let city = spawn_city(&mut world, gold);
+            if gold % 2 == 0 {
+                world.insert(city, Selected);
+            }
+        }
+
+        let (send_a, receive_a) = mpsc::channel();
+        let (send_b, receive_b) = mpsc::channel();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Update, reader(send_a, receive_b, seen.clone()))
+            .add_system(Stage::Update, reader(send_b, receive_a, seen.clone()));
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        pool.install(|| schedule.run(&mut world));
+        assert_eq!(*seen.lock().unwrap(), [4, 4]);
+    }
+
+    #[test]
+    fn filters_conflict_with_writers_only() {
+        let mut changed_gold = Access::new();
+        Changed::<Gold>::access(&mut changed_gold);
+        let mut reads_gold = Access::new();
+        reads_gold.add_read::<Gold>();
+        let mut writes_gold = Access::new();
+        writes_gold.add_write::<Gold>();
+
+        assert!(changed_gold.is_compatible(&reads_gold));
+        assert!(!changed_gold.is_compatible(&writes_gold));
+
+        // Within one query the filter doesn't alias the fetch.
+        writes_gold.add_filter_read::<Gold>();
+    }
+}
@@ -1,9 +1,14 @@
+use super::super::reflect::traits::Reflect;
+use super::components::Component;
 use crate::glm;
 
+#[derive(Reflect)]
 pub struct Color {
     pub color: glm::Vec3,
 }
 
+impl Component for Color {}
+
 impl Color {
     pub fn new(r: f32, g: f32, b: f32) -> Self {
         Self {
@@ -1,78 +1,480 @@
 use super::super::entity::EntityId;
-use crate::crowengine::components::use_shader::UseShader;
+use super::archetype::{Archetype, Column, TypedColumn};
+use super::change_detection::{ComponentTicks, Tick};
+use super::hooks::ComponentHooks;
+use super::sparse_set::{AnySparseSet, SparseSet};
 
-use std::any::{Any, TypeId};
-use std::collections::HashMap;
+use std::any::{type_name, Any, TypeId};
+use std::collections::{BTreeMap, HashMap};
 
-use std::iter;
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StorageType {
+    Table,
+    SparseSet,
+}
+
+// Components are stored in archetype tables unless they opt into a sparse set,
+// which suits markers that are added and removed often. They have to be
+// `Send + Sync` because systems may read them from several threads.
+pub trait Component: Any + Send + Sync {
+    const STORAGE: StorageType = StorageType::Table;
+}
+
+#[derive(Copy, Clone)]
+struct EntityLocation {
+    archetype: usize,
+    row: usize,
+}
+
+// Where a query reads a component type from within one archetype.
+pub enum ColumnRef<'a, T> {
+    Table(&'a TypedColumn<T>),
+    Sparse(&'a SparseSet<T>),
+    Missing,
+}
+
+impl<'a, T: Component> ColumnRef<'a, T> {
+    pub fn get(&self, entity: EntityId, row: usize) -> Option<&'a T> {
+        match self {
+            ColumnRef::Table(column) => Some(column.get(row)),
+            ColumnRef::Sparse(set) => set.get(entity),
+            ColumnRef::Missing => None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn contains(&self, entity: EntityId) -> bool {
+        match self {
+            ColumnRef::Table(_) => true,
+            ColumnRef::Sparse(set) => set.contains(entity),
+            ColumnRef::Missing => false,
+        }
+    }
 
-pub trait Component: Any {}
-impl<T: Any> Component for T {}
+    // Ticks are copied out rather than borrowed so a mutable fetch of the same
+    // component can update them while a filter is looking at them.
+    #[allow(dead_code)]
+    pub fn ticks(&self, entity: EntityId, row: usize) -> Option<ComponentTicks> {
+        let ticks = match self {
+            ColumnRef::Table(column) => column.get_ticks_ptr(row),
+            ColumnRef::Sparse(set) => set.get_ticks_ptr(entity)?,
+            ColumnRef::Missing => return None,
+        };
+        // Safety: every row's ticks have their own cell, and systems that write
+        // this component never run alongside the one reading them here.
+        Some(unsafe { ticks.read() })
+    }
 
+    // Marks the component as changed at `tick`.
+    // Safety: the caller must make sure no other reference to this component
+    // is alive for `'a`.
+    pub unsafe fn get_mut(&self, entity: EntityId, row: usize, tick: Tick) -> Option<&'a mut T> {
+        let (component, ticks) = match self {
+            ColumnRef::Table(column) => (column.get_ptr(row), column.get_ticks_ptr(row)),
+            ColumnRef::Sparse(set) => (set.get_ptr(entity)?, set.get_ticks_ptr(entity)?),
+            ColumnRef::Missing => return None,
+        };
+
+        unsafe {
+            (*ticks).changed = tick;
+            Some(&mut *component)
+        }
+    }
+}
+
+const EMPTY_ARCHETYPE: usize = 0;
+
+// Entities that never received a component have no location. Every other entity
+// lives in exactly one archetype, the empty one if it only has sparse components.
 pub struct ComponentStorage {
-    pub storages: HashMap<TypeId, Box<dyn Any>>,
+    archetypes: Vec<Archetype>,
+    archetype_ids: HashMap<Vec<TypeId>, usize>,
+    locations: Vec<Option<EntityLocation>>,
+    // Ordered so per-entity walks over the sets, like despawning, visit them in
+    // the same order in every run.
+    sparse_sets: BTreeMap<TypeId, Box<dyn AnySparseSet>>,
+    hooks: HashMap<TypeId, ComponentHooks>,
 }
 
 impl ComponentStorage {
     pub fn new() -> Self {
-        let mut storages: HashMap<TypeId, Box<dyn Any>> = HashMap::new();
-        storages.insert(
-            TypeId::of::<UseShader>(),
-            Box::new(HashMap::<EntityId, UseShader>::new()),
+        let mut archetype_ids = HashMap::new();
+        archetype_ids.insert(Vec::new(), EMPTY_ARCHETYPE);
+
+        ComponentStorage {
+            archetypes: vec![Archetype::new(Vec::new(), Vec::new())],
+            archetype_ids,
+            locations: Vec::new(),
+            sparse_sets: BTreeMap::new(),
+            hooks: HashMap::new(),
+        }
+    }
+
+    pub fn archetypes(&self) -> &[Archetype] {
+        &self.archetypes
+    }
+
+    pub fn column_ref<'a, T: Component>(&'a self, archetype: &'a Archetype) -> ColumnRef<'a, T> {
+        match T::STORAGE {
+            StorageType::Table => archetype
+                .column::<T>()
+                .map_or(ColumnRef::Missing, ColumnRef::Table),
+            StorageType::SparseSet => self
+                .sparse_set::<T>()
+                .map_or(ColumnRef::Missing, ColumnRef::Sparse),
+        }
+    }
+
+    pub fn insert<T: Component>(&mut self, entity: EntityId, component: T, tick: Tick) {
+        if T::STORAGE == StorageType::SparseSet {
+            if self.location(entity).is_none() {
+                let row = self.archetypes[EMPTY_ARCHETYPE].push_entity(entity);
+                self.set_location(
+                    entity,
+                    Some(EntityLocation {
+                        archetype: EMPTY_ARCHETYPE,
+                        row,
+                    }),
+                );
+            }
+
+            self.sparse_set_mut::<T>().insert(entity, component, tick);
+            return;
+        }
+
+        let location = self.location(entity);
+
+        if let Some(location) = location
+            && let Some(column) = self.archetypes[location.archetype].column_mut::<T>()
+        {
+            column.replace(location.row, component, tick);
+            return;
+        }
+
+        let source = location.map_or(EMPTY_ARCHETYPE, |location| location.archetype);
+        let target = self.add_target::<T>(source);
+        let row = match location {
+            Some(location) => self.move_entity(entity, location, target),
+            None => self.archetypes[target].push_entity(entity),
+        };
+
+        self.archetypes[target]
+            .column_mut::<T>()
+            .unwrap()
+            .push(component, ComponentTicks::new(tick));
+        self.set_location(
+            entity,
+            Some(EntityLocation {
+                archetype: target,
+                row,
+            }),
         );
-        ComponentStorage { storages }
     }
 
-    pub fn insert<T: Component>(&mut self, entity: EntityId, component: T) {
-        let type_id = TypeId::of::<T>();
+    pub fn remove<T: Component>(&mut self, entity: &EntityId) -> Option<T> {
+        if T::STORAGE == StorageType::SparseSet {
+            return self
+                .sparse_sets
+                .get_mut(&TypeId::of::<T>())
+                .and_then(|set| set.as_any_mut().downcast_mut::<SparseSet<T>>())
+                .and_then(|set| set.remove(*entity));
+        }
 
-        let storage = self
-            .storages
-            .entry(type_id)
-            .or_insert_with(|| Box::new(HashMap::<EntityId, T>::new()))
-            .downcast_mut::<HashMap<EntityId, T>>()
-            .unwrap();
+        let location = self.location(*entity)?;
+        let component = self.archetypes[location.archetype]
+            .column_mut::<T>()?
+            .swap_remove(location.row);
+
+        let target = self.remove_target(location.archetype, TypeId::of::<T>());
+        let row = self.move_entity(*entity, location, target);
+        self.set_location(
+            *entity,
+            Some(EntityLocation {
+                archetype: target,
+                row,
+            }),
+        );
+
+        Some(component)
+    }
+
+    // Returns the types of every component the entity had.
+    pub fn remove_entity(&mut self, entity: &EntityId) -> Vec<TypeId> {
+        let Some(location) = self.location(*entity) else {
+            return Vec::new();
+        };
+
+        let archetype = &mut self.archetypes[location.archetype];
+        let mut removed = archetype.types().to_vec();
+        if let Some(moved) = archetype.drop_row(location.row) {
+            self.set_row(moved, location.row);
+        }
+        for (type_id, set) in self.sparse_sets.iter_mut() {
+            if set.remove_entity(*entity) {
+                removed.push(*type_id);
+            }
+        }
+        self.set_location(*entity, None);
+
+        removed
+    }
+
+    // The types of every component the entity has, table components first.
+    pub fn component_types(&self, entity: &EntityId) -> Vec<TypeId> {
+        let Some(location) = self.location(*entity) else {
+            return Vec::new();
+        };
+
+        let mut types = self.archetypes[location.archetype].types().to_vec();
+        for (type_id, set) in self.sparse_sets.iter() {
+            if set.contains(*entity) {
+                types.push(*type_id);
+            }
+        }
+        types
+    }
+
+    pub fn hooks(&self, type_id: TypeId) -> Option<&ComponentHooks> {
+        self.hooks.get(&type_id)
+    }
+
+    pub fn hooks_mut<T: Component>(&mut self) -> &mut ComponentHooks {
+        self.hooks
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| ComponentHooks::new(type_name::<T>()))
+    }
 
-        storage.insert(entity, component);
+    pub fn contains<T: Component>(&self, entity: &EntityId) -> bool {
+        self.get::<T>(entity).is_some()
     }
 
     #[allow(dead_code)]
     pub fn get<T: Component>(&self, entity: &EntityId) -> Option<&T> {
-        let type_id = TypeId::of::<T>();
-        self.storages
-            .get(&type_id)
-            .and_then(|boxed| boxed.downcast_ref::<HashMap<EntityId, T>>())
-            .and_then(|map| map.get(entity))
+        let location = self.location(*entity)?;
+        self.column_ref::<T>(&self.archetypes[location.archetype])
+            .get(*entity, location.row)
     }
 
     #[allow(dead_code)]
-    pub fn get_mut<T: Component>(&mut self, entity: &EntityId) -> Option<&mut T> {
-        let type_id = TypeId::of::<T>();
-        self.storages
-            .get_mut(&type_id)
-            .and_then(|boxed| boxed.downcast_mut::<HashMap<EntityId, T>>())
-            .and_then(|map| map.get_mut(entity))
+    pub fn get_mut<T: Component>(&mut self, entity: &EntityId, tick: Tick) -> Option<&mut T> {
+        let location = self.location(*entity)?;
+        match T::STORAGE {
+            StorageType::Table => self.archetypes[location.archetype]
+                .column_mut::<T>()
+                .map(|column| column.get_mut(location.row, tick)),
+            StorageType::SparseSet => self
+                .sparse_sets
+                .get_mut(&TypeId::of::<T>())
+                .and_then(|set| set.as_any_mut().downcast_mut::<SparseSet<T>>())
+                .and_then(|set| set.get_mut(*entity, tick)),
+        }
     }
 
-    pub fn query_mut<T: Component>(&mut self) -> Box<dyn Iterator<Item = (EntityId, &mut T)> + '_> {
-        let type_id = TypeId::of::<T>();
+    fn sparse_set<T: Component>(&self) -> Option<&SparseSet<T>> {
+        self.sparse_sets
+            .get(&TypeId::of::<T>())
+            .and_then(|set| set.as_any().downcast_ref::<SparseSet<T>>())
+    }
 
-        match self.storages.get_mut(&type_id) {
-            Some(boxed_map) => {
-                if let Some(typed_map) = boxed_map.downcast_mut::<HashMap<EntityId, T>>() {
-                    Box::new(typed_map.iter_mut().map(|(id, comp)| (*id, comp)))
-                } else {
-                    Box::new(iter::empty())
-                }
-            }
-            None => Box::new(iter::empty()),
+    fn sparse_set_mut<T: Component>(&mut self) -> &mut SparseSet<T> {
+        self.sparse_sets
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(SparseSet::<T>::new()))
+            .as_any_mut()
+            .downcast_mut::<SparseSet<T>>()
+            .unwrap()
+    }
+
+    fn location(&self, entity: EntityId) -> Option<EntityLocation> {
+        self.locations.get(entity.index as usize).copied().flatten()
+    }
+
+    fn set_location(&mut self, entity: EntityId, location: Option<EntityLocation>) {
+        let index = entity.index as usize;
+        if index >= self.locations.len() {
+            self.locations.resize(index + 1, None);
+        }
+        self.locations[index] = location;
+    }
+
+    fn set_row(&mut self, entity: EntityId, row: usize) {
+        if let Some(location) = self.locations[entity.index as usize].as_mut() {
+            location.row = row;
         }
     }
 
-    pub fn get_all<T: Component>(&self) -> Option<&HashMap<EntityId, T>> {
+    // Moves the entity's row out of its current archetype and returns its row in `target`.
+    fn move_entity(&mut self, entity: EntityId, location: EntityLocation, target: usize) -> usize {
+        let (src, dst) = pair_mut(&mut self.archetypes, location.archetype, target);
+        let moved = src.move_row(location.row, dst);
+        let row = dst.push_entity(entity);
+
+        if let Some(moved) = moved {
+            self.set_row(moved, location.row);
+        }
+        row
+    }
+
+    fn add_target<T: Component>(&mut self, source: usize) -> usize {
         let type_id = TypeId::of::<T>();
-        self.storages
-            .get(&type_id)
-            .and_then(|boxed| boxed.downcast_ref::<HashMap<EntityId, T>>())
+
+        if let Some(target) = self.archetypes[source].add_edges.get(&type_id) {
+            return *target;
+        }
+
+        let archetype = &self.archetypes[source];
+        let mut columns: Vec<(TypeId, Box<dyn Column>)> = archetype
+            .types()
+            .iter()
+            .zip(archetype.columns())
+            .map(|(type_id, column)| (*type_id, column.new_empty()))
+            .collect();
+        columns.push((type_id, Box::new(TypedColumn::<T>::new())));
+
+        let target = self.find_or_create_archetype(columns);
+        self.archetypes[source].add_edges.insert(type_id, target);
+        target
+    }
+
+    fn remove_target(&mut self, source: usize, type_id: TypeId) -> usize {
+        if let Some(target) = self.archetypes[source].remove_edges.get(&type_id) {
+            return *target;
+        }
+
+        let archetype = &self.archetypes[source];
+        let columns: Vec<(TypeId, Box<dyn Column>)> = archetype
+            .types()
+            .iter()
+            .zip(archetype.columns())
+            .filter(|(other, _)| **other != type_id)
+            .map(|(type_id, column)| (*type_id, column.new_empty()))
+            .collect();
+
+        let target = self.find_or_create_archetype(columns);
+        self.archetypes[source].remove_edges.insert(type_id, target);
+        target
+    }
+
+    fn find_or_create_archetype(&mut self, mut columns: Vec<(TypeId, Box<dyn Column>)>) -> usize {
+        columns.sort_by_key(|(type_id, _)| *type_id);
+        let types: Vec<TypeId> = columns.iter().map(|(type_id, _)| *type_id).collect();
+
+        if let Some(id) = self.archetype_ids.get(&types) {
+            return *id;
+        }
+
+        let id = self.archetypes.len();
+        let columns = columns.into_iter().map(|(_, column)| column).collect();
+        self.archetypes.push(Archetype::new(types.clone(), columns));
+        self.archetype_ids.insert(types, id);
+        id
+    }
+}
+
+fn pair_mut(archetypes: &mut [Archetype], a: usize, b: usize) -> (&mut Archetype, &mut Archetype) {
+    if a < b {
+        let (left, right) = archetypes.split_at_mut(b);
+        (&mut left[a], &mut right[0])
+    } else {
+        let (left, right) = archetypes.split_at_mut(a);
+        (&mut right[0], &mut left[b])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[derive(Debug, PartialEq)]
+    struct Unit(u32);
+    impl Component for Unit {}
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+    impl Component for Health {}
+
+    struct Banner(Arc<AtomicUsize>);
+    impl Component for Banner {}
+
+    impl Drop for Banner {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn entity(index: u32) -> EntityId {
+        EntityId {
+            index,
+            generation: 0,
+        }
+    }
+
+    #[test]
+    fn components_move_along_with_their_entity() {
+        let mut storage = ComponentStorage::new();
+        for i in 0..3 {
+            storage.insert(entity(i), Unit(i), Tick(0));
+            storage.insert(entity(i), Health(i * 10), Tick(0));
+        }
+
+        // Moving the first entity out swaps the last one into its row.
+        assert_eq!(storage.remove::<Health>(&entity(0)), Some(Health(0)));
+        assert_eq!(storage.get::<Unit>(&entity(0)), Some(&Unit(0)));
+        assert_eq!(storage.get::<Health>(&entity(0)), None);
+        for i in 1..3 {
+            assert_eq!(storage.get::<Unit>(&entity(i)), Some(&Unit(i)));
+            assert_eq!(storage.get::<Health>(&entity(i)), Some(&Health(i * 10)));
+        }
+
+        storage.insert(entity(0), Health(5), Tick(0));
+        assert_eq!(storage.get::<Health>(&entity(0)), Some(&Health(5)));
+        assert_eq!(storage.remove::<Health>(&entity(0)), Some(Health(5)));
+        assert_eq!(storage.remove::<Health>(&entity(0)), None);
+    }
+
+    #[test]
+    fn archetypes_are_shared_and_reused() {
+        let mut storage = ComponentStorage::new();
+        storage.insert(entity(0), Unit(0), Tick(0));
+        storage.insert(entity(0), Health(0), Tick(0));
+        storage.insert(entity(1), Health(1), Tick(0));
+        storage.insert(entity(1), Unit(1), Tick(0));
+        let count = storage.archetypes().len();
+
+        storage.remove::<Health>(&entity(1));
+        storage.insert(entity(1), Health(1), Tick(0));
+        assert_eq!(storage.archetypes().len(), count);
+
+        let archetype = storage
+            .archetypes()
+            .iter()
+            .find(|archetype| archetype.has::<Unit>() && archetype.has::<Health>())
+            .unwrap();
+        assert_eq!(archetype.len(), 2);
+    }
+
+    #[test]
+    fn removed_entities_drop_their_components() {
+        let drops = Arc::new(AtomicUsize::new(0));
+        let mut storage = ComponentStorage::new();
+        for i in 0..2 {
+            storage.insert(entity(i), Unit(i), Tick(0));
+            storage.insert(entity(i), Banner(drops.clone()), Tick(0));
+        }
+
+        let mut removed = storage.remove_entity(&entity(0));
+        removed.sort();
+        let mut expected = vec![TypeId::of::<Unit>(), TypeId::of::<Banner>()];
+        expected.sort();
+        assert_eq!(removed, expected);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+        assert_eq!(storage.get::<Unit>(&entity(0)), None);
+        assert_eq!(storage.get::<Unit>(&entity(1)), Some(&Unit(1)));
+
+        // Replacing a component drops the old value.
+        storage.insert(entity(1), Banner(drops.clone()), Tick(0));
+        assert_eq!(drops.load(Ordering::Relaxed), 2);
     }
 }
@@ -0,0 +1,227 @@
+use super::super::entity::EntityId;
+use super::archetype::Archetype;
+use super::change_detection::Ticks;
+use super::components::{ColumnRef, Component, ComponentStorage, StorageType};
+use super::query::Access;
+
+use std::marker::PhantomData;
+
+// Narrows down which entities a query yields without fetching any data.
+pub trait QueryFilter {
+    type Column<'a>: 'a;
+
+    fn access(access: &mut Access);
+
+    fn matches_archetype(archetype: &Archetype) -> bool;
+
+    fn column<'a>(
+        storage: &'a ComponentStorage,
+        archetype: &'a Archetype,
+        ticks: Ticks,
+    ) -> Self::Column<'a>;
+
+    fn filter(column: &Self::Column<'_>, entity: EntityId, row: usize) -> bool;
+}
+
+#[allow(dead_code)]
+pub struct With<T>(PhantomData<T>);
+
+#[allow(dead_code)]
+pub struct Without<T>(PhantomData<T>);
+
+// Matches entities accepted by any of the filters in the tuple.
+#[allow(dead_code)]
+pub struct Or<T>(PhantomData<T>);
+
+impl<T: Component> QueryFilter for With<T> {
+    type Column<'a> = ColumnRef<'a, T>;
+
+    fn access(_access: &mut Access) {}
+
+    fn matches_archetype(archetype: &Archetype) -> bool {
+        T::STORAGE == StorageType::SparseSet || archetype.has::<T>()
+    }
+
+    fn column<'a>(
+        storage: &'a ComponentStorage,
+        archetype: &'a Archetype,
+        _ticks: Ticks,
+    ) -> Self::Column<'a> {
+        storage.column_ref::<T>(archetype)
+    }
+
+    fn filter(column: &Self::Column<'_>, entity: EntityId, _row: usize) -> bool {
+        column.contains(entity)
+    }
+}
+
+impl<T: Component> QueryFilter for Without<T> {
+    type Column<'a> = ColumnRef<'a, T>;
+
+    fn access(_access: &mut Access) {}
+
+    fn matches_archetype(archetype: &Archetype) -> bool {
+        T::STORAGE == StorageType::SparseSet || !archetype.has::<T>()
+    }
+
+    fn column<'a>(
+        storage: &'a ComponentStorage,
+        archetype: &'a Archetype,
+        _ticks: Ticks,
+    ) -> Self::Column<'a> {
+        storage.column_ref::<T>(archetype)
+    }
+
+    fn filter(column: &Self::Column<'_>, entity: EntityId, _row: usize) -> bool {
+        !column.contains(entity)
+    }
+}
+
+impl QueryFilter for () {
+    type Column<'a> = ();
+
+    fn access(_access: &mut Access) {}
+
+    fn matches_archetype(_archetype: &Archetype) -> bool {
+        true
+    }
+
+    fn column<'a>(
+        _storage: &'a ComponentStorage,
+        _archetype: &'a Archetype,
+        _ticks: Ticks,
+    ) -> Self::Column<'a> {
+    }
+
+    fn filter(_column: &Self::Column<'_>, _entity: EntityId, _row: usize) -> bool {
+        true
+    }
+}
+
+macro_rules! impl_query_filter {
+    ($($name:ident),+) => {
+        impl<$($name: QueryFilter),+> QueryFilter for ($($name,)+) {
+            type Column<'a> = ($($name::Column<'a>,)+);
+
+            fn access(access: &mut Access) {
+                $($name::access(access);)+
+            }
+
+            fn matches_archetype(archetype: &Archetype) -> bool {
+                $($name::matches_archetype(archetype))&&+
+            }
+
+            fn column<'a>(storage: &'a ComponentStorage, archetype: &'a Archetype, ticks: Ticks) -> Self::Column<'a> {
+                ($($name::column(storage, archetype, ticks),)+)
+            }
+
+            #[allow(non_snake_case)]
+            fn filter(column: &Self::Column<'_>, entity: EntityId, row: usize) -> bool {
+                let ($($name,)+) = column;
+                $($name::filter($name, entity, row))&&+
+            }
+        }
+
+        // Each branch remembers whether it matched the archetype at all, since
+        // an archetype only has to satisfy one of them.
+        impl<$($name: QueryFilter),+> QueryFilter for Or<($($name,)+)> {
+            type Column<'a> = ($((bool, $name::Column<'a>),)+);
+
+            fn access(access: &mut Access) {
+                $($name::access(access);)+
+            }
+
+            fn matches_archetype(archetype: &Archetype) -> bool {
+                $($name::matches_archetype(archetype))||+
+            }
+
+            fn column<'a>(storage: &'a ComponentStorage, archetype: &'a Archetype, ticks: Ticks) -> Self::Column<'a> {
+                ($(($name::matches_archetype(archetype), $name::column(storage, archetype, ticks)),)+)
+            }
+
+            #[allow(non_snake_case)]
+            fn filter(column: &Self::Column<'_>, entity: EntityId, row: usize) -> bool {
+                let ($($name,)+) = column;
+                $(($name.0 && $name::filter(&$name.1, entity, row)))||+
+            }
+        }
+    };
+}
+
+all_tuples!(impl_query_filter; F0, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12, F13, F14, F15);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::world::World;
+
+    struct Unit;
+    impl Component for Unit {}
+
+    struct Cavalry;
+    impl Component for Cavalry {}
+
+    struct Wounded;
+    impl Component for Wounded {}
+
+    struct Selected;
+    impl Component for Selected {
+        const STORAGE: StorageType = StorageType::SparseSet;
+    }
+
+    fn unit<T: Component>(world: &mut World, component: T) -> EntityId {
+        let entity = world.spawn_empty();
+        world.insert(entity, Unit);
+        world.insert(entity, component);
+        entity
+    }
+
+    fn matching<F: QueryFilter>(world: &World) -> Vec<EntityId> {
+        let mut entities: Vec<EntityId> = world
+            .query_filtered::<(EntityId,), F>()
+            .map(|(entity,)| entity)
+            .collect();
+        entities.sort_by_key(|entity| entity.index);
+        entities
+    }
+
+    #[test]
+    fn filters_match_table_and_sparse_components() {
+        let mut world = World::empty();
+        let cavalry = unit(&mut world, Cavalry);
+        let selected = unit(&mut world, Selected);
+        let wounded = unit(&mut world, Wounded);
+
+        assert_eq!(matching::<With<Cavalry>>(&world), [cavalry]);
+        assert_eq!(matching::<Without<Cavalry>>(&world), [selected, wounded]);
+        assert_eq!(matching::<With<Selected>>(&world), [selected]);
+        assert_eq!(matching::<Without<Selected>>(&world), [cavalry, wounded]);
+        assert_eq!(
+            matching::<Or<(With<Cavalry>, With<Selected>)>>(&world),
+            [cavalry, selected]
+        );
+        assert_eq!(
+            matching::<(With<Unit>, Without<Wounded>, Without<Selected>)>(&world),
+            [cavalry]
+        );
+    }
+
+    #[test]
+    fn filtered_queries_only_write_matching_entities() {
+        let mut world = World::empty();
+        let idle = world.spawn_empty();
+        world.insert(idle, Unit);
+        let chosen = unit(&mut world, Selected);
+
+        let written: Vec<EntityId> = world
+            .query_filtered_mut::<(EntityId, &mut Unit), With<Selected>>()
+            .map(|(entity, _)| entity)
+            .collect();
+        assert_eq!(written, [chosen]);
+        let written: Vec<EntityId> = world
+            .query_filtered_mut::<(EntityId, &mut Unit), Without<Selected>>()
+            .map(|(entity, _)| entity)
+            .collect();
+        assert_eq!(written, [idle]);
+    }
+}
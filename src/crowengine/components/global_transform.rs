@@ -0,0 +1,26 @@
+use super::components::Component;
+use nalgebra_glm as glm;
+
+// The world-space matrix of an entity, i.e. its `Transform` combined with the
+// transforms of all of its ancestors. Written by `propagate_transforms`, so it
+// should be read instead of modified.
+pub struct GlobalTransform {
+    pub matrix: glm::Mat4,
+}
+
+impl Component for GlobalTransform {}
+
+#[allow(dead_code)]
+impl GlobalTransform {
+    pub fn from_matrix(matrix: glm::Mat4) -> Self {
+        Self { matrix }
+    }
+
+    pub fn position(&self) -> glm::Vec3 {
+        self.matrix.column(3).xyz()
+    }
+
+    pub fn view(&self) -> glm::Mat4 {
+        self.matrix.try_inverse().unwrap_or(glm::identity())
+    }
+}
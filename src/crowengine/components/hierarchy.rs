@@ -0,0 +1,36 @@
+use super::super::entity::EntityId;
+use super::components::Component;
+
+// The entity this one is attached to. Kept in sync with the parent's
+// `Children` by `set_parent`, `remove_parent` and despawning, so don't insert
+// it directly. Neither type is registered for reflection, so scenes and
+// prefabs attach entities through `set_parent` as well.
+pub struct Parent(pub(crate) EntityId);
+
+impl Component for Parent {}
+
+#[allow(dead_code)]
+impl Parent {
+    pub fn get(&self) -> EntityId {
+        self.0
+    }
+}
+
+pub struct Children(pub(crate) Vec<EntityId>);
+
+impl Component for Children {}
+
+#[allow(dead_code)]
+impl Children {
+    pub fn iter(&self) -> impl Iterator<Item = EntityId> + '_ {
+        self.0.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
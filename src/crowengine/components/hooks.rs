@@ -0,0 +1,133 @@
+use super::super::entity::EntityId;
+use super::super::world::World;
+
+pub type ComponentHook = fn(&mut World, EntityId);
+
+// Functions run whenever a component of one type is added to, inserted into or
+// removed from any entity. `on_add` only runs when the entity didn't have the
+// component yet, `on_insert` runs after every insert and `on_remove` runs while
+// the component is still there, so it can be read one last time.
+pub struct ComponentHooks {
+    component: &'static str,
+    on_add: Option<ComponentHook>,
+    on_insert: Option<ComponentHook>,
+    on_remove: Option<ComponentHook>,
+}
+
+#[allow(dead_code)]
+impl ComponentHooks {
+    pub fn new(component: &'static str) -> Self {
+        Self {
+            component,
+            on_add: None,
+            on_insert: None,
+            on_remove: None,
+        }
+    }
+
+    pub fn on_add(&mut self, hook: ComponentHook) -> &mut Self {
+        set_hook(&mut self.on_add, hook, self.component, "on_add");
+        self
+    }
+
+    pub fn on_insert(&mut self, hook: ComponentHook) -> &mut Self {
+        set_hook(&mut self.on_insert, hook, self.component, "on_insert");
+        self
+    }
+
+    pub fn on_remove(&mut self, hook: ComponentHook) -> &mut Self {
+        set_hook(&mut self.on_remove, hook, self.component, "on_remove");
+        self
+    }
+
+    pub fn get(&self, trigger: Trigger) -> Option<ComponentHook> {
+        match trigger {
+            Trigger::Add => self.on_add,
+            Trigger::Insert => self.on_insert,
+            Trigger::Remove => self.on_remove,
+        }
+    }
+}
+
+// A component type only gets one hook of each kind, so two pieces of code
+// can't silently overwrite each other. Use observers for anything else.
+fn set_hook(slot: &mut Option<ComponentHook>, hook: ComponentHook, component: &str, kind: &str) {
+    if slot.is_some() {
+        panic!("Component {} already has an {} hook!", component, kind);
+    }
+    *slot = Some(hook);
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Trigger {
+    Add,
+    Insert,
+    Remove,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::components::components::Component;
+    use crate::crowengine::resources::Resource;
+
+    #[derive(Debug, PartialEq)]
+    struct City(u32);
+    impl Component for City {}
+
+    #[derive(Debug, PartialEq)]
+    struct Ruins(u32);
+    impl Component for Ruins {}
+
+    #[derive(Default)]
+    struct Log(Vec<&'static str>);
+    impl Resource for Log {}
+
+    fn log(world: &mut World, message: &'static str) {
+        world.resource_mut::<Log>().0.push(message);
+    }
+
+    fn world() -> World {
+        let mut world = World::empty();
+        world.insert_resource(Log::default());
+        world
+            .register_component_hooks::<City>()
+            .on_add(|world, _| log(world, "add"))
+            .on_insert(|world, _| log(world, "insert"))
+            .on_remove(|world, entity| {
+                let size = world.get::<City>(&entity).unwrap().0;
+                world.insert(entity, Ruins(size));
+                log(world, "remove");
+            });
+        world
+    }
+
+    #[test]
+    fn hooks_run_on_add_insert_and_remove() {
+        let mut world = world();
+        let city = world.spawn(City(7));
+        world.insert(city, City(8));
+        assert_eq!(world.resource::<Log>().0, ["add", "insert", "insert"]);
+
+        assert_eq!(world.remove::<City>(city), Some(City(8)));
+        assert_eq!(world.get::<Ruins>(&city), Some(&Ruins(8)));
+        assert_eq!(world.resource::<Log>().0.last(), Some(&"remove"));
+    }
+
+    #[test]
+    fn despawning_runs_remove_hooks() {
+        let mut world = world();
+        let city = world.spawn(City(3));
+        world.despawn(city);
+        assert_eq!(world.resource::<Log>().0.last(), Some(&"remove"));
+        assert!(!world.contains(city));
+        assert_eq!(world.query::<(&Ruins,)>().count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "already has an on_add hook")]
+    fn hooks_cannot_be_replaced() {
+        let mut world = world();
+        world.register_component_hooks::<City>().on_add(|_, _| {});
+    }
+}
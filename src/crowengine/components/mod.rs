@@ -1,7 +1,14 @@
+pub mod archetype;
 pub mod camera;
+pub mod change_detection;
 pub mod color;
 pub mod components;
+pub mod filter;
+pub mod global_transform;
+pub mod hierarchy;
+pub mod hooks;
 pub mod query;
+pub mod sparse_set;
 pub mod texture;
 pub mod transform;
 pub mod use_shader;
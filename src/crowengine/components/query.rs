@@ -1,188 +1,432 @@
 use super::super::entity::EntityId;
-use super::components::Component;
-use super::components::ComponentStorage;
-use std::collections::HashMap;
-
-pub trait FetchComponent<'a> {
-    type Component: Component;
-    type RefType;
-
-    fn fetch_from_component_storage(
-        map: &'a HashMap<EntityId, Self::Component>,
-        entity: &EntityId,
-    ) -> Option<Self::RefType>;
+use super::archetype::Archetype;
+use super::change_detection::{Tick, Ticks};
+use super::components::{ColumnRef, Component, ComponentStorage, StorageType};
+use super::filter::QueryFilter;
+
+use std::any::{type_name, TypeId};
+use std::marker::PhantomData;
+
+#[allow(dead_code)]
+struct ResourceAccess<R>(PhantomData<R>);
+
+// The component and resource types a query (or every parameter of a system)
+// reads and writes. Registering an access that aliases an earlier one panics,
+// which is what keeps `&mut T` fetches sound.
+pub struct Access {
+    reads: Vec<(TypeId, &'static str)>,
+    writes: Vec<(TypeId, &'static str)>,
+    // Types whose change ticks are read by `Added`/`Changed` filters. They never
+    // alias within a query but do conflict with writers in other systems.
+    filter_reads: Vec<TypeId>,
 }
 
-impl<'a, T: Component> FetchComponent<'a> for &'a T {
-    type Component = T;
-    type RefType = &'a T;
+impl Access {
+    pub fn new() -> Self {
+        Self {
+            reads: Vec::new(),
+            writes: Vec::new(),
+            filter_reads: Vec::new(),
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn add_filter_read<T: 'static>(&mut self) {
+        self.filter_reads.push(TypeId::of::<T>());
+    }
+
+    // Whether two systems with these accesses can run at the same time.
+    pub fn is_compatible(&self, other: &Access) -> bool {
+        fn writes_into(writer: &Access, reader: &Access) -> bool {
+            writer.writes.iter().any(|(type_id, _)| {
+                reader
+                    .reads
+                    .iter()
+                    .chain(reader.writes.iter())
+                    .any(|(other, _)| other == type_id)
+                    || reader.filter_reads.contains(type_id)
+            })
+        }
+
+        !writes_into(self, other) && !writes_into(other, self)
+    }
+
+    pub fn add_read<T: 'static>(&mut self) {
+        self.read(TypeId::of::<T>(), type_name::<T>());
+    }
+
+    pub fn add_write<T: 'static>(&mut self) {
+        self.write(TypeId::of::<T>(), type_name::<T>());
+    }
+
+    // Resources are keyed apart from components so a type can be both.
+    #[allow(dead_code)]
+    pub fn add_resource_read<R: 'static>(&mut self) {
+        self.read(TypeId::of::<ResourceAccess<R>>(), type_name::<R>());
+    }
+
+    #[allow(dead_code)]
+    pub fn add_resource_write<R: 'static>(&mut self) {
+        self.write(TypeId::of::<ResourceAccess<R>>(), type_name::<R>());
+    }
 
-    fn fetch_from_component_storage(
-        map: &'a HashMap<EntityId, T>,
-        entity: &EntityId,
-    ) -> Option<Self::RefType> {
-        map.get(entity)
+    fn read(&mut self, type_id: TypeId, name: &'static str) {
+        if self.writes.iter().any(|(other, _)| *other == type_id) {
+            panic!(
+                "Query borrows {} while it is already borrowed mutably!",
+                name
+            );
+        }
+        self.reads.push((type_id, name));
+    }
+
+    fn write(&mut self, type_id: TypeId, name: &'static str) {
+        if self
+            .reads
+            .iter()
+            .chain(self.writes.iter())
+            .any(|(other, _)| *other == type_id)
+        {
+            panic!(
+                "Query borrows {} mutably while it is already borrowed!",
+                name
+            );
+        }
+        self.writes.push((type_id, name));
     }
 }
 
-impl<'a, T: Component> FetchComponent<'a> for Option<&'a T> {
-    type Component = T;
-    type RefType = Option<&'a T>;
+pub trait FetchComponent {
+    type Item<'a>;
+    type Column<'a>: 'a;
+
+    fn access(access: &mut Access);
 
-    fn fetch_from_component_storage(
-        map: &'a HashMap<EntityId, T>,
-        entity: &EntityId,
-    ) -> Option<Self::RefType> {
-        Some(map.get(entity))
+    fn matches_archetype(archetype: &Archetype) -> bool;
+
+    fn column<'a>(
+        storage: &'a ComponentStorage,
+        archetype: &'a Archetype,
+        ticks: Ticks,
+    ) -> Self::Column<'a>;
+
+    // Safety: the caller must have validated the query's `Access` and hold the
+    // storage borrow that the access describes for `'a`.
+    unsafe fn fetch<'a>(
+        column: &Self::Column<'a>,
+        entity: EntityId,
+        row: usize,
+    ) -> Option<Self::Item<'a>>;
+}
+
+// Fetches that never hand out mutable references.
+pub trait ReadOnlyFetch {}
+
+impl<T: Component> FetchComponent for &T {
+    type Item<'a> = &'a T;
+    type Column<'a> = ColumnRef<'a, T>;
+
+    fn access(access: &mut Access) {
+        access.add_read::<T>();
+    }
+
+    // Sparse-set components are not part of the archetype, so they are
+    // checked per entity in `fetch` instead.
+    fn matches_archetype(archetype: &Archetype) -> bool {
+        T::STORAGE == StorageType::SparseSet || archetype.has::<T>()
+    }
+
+    fn column<'a>(
+        storage: &'a ComponentStorage,
+        archetype: &'a Archetype,
+        _ticks: Ticks,
+    ) -> Self::Column<'a> {
+        storage.column_ref::<T>(archetype)
+    }
+
+    unsafe fn fetch<'a>(
+        column: &Self::Column<'a>,
+        entity: EntityId,
+        row: usize,
+    ) -> Option<Self::Item<'a>> {
+        column.get(entity, row)
     }
 }
 
-pub trait Query<'a> {
-    type Item;
+impl<T: Component> ReadOnlyFetch for &T {}
 
-    #[allow(dead_code)]
-    fn fetch(storage: &'a ComponentStorage) -> Box<dyn Iterator<Item = Self::Item> + 'a>;
+impl<T: Component> FetchComponent for Option<&T> {
+    type Item<'a> = Option<&'a T>;
+    type Column<'a> = ColumnRef<'a, T>;
+
+    fn access(access: &mut Access) {
+        access.add_read::<T>();
+    }
+
+    fn matches_archetype(_archetype: &Archetype) -> bool {
+        true
+    }
+
+    fn column<'a>(
+        storage: &'a ComponentStorage,
+        archetype: &'a Archetype,
+        _ticks: Ticks,
+    ) -> Self::Column<'a> {
+        storage.column_ref::<T>(archetype)
+    }
+
+    unsafe fn fetch<'a>(
+        column: &Self::Column<'a>,
+        entity: EntityId,
+        row: usize,
+    ) -> Option<Self::Item<'a>> {
+        Some(column.get(entity, row))
+    }
 }
 
-impl<'a, A> Query<'a> for (A,)
-where
-    A: FetchComponent<'a> + 'a,
-{
-    type Item = (A::RefType,);
+impl<T: Component> ReadOnlyFetch for Option<&T> {}
 
-    fn fetch(storage: &'a ComponentStorage) -> Box<dyn Iterator<Item = Self::Item> + 'a> {
-        let map = storage.get_all::<A::Component>();
+impl<T: Component> FetchComponent for &mut T {
+    type Item<'a> = &'a mut T;
+    type Column<'a> = (ColumnRef<'a, T>, Tick);
 
-        match map {
-            Some(map) => {
-                let iter = map
-                    .iter()
-                    .filter_map(move |(id, _)| Some((A::fetch_from_component_storage(map, id)?,)));
-                Box::new(iter)
-            }
-            None => Box::new(std::iter::empty()),
-        }
+    fn access(access: &mut Access) {
+        access.add_write::<T>();
+    }
+
+    fn matches_archetype(archetype: &Archetype) -> bool {
+        T::STORAGE == StorageType::SparseSet || archetype.has::<T>()
+    }
+
+    fn column<'a>(
+        storage: &'a ComponentStorage,
+        archetype: &'a Archetype,
+        ticks: Ticks,
+    ) -> Self::Column<'a> {
+        (storage.column_ref::<T>(archetype), ticks.this_run)
+    }
+
+    unsafe fn fetch<'a>(
+        (column, tick): &Self::Column<'a>,
+        entity: EntityId,
+        row: usize,
+    ) -> Option<Self::Item<'a>> {
+        // Safety: `add_write` rejected every other access to `T` in this query
+        // and each row is yielded only once.
+        unsafe { column.get_mut(entity, row, *tick) }
     }
 }
 
-impl<'a, A, B> Query<'a> for (A, B)
-where
-    A: FetchComponent<'a> + 'a,
-    B: FetchComponent<'a> + 'a,
-{
-    type Item = (A::RefType, B::RefType);
-
-    fn fetch(storage: &'a ComponentStorage) -> Box<dyn Iterator<Item = Self::Item> + 'a> {
-        let a_map = storage.get_all::<A::Component>();
-        let b_map = storage.get_all::<B::Component>();
-
-        match (a_map, b_map) {
-            (Some(a), Some(b)) => {
-                let iter = a.iter().filter_map(move |(id, _)| {
-                    Some((
-                        A::fetch_from_component_storage(a, id)?,
-                        B::fetch_from_component_storage(b, id)?,
-                    ))
-                });
-                Box::new(iter)
-            }
-            _ => Box::new(std::iter::empty()),
-        }
+impl<T: Component> FetchComponent for Option<&mut T> {
+    type Item<'a> = Option<&'a mut T>;
+    type Column<'a> = (ColumnRef<'a, T>, Tick);
+
+    fn access(access: &mut Access) {
+        access.add_write::<T>();
+    }
+
+    fn matches_archetype(_archetype: &Archetype) -> bool {
+        true
+    }
+
+    fn column<'a>(
+        storage: &'a ComponentStorage,
+        archetype: &'a Archetype,
+        ticks: Ticks,
+    ) -> Self::Column<'a> {
+        (storage.column_ref::<T>(archetype), ticks.this_run)
+    }
+
+    unsafe fn fetch<'a>(
+        (column, tick): &Self::Column<'a>,
+        entity: EntityId,
+        row: usize,
+    ) -> Option<Self::Item<'a>> {
+        // Safety: see `&mut T`.
+        Some(unsafe { column.get_mut(entity, row, *tick) })
     }
 }
 
-impl<'a, A, B, C> Query<'a> for (A, B, C)
-where
-    A: FetchComponent<'a> + 'a,
-    B: FetchComponent<'a> + 'a,
-    C: FetchComponent<'a> + 'a,
-{
-    type Item = (A::RefType, B::RefType, C::RefType);
-
-    fn fetch(storage: &'a ComponentStorage) -> Box<dyn Iterator<Item = Self::Item> + 'a> {
-        let a_map = storage.get_all::<A::Component>();
-        let b_map = storage.get_all::<B::Component>();
-        let c_map = storage.get_all::<C::Component>();
-
-        match (a_map, b_map, c_map) {
-            (Some(a), Some(b), Some(c)) => {
-                let iter = a.iter().filter_map(move |(id, _)| {
-                    Some((
-                        A::fetch_from_component_storage(a, id)?,
-                        B::fetch_from_component_storage(b, id)?,
-                        C::fetch_from_component_storage(c, id)?,
-                    ))
-                });
-                Box::new(iter)
-            }
-            _ => Box::new(std::iter::empty()),
-        }
+impl FetchComponent for EntityId {
+    type Item<'a> = EntityId;
+    type Column<'a> = ();
+
+    fn access(_access: &mut Access) {}
+
+    fn matches_archetype(_archetype: &Archetype) -> bool {
+        true
+    }
+
+    fn column<'a>(
+        _storage: &'a ComponentStorage,
+        _archetype: &'a Archetype,
+        _ticks: Ticks,
+    ) -> Self::Column<'a> {
     }
+
+    unsafe fn fetch<'a>(
+        _column: &Self::Column<'a>,
+        entity: EntityId,
+        _row: usize,
+    ) -> Option<Self::Item<'a>> {
+        Some(entity)
+    }
+}
+
+impl ReadOnlyFetch for EntityId {}
+
+// A tuple of fetches. The lifetime of the yielded items comes from the storage
+// borrow, so `(&'static Transform,)` works as a query type just as well.
+//
+// Items come out archetype by archetype in the order the archetypes were
+// created, and in row order within each archetype. Both only depend on the
+// sequence of spawns, inserts, removes and despawns, so two worlds built the
+// same way always iterate the same way. The order does shift as entities move
+// between archetypes though, so sort the results (see
+// `Query::iter_sorted_by_key`) when the order itself matters.
+pub trait WorldQuery {
+    type Item<'a>
+    where
+        Self: 'a;
+
+    fn access(access: &mut Access);
+
+    // Safety: the caller must have validated `access` and hold a borrow of the
+    // storage that matches it (shared for read-only queries, exclusive otherwise).
+    unsafe fn fetch<'a, F: QueryFilter + 'a>(
+        storage: &'a ComponentStorage,
+        ticks: Ticks,
+    ) -> Box<dyn Iterator<Item = Self::Item<'a>> + 'a>
+    where
+        Self: 'a;
 }
 
-impl<'a, A, B, C, D> Query<'a> for (A, B, C, D)
-where
-    A: FetchComponent<'a> + 'a,
-    B: FetchComponent<'a> + 'a,
-    C: FetchComponent<'a> + 'a,
-    D: FetchComponent<'a> + 'a,
-{
-    type Item = (A::RefType, B::RefType, C::RefType, D::RefType);
-
-    fn fetch(storage: &'a ComponentStorage) -> Box<dyn Iterator<Item = Self::Item> + 'a> {
-        let a_map = storage.get_all::<A::Component>();
-        let b_map = storage.get_all::<B::Component>();
-        let c_map = storage.get_all::<C::Component>();
-        let d_map = storage.get_all::<D::Component>();
-
-        match (a_map, b_map, c_map, d_map) {
-            (Some(a), Some(b), Some(c), Some(d)) => {
-                let iter = a.iter().filter_map(move |(id, _)| {
-                    Some((
-                        A::fetch_from_component_storage(a, id)?,
-                        B::fetch_from_component_storage(b, id)?,
-                        C::fetch_from_component_storage(c, id)?,
-                        D::fetch_from_component_storage(d, id)?,
-                    ))
-                });
+pub trait ReadOnlyWorldQuery: WorldQuery {}
+
+// Only archetypes containing every required table component and accepted by
+// the filter are visited, and rows are read straight out of the archetype's columns.
+macro_rules! impl_world_query {
+    ($($name:ident),+) => {
+        impl<$($name: FetchComponent),+> WorldQuery for ($($name,)+) {
+            type Item<'a> = ($($name::Item<'a>,)+) where Self: 'a;
+
+            fn access(access: &mut Access) {
+                $($name::access(access);)+
+            }
+
+            #[allow(non_snake_case)]
+            unsafe fn fetch<'a, F: QueryFilter + 'a>(
+                storage: &'a ComponentStorage,
+                ticks: Ticks,
+            ) -> Box<dyn Iterator<Item = Self::Item<'a>> + 'a>
+            where
+                Self: 'a,
+            {
+                let iter = storage
+                    .archetypes()
+                    .iter()
+                    .filter(|archetype| {
+                        F::matches_archetype(archetype) $(&& $name::matches_archetype(archetype))+
+                    })
+                    .flat_map(move |archetype| {
+                        let filter = F::column(storage, archetype, ticks);
+                        let ($($name,)+) = ($($name::column(storage, archetype, ticks),)+);
+                        archetype
+                            .entities()
+                            .iter()
+                            .enumerate()
+                            .filter(move |(row, entity)| F::filter(&filter, **entity, *row))
+                            .filter_map(move |(row, entity)| unsafe {
+                                Some(($($name::fetch(&$name, *entity, row)?,)+))
+                            })
+                    });
                 Box::new(iter)
             }
-            _ => Box::new(std::iter::empty()),
         }
-    }
+
+        impl<$($name: FetchComponent + ReadOnlyFetch),+> ReadOnlyWorldQuery for ($($name,)+) {}
+    };
 }
 
-impl<'a, A, B, C, D, E> Query<'a> for (A, B, C, D, E)
-where
-    A: FetchComponent<'a> + 'a,
-    B: FetchComponent<'a> + 'a,
-    C: FetchComponent<'a> + 'a,
-    D: FetchComponent<'a> + 'a,
-    E: FetchComponent<'a> + 'a,
-{
-    type Item = (A::RefType, B::RefType, C::RefType, D::RefType, E::RefType);
-
-    fn fetch(storage: &'a ComponentStorage) -> Box<dyn Iterator<Item = Self::Item> + 'a> {
-        let a_map = storage.get_all::<A::Component>();
-        let b_map = storage.get_all::<B::Component>();
-        let c_map = storage.get_all::<C::Component>();
-        let d_map = storage.get_all::<D::Component>();
-        let e_map = storage.get_all::<E::Component>();
-
-        match (a_map, b_map, c_map, d_map, e_map) {
-            (Some(a), Some(b), Some(c), Some(d), Some(e)) => {
-                let iter = a.iter().filter_map(move |(id, _)| {
-                    Some((
-                        A::fetch_from_component_storage(a, id)?,
-                        B::fetch_from_component_storage(b, id)?,
-                        C::fetch_from_component_storage(c, id)?,
-                        D::fetch_from_component_storage(d, id)?,
-                        E::fetch_from_component_storage(e, id)?,
-                    ))
-                });
-                Box::new(iter)
+all_tuples!(impl_world_query; Q0, Q1, Q2, Q3, Q4, Q5, Q6, Q7, Q8, Q9, Q10, Q11, Q12, Q13, Q14, Q15);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::world::World;
+
+    #[derive(Debug, PartialEq)]
+    struct Gold(u32);
+    impl Component for Gold {}
+
+    #[derive(Debug, PartialEq)]
+    struct Income(u32);
+    impl Component for Income {}
+
+    #[derive(Debug, PartialEq)]
+    struct Treasury(u32);
+    impl Component for Treasury {
+        const STORAGE: StorageType = StorageType::SparseSet;
+    }
+
+    #[test]
+    fn several_components_can_be_borrowed_mutably_at_once() {
+        let mut world = World::empty();
+        for i in 1..=3 {
+            let city = world.spawn_empty();
+            world.insert(city, Gold(i));
+            if i < 3 {
+                world.insert(city, Income(i * 10));
+            }
+            if i == 2 {
+                world.insert(city, Treasury(0));
             }
-            _ => Box::new(std::iter::empty()),
         }
+
+        for (gold, income, treasury) in
+            world.query_mut::<(&mut Gold, &Income, Option<&mut Treasury>)>()
+        {
+            gold.0 += income.0;
+            if let Some(treasury) = treasury {
+                treasury.0 = gold.0;
+            }
+        }
+
+        let mut gold: Vec<u32> = world.query::<(&Gold,)>().map(|(gold,)| gold.0).collect();
+        gold.sort();
+        assert_eq!(gold, [3, 11, 22]);
+        assert_eq!(
+            world.query::<(&Treasury,)>().next().unwrap().0,
+            &Treasury(22)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "while it is already borrowed mutably")]
+    fn reading_and_writing_the_same_component_panics() {
+        let mut world = World::empty();
+        let _ = world.query_mut::<(&mut Gold, &Gold)>();
+    }
+
+    #[test]
+    #[should_panic(expected = "mutably while it is already borrowed")]
+    fn writing_the_same_component_twice_panics() {
+        let mut world = World::empty();
+        let _ = world.query_mut::<(&mut Gold, Option<&mut Gold>)>();
+    }
+
+    #[test]
+    fn accesses_only_conflict_through_writes() {
+        let mut reads_gold = Access::new();
+        reads_gold.add_read::<Gold>();
+        let mut writes_gold = Access::new();
+        writes_gold.add_write::<Gold>();
+        let mut writes_income = Access::new();
+        writes_income.add_write::<Income>();
+
+        assert!(reads_gold.is_compatible(&reads_gold));
+        assert!(!reads_gold.is_compatible(&writes_gold));
+        assert!(!writes_gold.is_compatible(&reads_gold));
+        assert!(writes_gold.is_compatible(&writes_income));
     }
 }
@@ -0,0 +1,188 @@
+use super::super::entity::EntityId;
+use super::change_detection::{ComponentTicks, Tick};
+use super::components::Component;
+
+use std::any::Any;
+use std::cell::UnsafeCell;
+
+// Dense component storage with a sparse index by entity, so adding and removing
+// a component never moves the entity between archetypes.
+pub struct SparseSet<T> {
+    dense: Vec<UnsafeCell<T>>,
+    ticks: Vec<UnsafeCell<ComponentTicks>>,
+    entities: Vec<EntityId>,
+    sparse: Vec<Option<usize>>,
+}
+
+// Safety: see `TypedColumn`.
+unsafe impl<T: Component> Sync for SparseSet<T> {}
+
+impl<T: Component> SparseSet<T> {
+    pub fn new() -> Self {
+        Self {
+            dense: Vec::new(),
+            ticks: Vec::new(),
+            entities: Vec::new(),
+            sparse: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, entity: EntityId, component: T, tick: Tick) {
+        if let Some(index) = self.dense_index(entity) {
+            *self.dense[index].get_mut() = component;
+            self.ticks[index].get_mut().changed = tick;
+            return;
+        }
+
+        let slot = entity.index as usize;
+        if slot >= self.sparse.len() {
+            self.sparse.resize(slot + 1, None);
+        }
+        self.sparse[slot] = Some(self.entities.len());
+        self.dense.push(UnsafeCell::new(component));
+        self.ticks.push(UnsafeCell::new(ComponentTicks::new(tick)));
+        self.entities.push(entity);
+    }
+
+    pub fn remove(&mut self, entity: EntityId) -> Option<T> {
+        let index = self.dense_index(entity)?;
+        self.sparse[entity.index as usize] = None;
+        self.entities.swap_remove(index);
+        self.ticks.swap_remove(index);
+        let component = self.dense.swap_remove(index).into_inner();
+
+        if let Some(moved) = self.entities.get(index) {
+            self.sparse[moved.index as usize] = Some(index);
+        }
+        Some(component)
+    }
+
+    pub fn contains(&self, entity: EntityId) -> bool {
+        self.dense_index(entity).is_some()
+    }
+
+    pub fn get(&self, entity: EntityId) -> Option<&T> {
+        let index = self.dense_index(entity)?;
+        // Safety: see `TypedColumn::get`, the same query validation applies.
+        Some(unsafe { &*self.dense[index].get() })
+    }
+
+    pub fn get_mut(&mut self, entity: EntityId, tick: Tick) -> Option<&mut T> {
+        let index = self.dense_index(entity)?;
+        self.ticks[index].get_mut().changed = tick;
+        Some(self.dense[index].get_mut())
+    }
+
+    pub fn get_ptr(&self, entity: EntityId) -> Option<*mut T> {
+        let index = self.dense_index(entity)?;
+        Some(self.dense[index].get())
+    }
+
+    pub fn get_ticks_ptr(&self, entity: EntityId) -> Option<*mut ComponentTicks> {
+        let index = self.dense_index(entity)?;
+        Some(self.ticks[index].get())
+    }
+
+    fn dense_index(&self, entity: EntityId) -> Option<usize> {
+        let index = self.sparse.get(entity.index as usize).copied().flatten()?;
+        (self.entities[index] == entity).then_some(index)
+    }
+}
+
+pub trait AnySparseSet: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn contains(&self, entity: EntityId) -> bool;
+    fn remove_entity(&mut self, entity: EntityId) -> bool;
+}
+
+impl<T: Component> AnySparseSet for SparseSet<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn contains(&self, entity: EntityId) -> bool {
+        SparseSet::contains(self, entity)
+    }
+
+    fn remove_entity(&mut self, entity: EntityId) -> bool {
+        self.remove(entity).is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::components::components::StorageType;
+    use crate::crowengine::world::World;
+
+    #[derive(Debug, PartialEq)]
+    struct Unit(u32);
+    impl Component for Unit {}
+
+    #[derive(Debug, PartialEq)]
+    struct Selected(u32);
+    impl Component for Selected {
+        const STORAGE: StorageType = StorageType::SparseSet;
+    }
+
+    fn entity(index: u32, generation: u32) -> EntityId {
+        EntityId { index, generation }
+    }
+
+    #[test]
+    fn removing_keeps_the_other_entities_reachable() {
+        let mut set = SparseSet::new();
+        for i in 0..4 {
+            set.insert(entity(i, 0), Selected(i), Tick(0));
+        }
+
+        assert_eq!(set.remove(entity(1, 0)), Some(Selected(1)));
+        assert_eq!(set.remove(entity(1, 0)), None);
+        for i in [0, 2, 3] {
+            assert_eq!(set.get(entity(i, 0)), Some(&Selected(i)));
+        }
+
+        set.insert(entity(2, 0), Selected(20), Tick(0));
+        assert_eq!(set.get(entity(2, 0)), Some(&Selected(20)));
+    }
+
+    #[test]
+    fn stale_ids_are_not_found() {
+        let mut set = SparseSet::new();
+        set.insert(entity(0, 1), Selected(0), Tick(0));
+        assert_eq!(set.get(entity(0, 0)), None);
+        assert_eq!(set.remove(entity(0, 0)), None);
+        assert_eq!(set.get(entity(0, 1)), Some(&Selected(0)));
+    }
+
+    #[test]
+    fn queries_mix_sparse_and_table_components() {
+        let mut world = World::empty();
+        let selected = world.spawn_empty();
+        let unit = world.spawn_empty();
+        world.insert(unit, Unit(1));
+        world.insert(selected, Selected(0));
+        world.insert(unit, Selected(1));
+
+        assert_eq!(world.remove::<Selected>(unit), Some(Selected(1)));
+        world.insert(unit, Selected(2));
+
+        let units: Vec<_> = world
+            .query::<(&Unit, Option<&Selected>)>()
+            .map(|(unit, selected)| (unit.0, selected.map(|selected| selected.0)))
+            .collect();
+        assert_eq!(units, [(1, Some(2))]);
+        for (selected,) in world.query_mut::<(&mut Selected,)>() {
+            selected.0 += 5;
+        }
+        assert_eq!(world.get::<Selected>(&selected), Some(&Selected(5)));
+
+        world.despawn(selected);
+        assert_eq!(world.query::<(&Selected,)>().count(), 1);
+    }
+}
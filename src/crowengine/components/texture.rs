@@ -1,7 +1,11 @@
+use super::components::Component;
+
 pub struct Texture {
     pub id: u32,
 }
 
+impl Component for Texture {}
+
 impl Texture {
     pub fn new(texture: u32) -> Self {
         Self { id: texture }
@@ -1,11 +1,17 @@
+use super::super::reflect::traits::Reflect;
+use super::components::Component;
 use nalgebra_glm as glm;
 
+#[derive(Reflect)]
 pub struct Transform {
     pub position: glm::Vec3,
     pub rotation: glm::Quat,
     pub scale: glm::Vec3,
 }
 
+impl Component for Transform {}
+
+#[allow(dead_code)]
 impl Transform {
     pub fn from_xyz(x: f32, y: f32, z: f32) -> Self {
         Self {
@@ -0,0 +1,51 @@
+use crate::window::headless::HeadlessPlugin;
+use crate::window::input::InputPlugin;
+#[cfg(feature = "offscreen")]
+use crate::window::offscreen::OffscreenPlugin;
+use crate::window::window_plugin::WindowPlugin;
+
+use super::app::PluginGroup;
+use super::assets::AssetsPlugin;
+use super::render::RenderPlugin;
+use super::time::TimePlugin;
+
+use super::transform_propagation::TransformPlugin;
+use std::time::Duration;
+
+// Everything a windowed game needs, in the order the plugins depend on each
+// other: the window creates the GL context that assets and rendering use.
+pub fn default_plugins() -> PluginGroup {
+    PluginGroup::new()
+        .add(WindowPlugin)
+        .add(InputPlugin)
+        .add(TimePlugin)
+        .add(TransformPlugin)
+        .add(AssetsPlugin)
+        .add(RenderPlugin)
+}
+
+// Everything but the window and rendering, for simulation servers and tests.
+// Input still works, but only through `KeyboardInput` events sent by hand.
+#[allow(dead_code)]
+pub fn headless_plugins(frame_time: Option<Duration>) -> PluginGroup {
+    PluginGroup::new()
+        .add(HeadlessPlugin { frame_time })
+        .add(InputPlugin)
+        .add(TimePlugin)
+        .add(TransformPlugin)
+}
+
+// The default plugins with an offscreen framebuffer in place of the window.
+// `OffscreenContext::read_pixels` returns what was rendered.
+#[cfg(feature = "offscreen")]
+#[allow(dead_code)]
+pub fn offscreen_plugins(width: i32, height: i32) -> PluginGroup {
+    PluginGroup::new()
+        .add(OffscreenPlugin { width, height })
+        .add(HeadlessPlugin { frame_time: None })
+        .add(InputPlugin)
+        .add(TimePlugin)
+        .add(TransformPlugin)
+        .add(AssetsPlugin)
+        .add(RenderPlugin)
+}
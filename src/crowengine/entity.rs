@@ -1,2 +1,142 @@
-#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
-pub struct EntityId(pub u32);
+use std::sync::atomic::{AtomicU32, Ordering};
+
+// Ordered by index first, which is the order entities were first allocated in.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct EntityId {
+    pub index: u32,
+    pub generation: u32,
+}
+
+struct EntityMeta {
+    generation: u32,
+    alive: bool,
+}
+
+pub struct Entities {
+    meta: Vec<EntityMeta>,
+    free: Vec<u32>,
+    reserved: AtomicU32,
+}
+
+impl Entities {
+    pub fn new() -> Self {
+        Self {
+            meta: Vec::new(),
+            free: Vec::new(),
+            reserved: AtomicU32::new(0),
+        }
+    }
+
+    // Reserved ids always come from fresh indices so they can be handed out
+    // through `&self` without touching the free list.
+    pub fn reserve(&self) -> EntityId {
+        let offset = self.reserved.fetch_add(1, Ordering::Relaxed);
+        EntityId {
+            index: self.meta.len() as u32 + offset,
+            generation: 0,
+        }
+    }
+
+    pub fn flush(&mut self) {
+        let reserved = std::mem::replace(self.reserved.get_mut(), 0);
+        for _ in 0..reserved {
+            self.meta.push(EntityMeta {
+                generation: 0,
+                alive: true,
+            });
+        }
+    }
+
+    pub fn alloc(&mut self) -> EntityId {
+        self.flush();
+
+        if let Some(index) = self.free.pop() {
+            let meta = &mut self.meta[index as usize];
+            meta.alive = true;
+            return EntityId {
+                index,
+                generation: meta.generation,
+            };
+        }
+
+        let index = self.meta.len() as u32;
+        self.meta.push(EntityMeta {
+            generation: 0,
+            alive: true,
+        });
+        EntityId {
+            index,
+            generation: 0,
+        }
+    }
+
+    // Bumps the generation so every copy of the old id becomes stale.
+    pub fn free(&mut self, entity: EntityId) -> bool {
+        self.flush();
+
+        if !self.contains(entity) {
+            return false;
+        }
+
+        let meta = &mut self.meta[entity.index as usize];
+        meta.alive = false;
+        meta.generation = meta.generation.wrapping_add(1);
+        self.free.push(entity.index);
+        true
+    }
+
+    pub fn contains(&self, entity: EntityId) -> bool {
+        self.meta
+            .get(entity.index as usize)
+            .is_some_and(|meta| meta.alive && meta.generation == entity.generation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn freed_indices_are_reused_with_a_new_generation() {
+        let mut entities = Entities::new();
+        let first = entities.alloc();
+        let second = entities.alloc();
+        assert_ne!(first, second);
+
+        assert!(entities.free(first));
+        assert!(!entities.contains(first));
+        let reused = entities.alloc();
+        assert_eq!(reused.index, first.index);
+        assert_eq!(reused.generation, first.generation + 1);
+        assert!(entities.contains(reused));
+        assert!(!entities.contains(first));
+    }
+
+    #[test]
+    fn stale_ids_cannot_be_freed() {
+        let mut entities = Entities::new();
+        let entity = entities.alloc();
+        assert!(entities.free(entity));
+        assert!(!entities.free(entity));
+
+        let reused = entities.alloc();
+        assert!(!entities.free(entity));
+        assert!(entities.contains(reused));
+    }
+
+    #[test]
+    fn reserved_ids_are_alive_once_flushed() {
+        let mut entities = Entities::new();
+        let allocated = entities.alloc();
+        entities.free(allocated);
+
+        let reserved = [entities.reserve(), entities.reserve()];
+        assert_eq!(reserved[0].index, 1);
+        assert_eq!(reserved[1].index, 2);
+        assert!(!entities.contains(reserved[0]));
+
+        entities.flush();
+        assert!(reserved.iter().all(|entity| entities.contains(*entity)));
+        assert_eq!(entities.alloc().index, allocated.index);
+    }
+}
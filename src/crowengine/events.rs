@@ -0,0 +1,266 @@
+use super::components::change_detection::Ticks;
+use super::components::query::Access;
+use super::resources::Resource;
+use super::systems::system_param::SystemParam;
+use super::world::World;
+
+use std::any::type_name;
+use std::marker::PhantomData;
+use std::mem;
+
+// Messages passed between systems, like "unit died" or "turn ended".
+pub trait Event: Send + Sync + 'static {}
+
+// Double-buffered event storage. `update` is called once per frame by
+// `World::update_events`, so every event survives for two frames and readers
+// running before or after the writer in a frame both get to see it.
+pub struct Events<E> {
+    previous: Vec<E>,
+    current: Vec<E>,
+    // The running count of events sent before the first event of each buffer.
+    previous_start: usize,
+    current_start: usize,
+    event_count: usize,
+}
+
+impl<E: Event> Resource for Events<E> {}
+
+#[allow(dead_code)]
+impl<E: Event> Events<E> {
+    pub fn new() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            previous_start: 0,
+            current_start: 0,
+            event_count: 0,
+        }
+    }
+
+    pub fn send(&mut self, event: E) {
+        self.current.push(event);
+        self.event_count += 1;
+    }
+
+    // Drops the events of the previous frame and starts a new one.
+    pub fn update(&mut self) {
+        self.previous = mem::take(&mut self.current);
+        self.previous_start = self.current_start;
+        self.current_start = self.event_count;
+    }
+
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // A reader that will see every event still stored.
+    pub fn get_reader(&self) -> ManualEventReader<E> {
+        ManualEventReader::new()
+    }
+
+    fn iter_from(&self, event_count: usize) -> impl Iterator<Item = &E> {
+        let previous = event_count
+            .saturating_sub(self.previous_start)
+            .min(self.previous.len());
+        let current = event_count
+            .saturating_sub(self.current_start)
+            .min(self.current.len());
+
+        self.previous[previous..]
+            .iter()
+            .chain(self.current[current..].iter())
+    }
+}
+
+// A read cursor into an `Events<E>` for code outside of systems, e.g. the main
+// loop or exclusive systems. Events that were dropped before being read are
+// skipped.
+pub struct ManualEventReader<E> {
+    last_event_count: usize,
+    marker: PhantomData<fn() -> E>,
+}
+
+impl<E: Event> ManualEventReader<E> {
+    pub fn new() -> Self {
+        Self {
+            last_event_count: 0,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn read<'a>(&mut self, events: &'a Events<E>) -> impl Iterator<Item = &'a E> {
+        let last_event_count = mem::replace(&mut self.last_event_count, events.event_count);
+        events.iter_from(last_event_count)
+    }
+}
+
+// Reads the events of type `E` sent since this system last ran.
+pub struct EventReader<'w, 's, E: Event> {
+    reader: &'s mut ManualEventReader<E>,
+    events: &'w Events<E>,
+}
+
+#[allow(dead_code)]
+impl<E: Event> EventReader<'_, '_, E> {
+    pub fn read(&mut self) -> impl Iterator<Item = &E> {
+        self.reader.read(self.events)
+    }
+}
+
+impl<E: Event> SystemParam for EventReader<'_, '_, E> {
+    type State = ManualEventReader<E>;
+    type Item<'w, 's> = EventReader<'w, 's, E>;
+
+    fn init_state(_world: &mut World, access: &mut Access) -> Self::State {
+        access.add_resource_read::<Events<E>>();
+        ManualEventReader::new()
+    }
+
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut Self::State,
+        world: &'w World,
+        _ticks: Ticks,
+    ) -> Self::Item<'w, 's> {
+        EventReader {
+            reader: state,
+            events: events::<E>(world),
+        }
+    }
+}
+
+pub struct EventWriter<'w, E: Event> {
+    events: &'w mut Events<E>,
+}
+
+#[allow(dead_code)]
+impl<E: Event> EventWriter<'_, E> {
+    pub fn send(&mut self, event: E) {
+        self.events.send(event);
+    }
+}
+
+impl<E: Event> SystemParam for EventWriter<'_, E> {
+    type State = ();
+    type Item<'w, 's> = EventWriter<'w, E>;
+
+    fn init_state(_world: &mut World, access: &mut Access) -> Self::State {
+        access.add_resource_write::<Events<E>>();
+    }
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        world: &'w World,
+        _ticks: Ticks,
+    ) -> Self::Item<'w, 's> {
+        // Safety: the write was registered in the system's access.
+        let events = unsafe { world.get_resource_unchecked_mut::<Events<E>>() };
+        EventWriter {
+            events: events.unwrap_or_else(|| missing::<E>()),
+        }
+    }
+}
+
+fn events<E: Event>(world: &World) -> &Events<E> {
+    world
+        .get_resource::<Events<E>>()
+        .unwrap_or_else(|| missing::<E>())
+}
+
+fn missing<E: Event>() -> ! {
+    panic!(
+        "Event {} has not been added with World::add_event!",
+        type_name::<E>()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::systems::schedule::{IntoSystemConfig, Schedule, Stage};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, PartialEq)]
+    struct UnitDied(u32);
+    impl Event for UnitDied {}
+
+    fn read(reader: &mut ManualEventReader<UnitDied>, events: &Events<UnitDied>) -> Vec<u32> {
+        reader.read(events).map(|event| event.0).collect()
+    }
+
+    #[test]
+    fn events_live_for_two_frames() {
+        let mut events = Events::new();
+        let mut reader = events.get_reader();
+        events.send(UnitDied(1));
+        events.update();
+        events.send(UnitDied(2));
+        assert_eq!(events.len(), 2);
+
+        let mut late = events.get_reader();
+        assert_eq!(read(&mut reader, &events), [1, 2]);
+        assert!(read(&mut reader, &events).is_empty());
+
+        events.update();
+        events.send(UnitDied(3));
+        assert_eq!(read(&mut late, &events), [2, 3]);
+        assert_eq!(read(&mut reader, &events), [3]);
+
+        events.update();
+        events.update();
+        assert!(events.is_empty());
+        assert!(read(&mut events.get_reader(), &events).is_empty());
+    }
+
+    #[test]
+    fn readers_see_each_event_once_before_or_after_the_writer() {
+        fn mourn(mut writer: EventWriter<UnitDied>) {
+            writer.send(UnitDied(1));
+        }
+
+        let early = Arc::new(Mutex::new(Vec::new()));
+        let late = Arc::new(Mutex::new(Vec::new()));
+        let (early_log, late_log) = (early.clone(), late.clone());
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(
+                Stage::Update,
+                (move |mut reader: EventReader<UnitDied>| {
+                    early_log
+                        .lock()
+                        .unwrap()
+                        .extend(reader.read().map(|event| event.0))
+                })
+                .before("mourn"),
+            )
+            .add_system(Stage::Update, mourn)
+            .add_system(
+                Stage::Update,
+                (move |mut reader: EventReader<UnitDied>| {
+                    late_log
+                        .lock()
+                        .unwrap()
+                        .extend(reader.read().map(|event| event.0))
+                })
+                .after("mourn"),
+            );
+
+        let mut world = World::empty();
+        world.add_event::<UnitDied>();
+        for _ in 0..3 {
+            world.update_events();
+            schedule.run(&mut world);
+        }
+        assert_eq!(*early.lock().unwrap(), [1, 1]);
+        assert_eq!(*late.lock().unwrap(), [1, 1, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "has not been added")]
+    fn sending_unregistered_events_panics() {
+        World::empty().send_event(UnitDied(1));
+    }
+}
@@ -1,41 +1,13 @@
+use super::super::backends::render_backend::RenderBackend;
+
 use image::GenericImageView;
 use std::path::Path;
 
-pub fn load_texture(path: &str) -> u32 {
-    let img = image::open(&Path::new(path)).expect("Failed to load texture");
+pub fn load_texture(backend: &mut dyn RenderBackend, path: &str) -> u32 {
+    let img = image::open(Path::new(path)).expect("Failed to load texture");
     let img = img.flipv();
     let data = img.to_rgba8();
 
     let (width, height) = img.dimensions();
-    let mut texture = 0;
-
-    unsafe {
-        gl::GenTextures(1, &mut texture);
-        gl::BindTexture(gl::TEXTURE_2D, texture);
-
-        gl::TexImage2D(
-            gl::TEXTURE_2D,
-            0,
-            gl::RGBA as i32,
-            width as i32,
-            height as i32,
-            0,
-            gl::RGBA,
-            gl::UNSIGNED_BYTE,
-            data.as_ptr() as *const _,
-        );
-        gl::GenerateMipmap(gl::TEXTURE_2D);
-
-        // Texture parameters (wrap, filter)
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::REPEAT as i32);
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT as i32);
-        gl::TexParameteri(
-            gl::TEXTURE_2D,
-            gl::TEXTURE_MIN_FILTER,
-            gl::LINEAR_MIPMAP_LINEAR as i32,
-        );
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
-    }
-
-    texture
+    backend.create_texture(width, height, &data)
 }
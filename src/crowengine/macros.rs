@@ -0,0 +1,10 @@
+// Invokes `$m` once for every suffix of the identifier list, which is how the
+// tuple impls for bundles, queries, filters and system parameters are generated
+// for every arity up to the length of the list.
+macro_rules! all_tuples {
+    ($m:ident; $head:ident $(, $tail:ident)*) => {
+        $m!($head $(, $tail)*);
+        all_tuples!($m; $($tail),*);
+    };
+    ($m:ident;) => {};
+}
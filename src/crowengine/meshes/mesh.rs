@@ -1,103 +1,55 @@
-extern crate gl;
-
+use super::super::backends::render_backend::{RenderId, Renderer};
+use super::super::entity::EntityId;
 use super::super::shaders::shader::Shader;
-use crate::glm;
-use nalgebra_glm::{identity, translate, Vec3};
+use super::super::world::World;
+use nalgebra_glm::Vec3;
 
+use super::super::components::components::Component;
 use nalgebra_glm::Mat4;
 
+// Vertex data that is uploaded to the backend the first time it is drawn, so
+// meshes can be created before, or entirely without, a GL context. The
+// `RenderPlugin` frees the upload again when the mesh is removed.
 pub struct Mesh {
-    vao: u32,
-    indices_count: i32,
+    vertices: Vec<f32>,
+    indices: Vec<u32>,
+    id: RenderId,
 }
 
+impl Component for Mesh {}
+
 impl Mesh {
     pub fn new((vertices, indices): (Vec<f32>, Vec<u32>)) -> Self {
-        let mut vao = 0;
-        let mut vbo = 0;
-        let mut ebo = 0;
-
-        unsafe {
-            gl::GenVertexArrays(1, &mut vao);
-            gl::GenBuffers(1, &mut vbo);
-            gl::GenBuffers(1, &mut ebo);
-
-            gl::BindVertexArray(vao);
-
-            // VBO
-            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
-            gl::BufferData(
-                gl::ARRAY_BUFFER,
-                (vertices.len() * std::mem::size_of::<f32>()) as isize,
-                vertices.as_ptr().cast(),
-                gl::STATIC_DRAW,
-            );
-
-            // EBO
-            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
-            gl::BufferData(
-                gl::ELEMENT_ARRAY_BUFFER,
-                (indices.len() * std::mem::size_of::<u32>()) as isize,
-                indices.as_ptr().cast(),
-                gl::STATIC_DRAW,
-            );
-
-            // Vertex attribute
-
-            let stride = (8 * std::mem::size_of::<f32>()) as i32;
-
-            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, std::ptr::null());
-            gl::EnableVertexAttribArray(0);
-
-            gl::VertexAttribPointer(
-                1,
-                2,
-                gl::FLOAT,
-                gl::FALSE,
-                stride,
-                (3 * std::mem::size_of::<f32>()) as *const _,
-            );
-            gl::EnableVertexAttribArray(1);
-
-            gl::VertexAttribPointer(
-                2,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                stride,
-                (5 * std::mem::size_of::<f32>()) as *const _,
-            );
-            gl::EnableVertexAttribArray(2);
-            gl::BindVertexArray(0);
-        }
-
         Mesh {
-            vao,
-            indices_count: indices.len() as i32,
+            vertices,
+            indices,
+            id: RenderId::new(),
         }
     }
 
-    pub fn set_color(&self, shader: &Shader, color: &Vec3) {
-        shader.set_color_uniform(&color);
+    pub fn id(&self) -> RenderId {
+        self.id
     }
 
-    pub fn set_texture(&self, shader: &Shader, name: &str, texture: &u32) {
-        shader.set_texture_uniform(name, 0);
-        unsafe {
-            gl::ActiveTexture(gl::TEXTURE0);
-            gl::BindTexture(gl::TEXTURE_2D, *texture);
-            gl::BindVertexArray(self.vao);
-            gl::DrawElements(
-                gl::TRIANGLES,
-                self.indices_count,
-                gl::UNSIGNED_INT,
-                std::ptr::null(),
-            );
-        }
+    pub fn vao(&self, renderer: &mut Renderer) -> u32 {
+        renderer.mesh(self.id, &self.vertices, &self.indices)
+    }
+
+    pub fn set_color(&self, renderer: &mut Renderer, shader: &Shader, color: &Vec3) {
+        shader.set_color_uniform(renderer, color);
+    }
+
+    pub fn set_texture(&self, renderer: &mut Renderer, shader: &Shader, name: &str, texture: &u32) {
+        shader.set_texture_uniform(renderer, name, 0);
+        renderer.backend_mut().bind_texture(0, *texture);
     }
 
+    // Every uniform is set before the draw call, so nothing is drawn with the
+    // matrices of the previous mesh or frame.
+    #[allow(clippy::too_many_arguments)]
     pub fn draw(
         &self,
+        renderer: &mut Renderer,
         shader: &Shader,
         color: &Vec3,
         texture: &u32,
@@ -105,12 +57,25 @@ impl Mesh {
         projection: &Mat4,
         view: &Mat4,
     ) {
-        shader.use_program();
+        let vao = self.vao(renderer);
+        shader.use_program(renderer);
+
+        self.set_color(renderer, shader, color);
+        shader.set_model_uniform(renderer, model_matrix);
+        self.set_texture(renderer, shader, "texture1", texture);
+        shader.set_projection_uniform(renderer, projection);
+        shader.set_view_uniform(renderer, view);
+
+        renderer
+            .backend_mut()
+            .draw_indexed(vao, self.indices.len() as i32);
+    }
+}
 
-        self.set_color(&shader, &color);
-        shader.set_model_uniform(model_matrix);
-        self.set_texture(&shader, "texture1", &texture);
-        shader.set_projection_uniform(&projection);
-        shader.set_view_uniform(&view);
+// An `on_remove` hook for `Mesh`, registered by the `RenderPlugin`.
+pub fn free_mesh(world: &mut World, entity: EntityId) {
+    let id = world.get::<Mesh>(&entity).unwrap().id();
+    if let Some(renderer) = world.get_resource_mut::<Renderer>() {
+        renderer.free_mesh(id);
     }
 }
@@ -0,0 +1,12 @@
+use super::super::bundle::Bundle;
+use super::super::components::texture::Texture;
+use super::super::components::transform::Transform;
+use super::mesh::Mesh;
+
+// Everything the renderer needs to draw a textured mesh.
+#[derive(Bundle)]
+pub struct MeshBundle {
+    pub transform: Transform,
+    pub mesh: Mesh,
+    pub texture: Texture,
+}
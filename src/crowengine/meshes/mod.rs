@@ -1,2 +1,3 @@
 pub mod cube;
 pub mod mesh;
+pub mod mesh_bundle;
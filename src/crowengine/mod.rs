@@ -1,9 +1,27 @@
+#[macro_use]
+mod macros;
+
+pub mod app;
+pub mod assets;
+pub mod backends;
 pub mod bundle;
+pub mod command_queue;
 pub mod commands;
 pub mod components;
+pub mod default_plugins;
 pub mod entity;
+pub mod events;
 pub mod loaders;
 pub mod meshes;
+pub mod observer;
+pub mod prefab;
+pub mod reflect;
 pub mod render;
+pub mod resources;
+pub mod scene;
 pub mod shaders;
+pub mod state;
+pub mod systems;
+pub mod time;
+pub mod transform_propagation;
 pub mod world;
@@ -0,0 +1,136 @@
+use super::components::hooks::Trigger;
+use super::entity::EntityId;
+use super::world::World;
+
+use std::any::TypeId;
+use std::collections::HashMap;
+
+pub type Observer = Box<dyn FnMut(&mut World, EntityId) + Send + Sync>;
+
+// Callbacks attached to a single entity, run with full world access when one
+// of its components is added, inserted or removed. Unlike hooks, an entity can
+// have any number of observers and they go away when it is despawned.
+pub struct Observers {
+    observers: HashMap<(EntityId, TypeId, Trigger), Vec<Observer>>,
+}
+
+impl Observers {
+    pub fn new() -> Self {
+        Self {
+            observers: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, entity: EntityId, type_id: TypeId, trigger: Trigger, observer: Observer) {
+        self.observers
+            .entry((entity, type_id, trigger))
+            .or_default()
+            .push(observer);
+    }
+
+    // Observers are taken out while they run, since they need the world mutably.
+    pub fn take(
+        &mut self,
+        entity: EntityId,
+        type_id: TypeId,
+        trigger: Trigger,
+    ) -> Option<Vec<Observer>> {
+        if self.observers.is_empty() {
+            return None;
+        }
+        self.observers.remove(&(entity, type_id, trigger))
+    }
+
+    // Puts observers back after they ran, ahead of any added in the meantime.
+    pub fn restore(
+        &mut self,
+        entity: EntityId,
+        type_id: TypeId,
+        trigger: Trigger,
+        mut observers: Vec<Observer>,
+    ) {
+        let key = (entity, type_id, trigger);
+        if let Some(added) = self.observers.remove(&key) {
+            observers.extend(added);
+        }
+        self.observers.insert(key, observers);
+    }
+
+    pub fn remove_entity(&mut self, entity: EntityId) {
+        self.observers.retain(|(other, _, _), _| *other != entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::command_queue::CommandQueue;
+    use crate::crowengine::commands::Commands;
+    use crate::crowengine::components::components::Component;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, PartialEq)]
+    struct Health(u32);
+    impl Component for Health {}
+
+    #[derive(Debug, PartialEq)]
+    struct Wounded;
+    impl Component for Wounded {}
+
+    type Log = Arc<Mutex<Vec<&'static str>>>;
+
+    fn logger(
+        log: &Log,
+        message: &'static str,
+    ) -> impl FnMut(&mut World, EntityId) + Send + Sync + 'static {
+        let log = log.clone();
+        move |_, _| log.lock().unwrap().push(message)
+    }
+
+    #[test]
+    fn observers_only_watch_their_entity() {
+        let log = Log::default();
+        let mut world = World::empty();
+        let hero = world.spawn_empty();
+        world
+            .entity_mut(hero)
+            .observe::<Health>(Trigger::Insert, logger(&log, "hero healed"))
+            .observe::<Health>(Trigger::Insert, logger(&log, "crowd cheers"));
+
+        world.insert(hero, Health(1));
+        world.insert(hero, Health(2));
+        world.spawn(Health(3));
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["hero healed", "crowd cheers", "hero healed", "crowd cheers"]
+        );
+    }
+
+    #[test]
+    fn observers_see_removed_components_and_go_away_on_despawn() {
+        let log = Log::default();
+        let mut world = World::empty();
+        let hero = world.spawn(Health(5));
+        let seen = log.clone();
+        world
+            .entity_mut(hero)
+            .observe::<Health>(Trigger::Remove, move |world, entity| {
+                assert_eq!(world.get::<Health>(&entity), Some(&Health(5)));
+                seen.lock().unwrap().push("hero fell");
+            });
+
+        let mut queue = CommandQueue::new();
+        let mut commands = Commands::new(&mut queue, &world);
+        commands
+            .entity(hero)
+            .observe::<Wounded>(Trigger::Add, logger(&log, "hero wounded"));
+        commands.despawn(hero);
+        world.apply(&mut queue);
+        assert_eq!(*log.lock().unwrap(), ["hero fell"]);
+
+        let recruit = world.spawn((Health(5), Wounded));
+        assert_eq!(recruit.index, hero.index);
+        world.despawn(recruit);
+        assert_eq!(log.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,588 @@
+use super::entity::EntityId;
+use super::reflect::traits::{Reflect, ReflectError};
+use super::reflect::type_registry::short_name;
+use super::reflect::value::Value;
+use super::resources::Resource;
+use super::scene::text::{self, ParseError};
+use super::world::World;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+// A named entity template. Components are stored as reflected values, so a
+// prefab with a `base` only has to list the fields it changes; they are merged
+// over the base's components when the prefab is spawned. Children are spawned
+// along with the entity and attached to it.
+#[derive(Clone, Default)]
+pub struct Prefab {
+    pub base: Option<String>,
+    pub components: Vec<(String, Value)>,
+    pub children: Vec<Prefab>,
+}
+
+#[allow(dead_code)]
+impl Prefab {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_base(mut self, base: &str) -> Self {
+        self.base = Some(base.to_string());
+        self
+    }
+
+    pub fn with<T: Reflect>(self, component: T) -> Self {
+        let name = short_name(component.reflect_type_name());
+        self.with_value(name, component.to_value())
+    }
+
+    // Sets some or all fields of a component, e.g. only `hp` of `Health`.
+    pub fn with_value(mut self, component: &str, value: Value) -> Self {
+        merge_component(&mut self.components, component, &value);
+        self
+    }
+
+    pub fn with_child(mut self, child: Prefab) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    // Prefab files have the same syntax as scenes and hold any number of
+    // prefabs by name:
+    //
+    //     (
+    //         unit: (
+    //             components: (Transform: (...), Health: (hp: 10)),
+    //         ),
+    //         warrior: (
+    //             base: "unit",
+    //             components: (Health: (hp: 20)),
+    //             children: [(base: "spear")],
+    //         ),
+    //     )
+    fn from_value(name: &str, value: &Value) -> Result<Self, PrefabError> {
+        let format = |message: &str| PrefabError::Format {
+            prefab: name.to_string(),
+            message: message.to_string(),
+        };
+
+        if !matches!(value, Value::Struct(_)) {
+            return Err(format("expected a struct"));
+        }
+
+        let base = match value.field("base") {
+            Some(Value::String(base)) => Some(base.clone()),
+            None => None,
+            _ => return Err(format("base should be a string")),
+        };
+        let components = match value.field("components") {
+            Some(Value::Struct(components)) => components.clone(),
+            None => Vec::new(),
+            _ => return Err(format("components should be a struct")),
+        };
+        let children = match value.field("children") {
+            Some(Value::List(children)) => children
+                .iter()
+                .map(|child| Prefab::from_value(name, child))
+                .collect::<Result<_, _>>()?,
+            None => Vec::new(),
+            _ => return Err(format("children should be a list")),
+        };
+
+        Ok(Prefab {
+            base,
+            components,
+            children,
+        })
+    }
+}
+
+fn merge_component(components: &mut Vec<(String, Value)>, name: &str, value: &Value) {
+    match components
+        .iter_mut()
+        .find(|(component, _)| component == name)
+    {
+        Some((_, component)) => component.merge(value),
+        None => components.push((name.to_string(), value.clone())),
+    }
+}
+
+struct PrefabFile {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    names: Vec<String>,
+}
+
+// All prefabs known to the world, defined in code with `insert` or loaded from
+// files. Files are checked for changes whenever a prefab is spawned, so edits
+// show up in newly spawned entities while the game is running. The last file
+// or `insert` to define a name owns it, and only the owner removes it again.
+pub struct Prefabs {
+    prefabs: HashMap<String, Prefab>,
+    // The file each name was last loaded from. Names missing here were
+    // inserted by code.
+    owners: HashMap<String, PathBuf>,
+    files: Vec<PrefabFile>,
+}
+
+impl Resource for Prefabs {}
+
+#[allow(dead_code)]
+impl Prefabs {
+    pub fn new() -> Self {
+        Self {
+            prefabs: HashMap::new(),
+            owners: HashMap::new(),
+            files: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, name: &str, prefab: Prefab) {
+        self.owners.remove(name);
+        self.prefabs.insert(name.to_string(), prefab);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Prefab> {
+        self.prefabs.get(name)
+    }
+
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<(), PrefabError> {
+        let path = path.as_ref().to_path_buf();
+        let modified = modified(&path);
+        let prefabs = read_file(&path)?;
+
+        let previous = match self.files.iter().position(|file| file.path == path) {
+            Some(index) => self.files.remove(index).names,
+            None => Vec::new(),
+        };
+        let names = self.replace(&path, &previous, prefabs);
+        self.files.push(PrefabFile {
+            path,
+            modified,
+            names,
+        });
+        Ok(())
+    }
+
+    // Re-reads every loaded file that changed on disk and returns the errors
+    // of those that failed. A failed file keeps its previous prefabs, doesn't
+    // stop the other files from reloading and is tried again once it changes.
+    pub fn reload_changed(&mut self) -> Vec<PrefabError> {
+        let mut errors = Vec::new();
+        for index in 0..self.files.len() {
+            let path = self.files[index].path.clone();
+            let modified = modified(&path);
+            if modified == self.files[index].modified {
+                continue;
+            }
+            self.files[index].modified = modified;
+
+            match read_file(&path) {
+                Ok(prefabs) => {
+                    let previous = std::mem::take(&mut self.files[index].names);
+                    self.files[index].names = self.replace(&path, &previous, prefabs);
+                }
+                Err(error) => errors.push(error),
+            }
+        }
+        errors
+    }
+
+    // Swaps the prefabs `path` defined before for the ones it defines now and
+    // returns their names. Names the file dropped are only removed if no other
+    // file or `insert` took them over in the meantime.
+    fn replace(
+        &mut self,
+        path: &Path,
+        previous: &[String],
+        prefabs: Vec<(String, Prefab)>,
+    ) -> Vec<String> {
+        let names: Vec<String> = prefabs.iter().map(|(name, _)| name.clone()).collect();
+        for name in previous {
+            if !names.contains(name) && self.owners.get(name).is_some_and(|owner| owner == path) {
+                self.owners.remove(name);
+                self.prefabs.remove(name);
+            }
+        }
+
+        for (name, prefab) in prefabs {
+            self.owners.insert(name.clone(), path.to_path_buf());
+            self.prefabs.insert(name, prefab);
+        }
+        names
+    }
+
+    // Flattens the prefab's chain of bases into a prefab without one.
+    pub fn resolve(&self, name: &str) -> Result<Prefab, PrefabError> {
+        let prefab = self
+            .prefabs
+            .get(name)
+            .ok_or_else(|| PrefabError::UnknownPrefab(name.to_string()))?;
+        self.resolve_prefab(prefab, &mut vec![name.to_string()])
+    }
+
+    fn resolve_prefab(
+        &self,
+        prefab: &Prefab,
+        chain: &mut Vec<String>,
+    ) -> Result<Prefab, PrefabError> {
+        let mut resolved = match &prefab.base {
+            Some(base) => {
+                if chain.contains(base) {
+                    chain.push(base.clone());
+                    return Err(PrefabError::Cycle(chain.clone()));
+                }
+
+                let base_prefab = self
+                    .prefabs
+                    .get(base)
+                    .ok_or_else(|| PrefabError::UnknownPrefab(base.clone()))?;
+                chain.push(base.clone());
+                let resolved = self.resolve_prefab(base_prefab, chain)?;
+                chain.pop();
+                resolved
+            }
+            None => Prefab::new(),
+        };
+
+        for (name, value) in &prefab.components {
+            merge_component(&mut resolved.components, name, value);
+        }
+        for child in &prefab.children {
+            resolved.children.push(self.resolve_prefab(child, chain)?);
+        }
+        Ok(resolved)
+    }
+}
+
+// Everything is parsed before anything is replaced, so a broken file doesn't
+// leave half of its prefabs updated.
+fn read_file(path: &Path) -> Result<Vec<(String, Prefab)>, PrefabError> {
+    let file_error = |error| PrefabError::File {
+        path: path.to_path_buf(),
+        error,
+    };
+
+    let source = fs::read_to_string(path).map_err(|error| file_error(FileError::Io(error)))?;
+    let document = text::parse(&source).map_err(|error| file_error(FileError::Parse(error)))?;
+    let Value::Struct(prefabs) = document else {
+        return Err(file_error(FileError::NotAStruct));
+    };
+
+    prefabs
+        .iter()
+        .map(|(name, value)| Ok((name.clone(), Prefab::from_value(name, value)?)))
+        .collect()
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// Inserts the components and spawns the children of prefab `name` into
+// `entity`. On error the entity and anything spawned for it are despawned.
+// Files that fail to reload are reported, but only fail the spawn if `name`
+// can't be resolved from what is still loaded.
+pub fn spawn_prefab(world: &mut World, entity: EntityId, name: &str) -> Result<(), PrefabError> {
+    let prefab = {
+        let prefabs = world.resource_mut::<Prefabs>();
+        for error in prefabs.reload_changed() {
+            eprintln!("{}!", error);
+        }
+        prefabs.resolve(name)
+    };
+
+    let prefab = match prefab {
+        Ok(prefab) => prefab,
+        Err(error) => {
+            world.despawn(entity);
+            return Err(error);
+        }
+    };
+
+    let result = insert_prefab(world, entity, &prefab);
+    if result.is_err() {
+        world.despawn_recursive(entity);
+    }
+    result
+}
+
+fn insert_prefab(world: &mut World, entity: EntityId, prefab: &Prefab) -> Result<(), PrefabError> {
+    for (name, value) in &prefab.components {
+        let component = world
+            .type_registry()
+            .get_by_name(name)
+            .ok_or_else(|| PrefabError::UnknownComponent(name.clone()))?
+            .component;
+        component
+            .insert(world, entity, value)
+            .map_err(|error| PrefabError::Reflect {
+                component: name.clone(),
+                error,
+            })?;
+    }
+
+    for child in &prefab.children {
+        let child_entity = world.spawn_empty();
+        world.set_parent(child_entity, entity);
+        insert_prefab(world, child_entity, child)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum FileError {
+    Io(std::io::Error),
+    Parse(ParseError),
+    NotAStruct,
+}
+
+#[derive(Debug)]
+pub enum PrefabError {
+    UnknownPrefab(String),
+    // The bases of a prefab lead back to the prefab itself.
+    Cycle(Vec<String>),
+    UnknownComponent(String),
+    File {
+        path: PathBuf,
+        error: FileError,
+    },
+    Format {
+        prefab: String,
+        message: String,
+    },
+    Reflect {
+        component: String,
+        error: ReflectError,
+    },
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PrefabError::UnknownPrefab(name) => write!(f, "Prefab '{}' does not exist", name),
+            PrefabError::Cycle(chain) => {
+                write!(
+                    f,
+                    "Prefabs [{}] inherit from each other",
+                    chain.join(" -> ")
+                )
+            }
+            PrefabError::UnknownComponent(name) => write!(
+                f,
+                "Component '{}' is not registered with World::register_type",
+                name
+            ),
+            PrefabError::File { path, error } => {
+                write!(f, "Could not load prefabs from {}: ", path.display())?;
+                match error {
+                    FileError::Io(error) => write!(f, "{}", error),
+                    FileError::Parse(error) => write!(f, "{}", error),
+                    FileError::NotAStruct => write!(f, "expected a struct of prefabs"),
+                }
+            }
+            PrefabError::Format { prefab, message } => {
+                write!(f, "Invalid prefab '{}': {}", prefab, message)
+            }
+            PrefabError::Reflect { component, error } => {
+                write!(f, "Component '{}' is invalid: {}", component, error)
+            }
+        }
+    }
+}
+
+impl std::error::Error for PrefabError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::command_queue::CommandQueue;
+    use crate::crowengine::commands::Commands;
+    use crate::crowengine::components::hierarchy::Children;
+    use crate::crowengine::components::transform::Transform;
+    use crate::crowengine::components::use_shader::UseShader;
+    use std::time::Duration;
+
+    fn unit() -> Prefab {
+        Prefab::new()
+            .with(Transform::from_xyz(1.0, 2.0, 3.0))
+            .with(UseShader::new("default".to_string()))
+    }
+
+    fn shader(world: &World, entity: EntityId) -> &str {
+        &world.get::<UseShader>(&entity).unwrap().shader_name
+    }
+
+    // Writes the file with a modification time `version` seconds after the
+    // epoch, so every write counts as a change however fast the test runs.
+    fn write(path: &Path, text: &str, version: u64) {
+        fs::write(path, text).unwrap();
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(version))
+            .unwrap();
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn prefabs_override_their_base() {
+        let mut world = World::empty();
+        let prefabs = world.resource_mut::<Prefabs>();
+        prefabs.insert("unit", unit());
+        prefabs.insert(
+            "warrior",
+            Prefab::new()
+                .with_base("unit")
+                .with_value(
+                    "Transform",
+                    Value::Struct(vec![(
+                        "position".to_string(),
+                        Value::Struct(vec![("y".to_string(), Value::Float(9.0))]),
+                    )]),
+                )
+                .with_child(Prefab::new().with_base("unit")),
+        );
+
+        let warrior = world.spawn_prefab("warrior").unwrap();
+        let position = world.get::<Transform>(&warrior).unwrap().position;
+        assert_eq!((position.x, position.y, position.z), (1.0, 9.0, 3.0));
+        assert_eq!(shader(&world, warrior), "default");
+
+        let children: Vec<EntityId> = world.get::<Children>(&warrior).unwrap().iter().collect();
+        assert_eq!(children.len(), 1);
+        assert_eq!(
+            world.get::<Transform>(&children[0]).unwrap().position.y,
+            2.0
+        );
+    }
+
+    #[test]
+    fn unresolvable_prefabs_spawn_nothing() {
+        let mut world = World::empty();
+        let prefabs = world.resource_mut::<Prefabs>();
+        prefabs.insert("knight", Prefab::new().with_base("squire"));
+        prefabs.insert("squire", Prefab::new().with_base("knight"));
+        prefabs.insert(
+            "ghost",
+            Prefab::new().with_child(Prefab::new().with_value("Bogus", Value::Int(1))),
+        );
+
+        assert!(matches!(
+            world.spawn_prefab("knight"),
+            Err(PrefabError::Cycle(chain)) if chain == ["knight", "squire", "knight"]
+        ));
+        assert!(matches!(
+            world.spawn_prefab("nobody"),
+            Err(PrefabError::UnknownPrefab(_))
+        ));
+        assert!(matches!(
+            world.spawn_prefab("ghost"),
+            Err(PrefabError::UnknownComponent(_))
+        ));
+        assert_eq!(world.query::<(EntityId,)>().count(), 0);
+    }
+
+    #[test]
+    fn changed_files_are_reloaded_when_spawning() {
+        let dir = temp_dir("prefab_reload");
+        let archers = dir.join("archers.prefab");
+        write(
+            &archers,
+            "(archer: (base: \"unit\", components: (UseShader: (shader_name: \"bow\"))))",
+            1,
+        );
+
+        let mut world = World::empty();
+        world.resource_mut::<Prefabs>().insert("unit", unit());
+        world.resource_mut::<Prefabs>().load(&archers).unwrap();
+
+        let mut queue = CommandQueue::new();
+        let archer = Commands::new(&mut queue, &world).spawn_prefab("archer");
+        world.apply(&mut queue);
+        assert_eq!(shader(&world, archer), "bow");
+
+        write(
+            &archers,
+            "(archer: (base: \"unit\", components: (UseShader: (shader_name: \"crossbow\"))))",
+            2,
+        );
+        let archer = world.spawn_prefab("archer").unwrap();
+        assert_eq!(shader(&world, archer), "crossbow");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn broken_files_keep_their_prefabs() {
+        let dir = temp_dir("prefab_broken");
+        let (archers, riders) = (dir.join("archers.prefab"), dir.join("riders.prefab"));
+        write(&archers, "(archer: (base: \"unit\"))", 1);
+        write(&riders, "(rider: (base: \"unit\"))", 1);
+
+        let mut world = World::empty();
+        let prefabs = world.resource_mut::<Prefabs>();
+        prefabs.insert("unit", unit());
+        prefabs.load(&archers).unwrap();
+        prefabs.load(&riders).unwrap();
+
+        write(&archers, "(archer: (base: \"unit\"", 2);
+        write(
+            &riders,
+            "(rider: (base: \"unit\", components: (UseShader: (shader_name: \"horse\"))))",
+            2,
+        );
+        let errors = world.resource_mut::<Prefabs>().reload_changed();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(
+            &errors[0],
+            PrefabError::File { path, error: FileError::Parse(_) } if *path == archers
+        ));
+
+        // The error is reported once per change, and spawning carries on.
+        assert!(world.resource_mut::<Prefabs>().reload_changed().is_empty());
+        let archer = world.spawn_prefab("archer").unwrap();
+        assert_eq!(shader(&world, archer), "default");
+        let rider = world.spawn_prefab("rider").unwrap();
+        assert_eq!(shader(&world, rider), "horse");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn files_only_remove_the_prefabs_they_own() {
+        let dir = temp_dir("prefab_owners");
+        let (archers, riders) = (dir.join("archers.prefab"), dir.join("riders.prefab"));
+        write(&archers, "(archer: (), scout: (), spy: ())", 1);
+        write(&riders, "(scout: ())", 1);
+
+        let mut world = World::empty();
+        let prefabs = world.resource_mut::<Prefabs>();
+        prefabs.load(&archers).unwrap();
+        prefabs.load(&riders).unwrap();
+        prefabs.insert("spy", unit());
+
+        write(&archers, "(archer: ())", 2);
+        assert!(prefabs.reload_changed().is_empty());
+        assert!(prefabs.get("scout").is_some());
+        assert!(prefabs.get("spy").is_some());
+
+        write(&riders, "()", 2);
+        assert!(prefabs.reload_changed().is_empty());
+        assert!(prefabs.get("scout").is_none());
+        assert!(prefabs.get("archer").is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
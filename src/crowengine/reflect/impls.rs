@@ -0,0 +1,288 @@
+use super::super::entity::EntityId;
+use super::traits::{from_field, FieldInfo, FromReflect, Reflect, ReflectError, TypeInfo};
+use super::value::Value;
+
+use nalgebra_glm as glm;
+use std::any::{type_name, Any};
+
+// Everything a leaf type needs besides converting to and from a `Value`.
+macro_rules! reflect_leaf {
+    () => {
+        fn type_info() -> TypeInfo {
+            TypeInfo::Value
+        }
+
+        fn reflect_type_name(&self) -> &'static str {
+            type_name::<Self>()
+        }
+
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn as_any_mut(&mut self) -> &mut dyn Any {
+            self
+        }
+
+        fn apply(&mut self, value: &Value) -> Result<(), ReflectError> {
+            *self = Self::from_value(value)?;
+            Ok(())
+        }
+    };
+}
+
+macro_rules! impl_reflect_int {
+    ($($ty:ty),*) => {
+        $(
+            impl Reflect for $ty {
+                reflect_leaf!();
+
+                fn to_value(&self) -> Value {
+                    Value::Int(*self as i64)
+                }
+            }
+
+            impl FromReflect for $ty {
+                fn from_value(value: &Value) -> Result<Self, ReflectError> {
+                    let Value::Int(int) = value else {
+                        return Err(value.mismatch(type_name::<Self>()));
+                    };
+                    <$ty>::try_from(*int).map_err(|_| ReflectError::OutOfRange {
+                        type_name: type_name::<Self>(),
+                        value: *int,
+                    })
+                }
+            }
+        )*
+    };
+}
+
+// `u64` and `usize` are left out since `Value::Int` can't hold all of their
+// values.
+impl_reflect_int!(i8, i16, i32, i64, u8, u16, u32);
+
+macro_rules! impl_reflect_float {
+    ($($ty:ty),*) => {
+        $(
+            impl Reflect for $ty {
+                reflect_leaf!();
+
+                // Goes through the shortest decimal form so an `f32` like 0.1
+                // doesn't turn into 0.10000000149011612.
+                fn to_value(&self) -> Value {
+                    Value::Float(self.to_string().parse().unwrap())
+                }
+            }
+
+            // Integers are accepted too, so files can say `1` instead of `1.0`.
+            impl FromReflect for $ty {
+                fn from_value(value: &Value) -> Result<Self, ReflectError> {
+                    match value {
+                        Value::Float(float) => Ok(*float as $ty),
+                        Value::Int(int) => Ok(*int as $ty),
+                        _ => Err(value.mismatch(type_name::<Self>())),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_reflect_float!(f32, f64);
+
+impl Reflect for bool {
+    reflect_leaf!();
+
+    fn to_value(&self) -> Value {
+        Value::Bool(*self)
+    }
+}
+
+impl FromReflect for bool {
+    fn from_value(value: &Value) -> Result<Self, ReflectError> {
+        match value {
+            Value::Bool(bool) => Ok(*bool),
+            _ => Err(value.mismatch(type_name::<Self>())),
+        }
+    }
+}
+
+impl Reflect for String {
+    reflect_leaf!();
+
+    fn to_value(&self) -> Value {
+        Value::String(self.clone())
+    }
+}
+
+impl FromReflect for String {
+    fn from_value(value: &Value) -> Result<Self, ReflectError> {
+        match value {
+            Value::String(string) => Ok(string.clone()),
+            _ => Err(value.mismatch(type_name::<Self>())),
+        }
+    }
+}
+
+impl Reflect for EntityId {
+    reflect_leaf!();
+
+    fn to_value(&self) -> Value {
+        Value::Entity(*self)
+    }
+}
+
+impl FromReflect for EntityId {
+    fn from_value(value: &Value) -> Result<Self, ReflectError> {
+        match value {
+            Value::Entity(entity) => Ok(*entity),
+            _ => Err(value.mismatch(type_name::<Self>())),
+        }
+    }
+}
+
+impl<T: FromReflect> Reflect for Vec<T> {
+    fn type_info() -> TypeInfo {
+        TypeInfo::List {
+            item_type_name: type_name::<T>(),
+        }
+    }
+
+    fn reflect_type_name(&self) -> &'static str {
+        type_name::<Self>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn field_names(&self) -> Vec<String> {
+        (0..self.len()).map(|index| index.to_string()).collect()
+    }
+
+    fn field(&self, name: &str) -> Option<&dyn Reflect> {
+        let item = self.get(name.parse::<usize>().ok()?)?;
+        Some(item)
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        let item = self.get_mut(name.parse::<usize>().ok()?)?;
+        Some(item)
+    }
+
+    fn to_value(&self) -> Value {
+        Value::List(self.iter().map(Reflect::to_value).collect())
+    }
+
+    // Lists are replaced as a whole, since there's no sensible way to merge them.
+    fn apply(&mut self, value: &Value) -> Result<(), ReflectError> {
+        *self = Self::from_value(value)?;
+        Ok(())
+    }
+}
+
+impl<T: FromReflect> FromReflect for Vec<T> {
+    fn from_value(value: &Value) -> Result<Self, ReflectError> {
+        match value {
+            Value::List(items) => items.iter().map(T::from_value).collect(),
+            _ => Err(value.mismatch(type_name::<Self>())),
+        }
+    }
+}
+
+// glm vectors are reflected as structs of their components.
+macro_rules! impl_reflect_vec {
+    ($ty:ty; $($name:ident),*) => {
+        impl Reflect for $ty {
+            fn type_info() -> TypeInfo {
+                TypeInfo::Struct {
+                    fields: vec![$(FieldInfo {
+                        name: stringify!($name),
+                        type_name: type_name::<f32>(),
+                    }),*],
+                }
+            }
+
+            fn reflect_type_name(&self) -> &'static str {
+                type_name::<Self>()
+            }
+
+            fn as_any(&self) -> &dyn Any {
+                self
+            }
+
+            fn as_any_mut(&mut self) -> &mut dyn Any {
+                self
+            }
+
+            fn field_names(&self) -> Vec<String> {
+                vec![$(stringify!($name).to_string()),*]
+            }
+
+            fn field(&self, name: &str) -> Option<&dyn Reflect> {
+                match name {
+                    $(stringify!($name) => Some(&self.$name),)*
+                    _ => None,
+                }
+            }
+
+            fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+                match name {
+                    $(stringify!($name) => Some(&mut self.$name),)*
+                    _ => None,
+                }
+            }
+        }
+
+        impl FromReflect for $ty {
+            fn from_value(value: &Value) -> Result<Self, ReflectError> {
+                Ok(<$ty>::new($(from_field(value, type_name::<Self>(), stringify!($name))?),*))
+            }
+        }
+    };
+}
+
+impl_reflect_vec!(glm::Vec2; x, y);
+impl_reflect_vec!(glm::Vec3; x, y, z);
+impl_reflect_vec!(glm::Vec4; x, y, z, w);
+
+// Quaternions share the x, y, z, w layout of their coordinate vector.
+impl Reflect for glm::Quat {
+    fn type_info() -> TypeInfo {
+        glm::Vec4::type_info()
+    }
+
+    fn reflect_type_name(&self) -> &'static str {
+        type_name::<Self>()
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn field_names(&self) -> Vec<String> {
+        self.coords.field_names()
+    }
+
+    fn field(&self, name: &str) -> Option<&dyn Reflect> {
+        self.coords.field(name)
+    }
+
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> {
+        self.coords.field_mut(name)
+    }
+}
+
+impl FromReflect for glm::Quat {
+    fn from_value(value: &Value) -> Result<Self, ReflectError> {
+        Ok(glm::Quat::from(glm::Vec4::from_value(value)?))
+    }
+}
@@ -0,0 +1,4 @@
+pub mod impls;
+pub mod traits;
+pub mod type_registry;
+pub mod value;
@@ -0,0 +1,321 @@
+use super::value::Value;
+
+use std::any::{type_name, Any};
+use std::fmt;
+
+pub use crowengine_derive::Reflect;
+
+#[allow(dead_code)]
+pub struct FieldInfo {
+    pub name: &'static str,
+    pub type_name: &'static str,
+}
+
+// The shape of a reflected type, as far as the registry is concerned.
+#[allow(dead_code)]
+pub enum TypeInfo {
+    // A leaf such as a number, a string or an entity id.
+    Value,
+    List { item_type_name: &'static str },
+    Struct { fields: Vec<FieldInfo> },
+}
+
+// Runtime access to the fields of a value, used by scenes, prefabs and tools
+// that don't know the concrete type. Structs get it through `#[derive(Reflect)]`,
+// which requires every field to be reflectable as well.
+#[allow(dead_code)]
+pub trait Reflect: Any + Send + Sync {
+    fn type_info() -> TypeInfo
+    where
+        Self: Sized;
+
+    fn reflect_type_name(&self) -> &'static str;
+
+    fn as_any(&self) -> &dyn Any;
+
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+
+    // Struct fields by name, list items by index. Leaf values have none.
+    fn field_names(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    fn field(&self, _name: &str) -> Option<&dyn Reflect> {
+        None
+    }
+
+    fn field_mut(&mut self, _name: &str) -> Option<&mut dyn Reflect> {
+        None
+    }
+
+    fn to_value(&self) -> Value {
+        Value::Struct(
+            self.field_names()
+                .into_iter()
+                .map(|name| {
+                    let value = self.field(&name).unwrap().to_value();
+                    (name, value)
+                })
+                .collect(),
+        )
+    }
+
+    // Overwrites the fields present in `value` and leaves the rest alone.
+    fn apply(&mut self, value: &Value) -> Result<(), ReflectError> {
+        let Value::Struct(fields) = value else {
+            return Err(value.mismatch(self.reflect_type_name()));
+        };
+
+        for (name, value) in fields {
+            let type_name = self.reflect_type_name();
+            self.field_mut(name)
+                .ok_or_else(|| ReflectError::NoField {
+                    type_name,
+                    field: name.clone(),
+                })?
+                .apply(value)?;
+        }
+        Ok(())
+    }
+}
+
+// Reflected types that can be built from a `Value` alone, which is what lets
+// the registry insert components it only knows by name.
+pub trait FromReflect: Reflect + Sized {
+    fn from_value(value: &Value) -> Result<Self, ReflectError>;
+}
+
+// Used by `#[derive(Reflect)]` to read one field of a struct value.
+pub fn from_field<T: FromReflect>(
+    value: &Value,
+    type_name: &'static str,
+    field: &'static str,
+) -> Result<T, ReflectError> {
+    if !matches!(value, Value::Struct(_)) {
+        return Err(value.mismatch(type_name));
+    }
+
+    let field_value = value
+        .field(field)
+        .ok_or(ReflectError::MissingField { type_name, field })?;
+    T::from_value(field_value)
+}
+
+#[allow(dead_code)]
+impl dyn Reflect {
+    pub fn downcast_ref<T: Reflect>(&self) -> Option<&T> {
+        self.as_any().downcast_ref()
+    }
+
+    pub fn downcast_mut<T: Reflect>(&mut self) -> Option<&mut T> {
+        self.as_any_mut().downcast_mut()
+    }
+
+    // Follows a dot-separated path such as "position.x" or "children.0".
+    pub fn path(&self, path: &str) -> Result<&dyn Reflect, ReflectError> {
+        let mut value = self;
+        for name in path.split('.') {
+            value = value.field(name).ok_or_else(|| ReflectError::NoField {
+                type_name: value.reflect_type_name(),
+                field: name.to_string(),
+            })?;
+        }
+        Ok(value)
+    }
+
+    pub fn path_mut(&mut self, path: &str) -> Result<&mut dyn Reflect, ReflectError> {
+        let mut value = self;
+        for name in path.split('.') {
+            let type_name = value.reflect_type_name();
+            value = value.field_mut(name).ok_or_else(|| ReflectError::NoField {
+                type_name,
+                field: name.to_string(),
+            })?;
+        }
+        Ok(value)
+    }
+
+    pub fn get_path<T: Reflect>(&self, path: &str) -> Result<&T, ReflectError> {
+        let value = self.path(path)?;
+        value.downcast_ref().ok_or_else(|| ReflectError::WrongType {
+            expected: type_name::<T>(),
+            found: value.reflect_type_name(),
+        })
+    }
+
+    pub fn set_path<T: Reflect>(&mut self, path: &str, new_value: T) -> Result<(), ReflectError> {
+        let value = self.path_mut(path)?;
+        let found = value.reflect_type_name();
+        *value.downcast_mut().ok_or(ReflectError::WrongType {
+            expected: type_name::<T>(),
+            found,
+        })? = new_value;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum ReflectError {
+    // A path or value named a field the type doesn't have.
+    NoField {
+        type_name: &'static str,
+        field: String,
+    },
+    // A struct value is missing a field needed to build the type.
+    MissingField {
+        type_name: &'static str,
+        field: &'static str,
+    },
+    // A `Value` of the wrong kind, e.g. a string where a float was expected.
+    TypeMismatch {
+        expected: &'static str,
+        found: &'static str,
+    },
+    // A Rust value of the wrong type was passed to `set_path` or `get_path`.
+    WrongType {
+        expected: &'static str,
+        found: &'static str,
+    },
+    OutOfRange {
+        type_name: &'static str,
+        value: i64,
+    },
+}
+
+impl fmt::Display for ReflectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReflectError::NoField { type_name, field } => {
+                write!(f, "Type {} has no field '{}'", type_name, field)
+            }
+            ReflectError::MissingField { type_name, field } => {
+                write!(f, "Value for {} is missing field '{}'", type_name, field)
+            }
+            ReflectError::TypeMismatch { expected, found } => {
+                write!(
+                    f,
+                    "Expected a value for {}, found a value of type {}",
+                    expected, found
+                )
+            }
+            ReflectError::WrongType { expected, found } => {
+                write!(f, "Expected {}, found {}", expected, found)
+            }
+            ReflectError::OutOfRange { type_name, value } => {
+                write!(f, "Value {} does not fit into {}", value, type_name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReflectError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::components::transform::Transform;
+    use crate::crowengine::entity::EntityId;
+
+    #[derive(Reflect, Debug, PartialEq)]
+    struct Order(Vec<EntityId>, f32);
+
+    #[derive(Reflect, Debug, PartialEq)]
+    struct Unit {
+        name: String,
+        health: u8,
+        order: Order,
+    }
+
+    fn warrior() -> Unit {
+        Unit {
+            name: "warrior".to_string(),
+            health: 10,
+            order: Order(
+                vec![EntityId {
+                    index: 4,
+                    generation: 1,
+                }],
+                0.5,
+            ),
+        }
+    }
+
+    #[test]
+    fn paths_reach_nested_fields() {
+        let mut transform = Transform::from_xyz(1.0, 2.0, 3.0);
+        let reflected = &mut transform as &mut dyn Reflect;
+        reflected.set_path("position.x", 5.0f32).unwrap();
+        assert_eq!(*reflected.get_path::<f32>("position.x").unwrap(), 5.0);
+        assert_eq!(*reflected.get_path::<f32>("rotation.w").unwrap(), 1.0);
+        assert_eq!(transform.position.x, 5.0);
+
+        let unit = warrior();
+        let reflected = &unit as &dyn Reflect;
+        assert_eq!(reflected.get_path::<f32>("order.1").unwrap(), &0.5);
+        assert_eq!(
+            reflected.path("order.0.0").unwrap().to_value(),
+            Value::Entity(unit.order.0[0])
+        );
+    }
+
+    #[test]
+    fn bad_paths_and_types_are_errors() {
+        let mut transform = Transform::from_xyz(0.0, 0.0, 0.0);
+        let reflected = &mut transform as &mut dyn Reflect;
+        assert!(matches!(
+            reflected.path("position.q"),
+            Err(ReflectError::NoField { field, .. }) if field == "q"
+        ));
+        assert!(matches!(
+            reflected.set_path("position.x", 5u32),
+            Err(ReflectError::WrongType { .. })
+        ));
+        assert!(matches!(
+            reflected.get_path::<f64>("scale.y"),
+            Err(ReflectError::WrongType { .. })
+        ));
+    }
+
+    #[test]
+    fn values_convert_back_and_apply_partially() {
+        let unit = warrior();
+        let value = unit.to_value();
+        assert_eq!(Unit::from_value(&value).unwrap(), unit);
+
+        let mut wounded = warrior();
+        (&mut wounded as &mut dyn Reflect)
+            .apply(&Value::Struct(vec![("health".to_string(), Value::Int(3))]))
+            .unwrap();
+        assert_eq!(wounded.health, 3);
+        assert_eq!(wounded.name, "warrior");
+
+        assert!(matches!(
+            Unit::from_value(&Value::Struct(vec![])),
+            Err(ReflectError::MissingField { field: "name", .. })
+        ));
+        assert!(matches!(
+            u8::from_value(&Value::Int(300)),
+            Err(ReflectError::OutOfRange { value: 300, .. })
+        ));
+        assert!(matches!(
+            String::from_value(&Value::Float(1.0)),
+            Err(ReflectError::TypeMismatch { .. })
+        ));
+        assert_eq!(f32::from_value(&Value::Int(2)).unwrap(), 2.0);
+    }
+
+    #[test]
+    fn ints_round_trip_at_their_bounds() {
+        assert_eq!(i64::from_value(&i64::MAX.to_value()).unwrap(), i64::MAX);
+        assert_eq!(i64::from_value(&i64::MIN.to_value()).unwrap(), i64::MIN);
+        assert_eq!(u32::from_value(&u32::MAX.to_value()).unwrap(), u32::MAX);
+        assert!(matches!(
+            u32::from_value(&Value::Int(u32::MAX as i64 + 1)),
+            Err(ReflectError::OutOfRange { .. })
+        ));
+        assert!(matches!(
+            u32::from_value(&Value::Int(-1)),
+            Err(ReflectError::OutOfRange { value: -1, .. })
+        ));
+    }
+}
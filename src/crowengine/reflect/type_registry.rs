@@ -0,0 +1,247 @@
+use super::super::components::components::Component;
+use super::super::entity::EntityId;
+use super::super::resources::Resource;
+use super::super::world::World;
+use super::traits::{FromReflect, Reflect, ReflectError, TypeInfo};
+use super::value::Value;
+
+use std::any::{type_name, TypeId};
+use std::collections::HashMap;
+
+// Type-erased access to one component type of an entity. It's `Copy` so it can
+// be taken out of the registry before the world is borrowed mutably.
+#[derive(Copy, Clone)]
+pub struct ReflectComponent {
+    get: fn(&World, EntityId) -> Option<&dyn Reflect>,
+    get_mut: fn(&mut World, EntityId) -> Option<&mut dyn Reflect>,
+    insert: fn(&mut World, EntityId, &Value) -> Result<(), ReflectError>,
+    remove: fn(&mut World, EntityId),
+}
+
+#[allow(dead_code)]
+impl ReflectComponent {
+    fn of<T: Component + FromReflect>() -> Self {
+        Self {
+            get: |world, entity| {
+                world
+                    .get::<T>(&entity)
+                    .map(|component| component as &dyn Reflect)
+            },
+            get_mut: |world, entity| {
+                world
+                    .get_mut::<T>(&entity)
+                    .map(|component| component as &mut dyn Reflect)
+            },
+            insert: |world, entity, value| {
+                world.insert(entity, T::from_value(value)?);
+                Ok(())
+            },
+            remove: |world, entity| {
+                world.remove::<T>(entity);
+            },
+        }
+    }
+
+    pub fn get<'w>(&self, world: &'w World, entity: EntityId) -> Option<&'w dyn Reflect> {
+        (self.get)(world, entity)
+    }
+
+    pub fn get_mut<'w>(
+        &self,
+        world: &'w mut World,
+        entity: EntityId,
+    ) -> Option<&'w mut dyn Reflect> {
+        (self.get_mut)(world, entity)
+    }
+
+    // Builds the component from `value` and inserts it, replacing any old one.
+    pub fn insert(
+        &self,
+        world: &mut World,
+        entity: EntityId,
+        value: &Value,
+    ) -> Result<(), ReflectError> {
+        (self.insert)(world, entity, value)
+    }
+
+    pub fn remove(&self, world: &mut World, entity: EntityId) {
+        (self.remove)(world, entity)
+    }
+}
+
+#[allow(dead_code)]
+pub struct TypeRegistration {
+    // The type's name without its module path, which is what files refer to.
+    pub name: &'static str,
+    pub type_name: &'static str,
+    pub type_id: TypeId,
+    pub info: TypeInfo,
+    pub component: ReflectComponent,
+}
+
+// Every component type known by name at runtime. Engine components are
+// registered by `World::empty`; game code adds its own with
+// `World::register_type`.
+pub struct TypeRegistry {
+    registrations: Vec<TypeRegistration>,
+    by_id: HashMap<TypeId, usize>,
+    by_name: HashMap<&'static str, usize>,
+}
+
+impl Resource for TypeRegistry {}
+
+#[allow(dead_code)]
+impl TypeRegistry {
+    pub fn new() -> Self {
+        Self {
+            registrations: Vec::new(),
+            by_id: HashMap::new(),
+            by_name: HashMap::new(),
+        }
+    }
+
+    // Registering the same type twice does nothing, but two types sharing a
+    // name would make files ambiguous, so that panics.
+    pub fn register<T: Component + FromReflect>(&mut self) {
+        let type_id = TypeId::of::<T>();
+        if self.by_id.contains_key(&type_id) {
+            return;
+        }
+
+        let name = short_name(type_name::<T>());
+        if let Some(&other) = self.by_name.get(name) {
+            panic!(
+                "Types {} and {} are both registered as '{}'!",
+                self.registrations[other].type_name,
+                type_name::<T>(),
+                name
+            );
+        }
+
+        let index = self.registrations.len();
+        self.registrations.push(TypeRegistration {
+            name,
+            type_name: type_name::<T>(),
+            type_id,
+            info: T::type_info(),
+            component: ReflectComponent::of::<T>(),
+        });
+        self.by_id.insert(type_id, index);
+        self.by_name.insert(name, index);
+    }
+
+    pub fn get(&self, type_id: TypeId) -> Option<&TypeRegistration> {
+        self.by_id
+            .get(&type_id)
+            .map(|&index| &self.registrations[index])
+    }
+
+    pub fn get_by_name(&self, name: &str) -> Option<&TypeRegistration> {
+        self.by_name
+            .get(name)
+            .map(|&index| &self.registrations[index])
+    }
+
+    // In registration order.
+    pub fn iter(&self) -> impl Iterator<Item = &TypeRegistration> {
+        self.registrations.iter()
+    }
+}
+
+// The name a type is registered under, i.e. without its module path.
+pub fn short_name(type_name: &'static str) -> &'static str {
+    let path = type_name.split('<').next().unwrap_or(type_name);
+    let start = path.rfind("::").map_or(0, |index| index + 2);
+    &type_name[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::components::transform::Transform;
+    use crate::crowengine::reflect::traits::Reflect;
+
+    #[derive(Reflect, Debug, PartialEq)]
+    struct Unit {
+        name: String,
+        health: u32,
+    }
+    impl Component for Unit {}
+
+    mod other {
+        use super::*;
+
+        #[derive(Reflect)]
+        pub struct Unit;
+        impl Component for Unit {}
+    }
+
+    #[test]
+    fn registered_components_are_reachable_by_name() {
+        let mut world = World::empty();
+        world.register_type::<Unit>();
+        world.register_type::<Unit>();
+        let entity = world.spawn((
+            Transform::from_xyz(1.0, 2.0, 3.0),
+            Unit {
+                name: "warrior".to_string(),
+                health: 10,
+            },
+        ));
+
+        let mut names: Vec<_> = world
+            .reflect_components(entity)
+            .iter()
+            .map(|component| short_name(component.reflect_type_name()))
+            .collect();
+        names.sort();
+        assert_eq!(names, ["Transform", "Unit"]);
+
+        world
+            .reflect_component_mut(entity, "Transform")
+            .unwrap()
+            .set_path("position.y", 7.0f32)
+            .unwrap();
+        assert_eq!(world.get::<Transform>(&entity).unwrap().position.y, 7.0);
+        assert!(world.reflect_component(entity, "Health").is_none());
+    }
+
+    #[test]
+    fn registrations_insert_and_remove_from_values() {
+        let mut world = World::empty();
+        world.register_type::<Unit>();
+        let value = Value::Struct(vec![
+            ("name".to_string(), Value::String("archer".to_string())),
+            ("health".to_string(), Value::Int(6)),
+        ]);
+
+        let component = world.type_registry().get_by_name("Unit").unwrap().component;
+        let entity = world.spawn_empty();
+        component.insert(&mut world, entity, &value).unwrap();
+        assert_eq!(world.get::<Unit>(&entity).unwrap().name, "archer");
+        assert!(component
+            .insert(&mut world, entity, &Value::Int(1))
+            .is_err());
+
+        component.remove(&mut world, entity);
+        assert!(world.get::<Unit>(&entity).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "are both registered as 'Unit'")]
+    fn types_sharing_a_name_panic() {
+        let mut registry = TypeRegistry::new();
+        registry.register::<Unit>();
+        registry.register::<other::Unit>();
+    }
+
+    #[test]
+    fn short_names_drop_the_module_path() {
+        assert_eq!(short_name(type_name::<Unit>()), "Unit");
+        assert_eq!(
+            short_name("crowengine::state::StateScoped<game::GameState>"),
+            "StateScoped<game::GameState>"
+        );
+        assert_eq!(short_name("Unit"), "Unit");
+    }
+}
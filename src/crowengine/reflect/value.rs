@@ -0,0 +1,87 @@
+use super::super::entity::EntityId;
+use super::traits::ReflectError;
+
+// A reflected value detached from its Rust type, which is what gets written to
+// and read from files. Structs keep their fields in declaration order.
+#[allow(dead_code)]
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Entity(EntityId),
+    List(Vec<Value>),
+    Struct(Vec<(String, Value)>),
+}
+
+#[allow(dead_code)]
+impl Value {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "integer",
+            Value::Float(_) => "float",
+            Value::String(_) => "string",
+            Value::Entity(_) => "entity",
+            Value::List(_) => "list",
+            Value::Struct(_) => "struct",
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        match self {
+            Value::Struct(fields) => fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    // Overlays `other` onto `self`: struct fields are merged one by one, which
+    // is how partial overrides work, and everything else is replaced.
+    pub fn merge(&mut self, other: &Value) {
+        match (self, other) {
+            (Value::Struct(fields), Value::Struct(overrides)) => {
+                for (name, value) in overrides {
+                    match fields.iter_mut().find(|(field, _)| field == name) {
+                        Some((_, field)) => field.merge(value),
+                        None => fields.push((name.clone(), value.clone())),
+                    }
+                }
+            }
+            (this, other) => *this = other.clone(),
+        }
+    }
+
+    // Replaces every entity reference inside the value, stopping at the first
+    // one `f` can't map.
+    pub fn map_entities<E>(
+        &mut self,
+        f: &mut impl FnMut(EntityId) -> Result<EntityId, E>,
+    ) -> Result<(), E> {
+        match self {
+            Value::Entity(entity) => *entity = f(*entity)?,
+            Value::List(items) => {
+                for item in items {
+                    item.map_entities(f)?;
+                }
+            }
+            Value::Struct(fields) => {
+                for (_, value) in fields {
+                    value.map_entities(f)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    pub fn mismatch(&self, expected: &'static str) -> ReflectError {
+        ReflectError::TypeMismatch {
+            expected,
+            found: self.kind(),
+        }
+    }
+}
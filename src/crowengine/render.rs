@@ -1,35 +1,201 @@
 use crate::crowengine::components::camera::Camera;
 use crate::crowengine::components::color::Color;
+use crate::crowengine::components::global_transform::GlobalTransform;
 use crate::crowengine::components::texture::Texture;
-use crate::crowengine::components::transform::Transform;
 use crate::crowengine::components::use_shader::UseShader;
 
 use nalgebra_glm as glm;
 
-use super::meshes::mesh::Mesh;
+use super::app::{App, Plugin};
+use super::backends::gl_backend::GlBackend;
+use super::backends::render_backend::Renderer;
+use super::meshes::mesh::{free_mesh, Mesh};
+use super::shaders::fragment_shader::FRAGMENT_SHADER_SOURCE;
+use super::shaders::shader::{Shader, Shaders};
+use super::shaders::vertex_shader::VERTEX_SHADER_SOURCE;
+use super::systems::schedule::{IntoSystemConfig, Stage};
 use super::world::World;
 
+pub const CLEAR_COLOR: [f32; 4] = [0.2, 0.3, 0.3, 1.0];
+
+// Exclusive, like every system using the `Renderer`, so it stays on the thread
+// owning the GL context.
+pub fn clear_screen(world: &mut World) {
+    world
+        .resource_mut::<Renderer>()
+        .backend_mut()
+        .clear(CLEAR_COLOR);
+}
+
 pub fn render(world: &mut World) {
-    for (camera_transform, camera) in world.query::<(&Transform, &Camera)>() {
-        let view = camera_transform.view();
-        let projection =
-            glm::perspective(camera.aspect_ratio, camera.fov_y, camera.near, camera.far);
-
-        for (transform, color, texture, mesh, use_shader) in world.query::<(
-            &Transform,
-            Option<&Color>,
-            &Texture,
-            &Mesh,
-            Option<&UseShader>,
-        )>() {
-            mesh.draw(
-                UseShader::get_optional_shader(use_shader, world),
-                &color.unwrap_or(&Color::new(0., 0., 0.)).color,
-                &texture.id,
-                &transform.model_matrix(),
-                &projection,
-                &view,
-            );
+    world.resource_scope(|world, renderer: &mut Renderer| {
+        let shaders = world.resource::<Shaders>();
+
+        for (camera_transform, camera) in world.query::<(&GlobalTransform, &Camera)>() {
+            let view = camera_transform.view();
+            let projection =
+                glm::perspective(camera.aspect_ratio, camera.fov_y, camera.near, camera.far);
+
+            for (transform, color, texture, mesh, use_shader) in world.query::<(
+                &GlobalTransform,
+                Option<&Color>,
+                &Texture,
+                &Mesh,
+                Option<&UseShader>,
+            )>() {
+                mesh.draw(
+                    renderer,
+                    UseShader::get_optional_shader(use_shader, shaders),
+                    &color.unwrap_or(&Color::new(0., 0., 0.)).color,
+                    &texture.id,
+                    &transform.matrix,
+                    &projection,
+                    &view,
+                );
+            }
+        }
+    });
+}
+
+// Renders with OpenGL unless a `Renderer` was inserted before the plugin is
+// added, e.g. one with a `RecordingBackend` for tests. Meshes are deleted from
+// the backend when they are removed from their entity.
+pub struct RenderPlugin;
+
+impl Plugin for RenderPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<Renderer>() {
+            app.insert_resource(Renderer::new(GlBackend::new()));
         }
+        app.world
+            .register_component_hooks::<Mesh>()
+            .on_remove(free_mesh);
+
+        let mut shaders = Shaders::new();
+        shaders.insert(
+            "default".to_string(),
+            Shader::new(VERTEX_SHADER_SOURCE, FRAGMENT_SHADER_SOURCE),
+        );
+
+        app.insert_resource(shaders)
+            .add_system(Stage::Render, clear_screen.before("render"))
+            .add_system(Stage::Render, render);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::backends::recording_backend::{RecordingBackend, RenderCommand};
+    use crate::crowengine::components::transform::Transform;
+    use crate::crowengine::entity::EntityId;
+    use crate::crowengine::meshes::cube::Cube;
+    use crate::crowengine::transform_propagation::TransformPlugin;
+
+    fn app() -> App {
+        let mut app = App::new();
+        app.insert_resource(Renderer::new(RecordingBackend::new()))
+            .add_plugin(TransformPlugin)
+            .add_plugin(RenderPlugin);
+        app.world.spawn((
+            Camera {
+                fov_y: 0.785,
+                near: 0.1,
+                far: 100.0,
+                aspect_ratio: 1.0,
+            },
+            Transform::from_xyz(0.0, 0.0, 3.0),
+        ));
+        app
+    }
+
+    fn spawn_cube(world: &mut World, x: f32) -> EntityId {
+        world.spawn((
+            Transform::from_xyz(x, 0.0, 0.0),
+            Mesh::new(Cube::new(0.5, 0.5, 0.5)),
+            Texture::new(7),
+            Color::new(1., 0., 0.),
+        ))
+    }
+
+    fn commands(app: &App) -> Vec<RenderCommand> {
+        let renderer = app.world.resource::<Renderer>();
+        let backend = renderer.backend().downcast_ref::<RecordingBackend>();
+        backend.unwrap().commands().to_vec()
+    }
+
+    fn uploads(app: &App) -> usize {
+        commands(app)
+            .iter()
+            .filter(|command| {
+                matches!(
+                    command,
+                    RenderCommand::CreateMesh { .. } | RenderCommand::CreateShader { .. }
+                )
+            })
+            .count()
+    }
+
+    #[test]
+    fn meshes_are_drawn_where_their_transform_puts_them() {
+        let mut app = app();
+        for x in [-1.0, 0.0, 1.0] {
+            spawn_cube(&mut app.world, x);
+        }
+        app.update();
+
+        let renderer = app.world.resource::<Renderer>();
+        let backend = renderer.backend().downcast_ref::<RecordingBackend>();
+        let draw_calls = backend.unwrap().draw_calls();
+        assert_eq!(draw_calls.len(), 3);
+
+        let mut xs: Vec<f32> = draw_calls
+            .iter()
+            .map(|draw_call| draw_call.mat4("model").unwrap()[(0, 3)])
+            .collect();
+        xs.sort_by(f32::total_cmp);
+        assert_eq!(xs, [-1.0, 0.0, 1.0]);
+        assert!(draw_calls.iter().all(|draw_call| {
+            draw_call.textures[&0] == 7
+                && draw_call.vec3("color").unwrap().x == 1.0
+                && draw_call.mat4("view").unwrap()[(2, 3)] == -3.0
+        }));
+        assert!(matches!(commands(&app)[0], RenderCommand::Clear(_)));
+    }
+
+    #[test]
+    fn meshes_and_shaders_are_uploaded_once_per_renderer() {
+        let mut app = app();
+        spawn_cube(&mut app.world, 0.0);
+        app.update();
+        app.update();
+        assert_eq!(uploads(&app), 2);
+
+        app.insert_resource(Renderer::new(RecordingBackend::new()));
+        app.update();
+        assert_eq!(uploads(&app), 2);
+    }
+
+    #[test]
+    fn removed_meshes_are_deleted_from_the_backend() {
+        let mut app = app();
+        let despawned = spawn_cube(&mut app.world, 0.0);
+        let removed = spawn_cube(&mut app.world, 1.0);
+        app.update();
+
+        app.world.despawn(despawned);
+        app.world.remove::<Mesh>(removed);
+        let never_drawn = spawn_cube(&mut app.world, 2.0);
+        app.world.despawn(never_drawn);
+
+        let mut deleted: Vec<_> = commands(&app)
+            .into_iter()
+            .filter_map(|command| match command {
+                RenderCommand::DeleteMesh(mesh) => Some(mesh),
+                _ => None,
+            })
+            .collect();
+        deleted.sort();
+        assert_eq!(deleted, [1, 2]);
     }
 }
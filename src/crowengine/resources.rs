@@ -0,0 +1,100 @@
+use std::any::{type_name, Any, TypeId};
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+
+// Global state that isn't attached to an entity, such as time, input or game
+// settings. There is at most one value of each resource type in a world.
+pub trait Resource: Any + Send + Sync {}
+
+// Each value sits in an `UnsafeCell` so systems can borrow different resources
+// mutably through a shared `World`, the same way they do with component columns.
+pub struct Resources {
+    values: HashMap<TypeId, UnsafeCell<Box<dyn Any + Send + Sync>>>,
+}
+
+// Safety: values are only written through `&mut self` or through system
+// parameters whose access was checked against every other running system.
+unsafe impl Sync for Resources {}
+
+#[allow(dead_code)]
+impl Resources {
+    pub fn new() -> Self {
+        Self {
+            values: HashMap::new(),
+        }
+    }
+
+    pub fn insert<R: Resource>(&mut self, resource: R) -> Option<R> {
+        self.values
+            .insert(TypeId::of::<R>(), UnsafeCell::new(Box::new(resource)))
+            .map(|old| *downcast(old.into_inner()))
+    }
+
+    pub fn remove<R: Resource>(&mut self) -> Option<R> {
+        self.values
+            .remove(&TypeId::of::<R>())
+            .map(|old| *downcast(old.into_inner()))
+    }
+
+    pub fn contains<R: Resource>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<R>())
+    }
+
+    pub fn get<R: Resource>(&self) -> Option<&R> {
+        let value = self.values.get(&TypeId::of::<R>())?;
+        // Safety: mutable access through `get_unchecked_mut` is only handed out
+        // to systems whose access doesn't overlap with this borrow.
+        unsafe { (*value.get()).downcast_ref() }
+    }
+
+    pub fn get_mut<R: Resource>(&mut self) -> Option<&mut R> {
+        self.values
+            .get_mut(&TypeId::of::<R>())?
+            .get_mut()
+            .downcast_mut()
+    }
+
+    // Safety: the caller must make sure no other reference to the resource is
+    // alive for `'a`.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_unchecked_mut<R: Resource>(&self) -> Option<&mut R> {
+        let value = self.values.get(&TypeId::of::<R>())?;
+        unsafe { (*value.get()).downcast_mut() }
+    }
+}
+
+fn downcast<R: Resource>(value: Box<dyn Any + Send + Sync>) -> Box<R> {
+    value.downcast().unwrap_or_else(|_| {
+        panic!(
+            "Resource {} is stored under the wrong type!",
+            type_name::<R>()
+        );
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Score(u32);
+    impl Resource for Score {}
+
+    #[derive(Debug, PartialEq)]
+    struct Turn(u32);
+    impl Resource for Turn {}
+
+    #[test]
+    fn there_is_one_value_per_type() {
+        let mut resources = Resources::new();
+        assert_eq!(resources.insert(Score(1)), None);
+        assert_eq!(resources.insert(Score(2)), Some(Score(1)));
+        resources.insert(Turn(7));
+
+        resources.get_mut::<Score>().unwrap().0 += 1;
+        assert_eq!(resources.get::<Score>(), Some(&Score(3)));
+        assert_eq!(resources.remove::<Turn>(), Some(Turn(7)));
+        assert!(!resources.contains::<Turn>());
+        assert_eq!(resources.get::<Turn>(), None);
+    }
+}
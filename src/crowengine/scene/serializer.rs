@@ -0,0 +1,543 @@
+use super::super::components::hierarchy::{Children, Parent};
+use super::super::entity::EntityId;
+use super::super::reflect::traits::ReflectError;
+use super::super::reflect::value::Value;
+use super::super::world::World;
+use super::text::{self, ParseError};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+pub struct SceneEntity {
+    // The entity's number within the scene, which `#n` references point at.
+    pub id: u32,
+    // The scene number of the entity this one is attached to. The hierarchy
+    // is saved here rather than as components, so loading can rebuild it
+    // through `World::set_parent`.
+    pub parent: Option<u32>,
+    // Component values by registered type name.
+    pub components: Vec<(String, Value)>,
+}
+
+// Entities and their registered components, detached from any world. Only
+// reflected components are saved, so e.g. meshes and textures have to be added
+// back by game code after loading.
+//
+// The text form is a list of entities:
+//
+//     (
+//         entities: [
+//             (
+//                 id: 1,
+//                 parent: #0,
+//                 components: (
+//                     Transform: (...),
+//                 ),
+//             ),
+//             ...
+//         ],
+//     )
+pub struct Scene {
+    pub entities: Vec<SceneEntity>,
+}
+
+#[allow(dead_code)]
+impl Scene {
+    // Every entity that has at least one registered component or is part of
+    // a hierarchy, by entity id.
+    pub fn from_world(world: &World) -> Result<Self, SceneError> {
+        let mut entities: Vec<EntityId> = world
+            .query::<(EntityId, Option<&Parent>, Option<&Children>)>()
+            .filter(|(entity, parent, children)| {
+                parent.is_some()
+                    || children.is_some()
+                    || !world.reflect_components(*entity).is_empty()
+            })
+            .map(|(entity, _, _)| entity)
+            .collect();
+        entities.sort();
+
+        Self::from_entities(world, &entities)
+    }
+
+    // Entities are numbered in the order given. A component referring to an
+    // entity that isn't part of the selection is an error, since the reference
+    // couldn't be restored when loading. So is a parent outside of it.
+    pub fn from_entities(world: &World, entities: &[EntityId]) -> Result<Self, SceneError> {
+        let ids: HashMap<EntityId, u32> = entities
+            .iter()
+            .enumerate()
+            .map(|(id, entity)| (*entity, id as u32))
+            .collect();
+        let registry = world.type_registry();
+
+        let mut scene = Scene {
+            entities: Vec::new(),
+        };
+        for (id, &entity) in entities.iter().enumerate() {
+            let parent = match world.get::<Parent>(&entity) {
+                Some(parent) => Some(*ids.get(&parent.get()).ok_or(
+                    SceneError::EntityOutsideScene {
+                        component: "Parent".to_string(),
+                        entity: parent.get(),
+                    },
+                )?),
+                None => None,
+            };
+
+            let mut components = Vec::new();
+            // In registration order, so files list components the same way.
+            for registration in registry.iter() {
+                let Some(component) = registration.component.get(world, entity) else {
+                    continue;
+                };
+                let name = registration.name;
+
+                let mut value = component.to_value();
+                value.map_entities(&mut |other| {
+                    ids.get(&other).map(|&id| scene_entity(id)).ok_or(
+                        SceneError::EntityOutsideScene {
+                            component: name.to_string(),
+                            entity: other,
+                        },
+                    )
+                })?;
+                components.push((name.to_string(), value));
+            }
+
+            scene.entities.push(SceneEntity {
+                id: id as u32,
+                parent,
+                components,
+            });
+        }
+        Ok(scene)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SceneError> {
+        Self::from_text(&fs::read_to_string(path).map_err(SceneError::Io)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneError> {
+        fs::write(path, self.to_text()).map_err(SceneError::Io)
+    }
+
+    pub fn to_text(&self) -> String {
+        let entities = self
+            .entities
+            .iter()
+            .map(|entity| {
+                let mut fields = vec![("id".to_string(), Value::Int(entity.id as i64))];
+                if let Some(parent) = entity.parent {
+                    fields.push(("parent".to_string(), Value::Entity(scene_entity(parent))));
+                }
+                fields.push((
+                    "components".to_string(),
+                    Value::Struct(entity.components.clone()),
+                ));
+                Value::Struct(fields)
+            })
+            .collect();
+
+        text::write(&Value::Struct(vec![(
+            "entities".to_string(),
+            Value::List(entities),
+        )]))
+    }
+
+    pub fn from_text(text: &str) -> Result<Self, SceneError> {
+        let document = text::parse(text).map_err(SceneError::Parse)?;
+
+        let Some(Value::List(entities)) = document.field("entities") else {
+            return Err(SceneError::Format(
+                "expected a list of entities".to_string(),
+            ));
+        };
+
+        let mut scene = Scene {
+            entities: Vec::new(),
+        };
+        for entity in entities {
+            let id = match entity.field("id") {
+                Some(Value::Int(id)) if u32::try_from(*id).is_ok() => *id as u32,
+                _ => return Err(SceneError::Format("entity without a valid id".to_string())),
+            };
+            let parent = match entity.field("parent") {
+                Some(Value::Entity(parent)) => Some(parent.index),
+                None => None,
+                _ => {
+                    return Err(SceneError::Format(format!(
+                        "parent of entity #{} is not an entity reference",
+                        id
+                    )));
+                }
+            };
+            let components = match entity.field("components") {
+                Some(Value::Struct(components)) => components.clone(),
+                None => Vec::new(),
+                _ => {
+                    return Err(SceneError::Format(format!(
+                        "components of entity #{} are not a struct",
+                        id
+                    )));
+                }
+            };
+
+            if scene.entities.iter().any(|other| other.id == id) {
+                return Err(SceneError::Format(format!("entity #{} appears twice", id)));
+            }
+            scene.entities.push(SceneEntity {
+                id,
+                parent,
+                components,
+            });
+        }
+        Ok(scene)
+    }
+
+    // Spawns a fresh entity for every scene entity and returns them in scene
+    // order, with references between them pointing at the new entities. The
+    // whole scene is checked against the registry and its hierarchy first, and
+    // anything spawned is despawned again if a component fails to build, so an
+    // error leaves the world as it was.
+    pub fn spawn(&self, world: &mut World) -> Result<Vec<EntityId>, SceneError> {
+        self.check_hierarchy()?;
+        for entity in &self.entities {
+            for (name, _) in &entity.components {
+                if world.type_registry().get_by_name(name).is_none() {
+                    return Err(SceneError::UnknownComponent {
+                        entity: entity.id,
+                        component: name.clone(),
+                    });
+                }
+            }
+        }
+
+        let spawned: Vec<EntityId> = self.entities.iter().map(|_| world.spawn_empty()).collect();
+        let entities: HashMap<u32, EntityId> = self
+            .entities
+            .iter()
+            .zip(&spawned)
+            .map(|(entity, spawned)| (entity.id, *spawned))
+            .collect();
+
+        let result = self.insert_components(world, &entities, &spawned);
+        if result.is_err() {
+            for entity in &spawned {
+                world.despawn(*entity);
+            }
+            return result.map(|_| spawned);
+        }
+
+        for (entity, &child) in self.entities.iter().zip(&spawned) {
+            if let Some(parent) = entity.parent {
+                world.set_parent(child, entities[&parent]);
+            }
+        }
+        Ok(spawned)
+    }
+
+    // Every parent has to be part of the scene, and no entity may end up as
+    // its own ancestor, which `World::set_parent` would panic on.
+    fn check_hierarchy(&self) -> Result<(), SceneError> {
+        let parents: HashMap<u32, Option<u32>> = self
+            .entities
+            .iter()
+            .map(|entity| (entity.id, entity.parent))
+            .collect();
+
+        for entity in &self.entities {
+            let mut ancestor = entity.parent;
+            let mut depth = 0;
+            while let Some(id) = ancestor {
+                let Some(&parent) = parents.get(&id) else {
+                    return Err(SceneError::Format(format!(
+                        "parent #{} of entity #{} is not in the scene",
+                        id, entity.id
+                    )));
+                };
+                depth += 1;
+                if id == entity.id || depth > parents.len() {
+                    return Err(SceneError::Format(format!(
+                        "entity #{} is its own ancestor",
+                        entity.id
+                    )));
+                }
+                ancestor = parent;
+            }
+        }
+        Ok(())
+    }
+
+    fn insert_components(
+        &self,
+        world: &mut World,
+        entities: &HashMap<u32, EntityId>,
+        spawned: &[EntityId],
+    ) -> Result<(), SceneError> {
+        for (entity, &target) in self.entities.iter().zip(spawned) {
+            for (name, value) in &entity.components {
+                let mut value = value.clone();
+                value.map_entities(&mut |other| {
+                    entities
+                        .get(&other.index)
+                        .copied()
+                        .ok_or(SceneError::UnknownEntity {
+                            entity: entity.id,
+                            component: name.clone(),
+                            reference: other.index,
+                        })
+                })?;
+
+                let component = world.type_registry().get_by_name(name).unwrap().component;
+                component
+                    .insert(world, target, &value)
+                    .map_err(|error| SceneError::Reflect {
+                        entity: entity.id,
+                        component: name.clone(),
+                        error,
+                    })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Entity references inside a scene use the scene number as their index.
+fn scene_entity(id: u32) -> EntityId {
+    EntityId {
+        index: id,
+        generation: 0,
+    }
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse(ParseError),
+    // The file parsed, but isn't laid out like a scene.
+    Format(String),
+    UnknownComponent {
+        entity: u32,
+        component: String,
+    },
+    // A `#n` reference to an entity the scene doesn't contain.
+    UnknownEntity {
+        entity: u32,
+        component: String,
+        reference: u32,
+    },
+    EntityOutsideScene {
+        component: String,
+        entity: EntityId,
+    },
+    Reflect {
+        entity: u32,
+        component: String,
+        error: ReflectError,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(error) => write!(f, "Could not access scene file: {}", error),
+            SceneError::Parse(error) => write!(f, "Could not parse scene: {}", error),
+            SceneError::Format(message) => write!(f, "Invalid scene: {}", message),
+            SceneError::UnknownComponent { entity, component } => write!(
+                f,
+                "Entity #{} has component '{}', which is not registered with World::register_type",
+                entity, component
+            ),
+            SceneError::UnknownEntity {
+                entity,
+                component,
+                reference,
+            } => write!(
+                f,
+                "Component '{}' of entity #{} refers to entity #{}, which is not in the scene",
+                component, entity, reference
+            ),
+            SceneError::EntityOutsideScene { component, entity } => write!(
+                f,
+                "A '{}' component refers to {:?}, which is not part of the saved entities",
+                component, entity
+            ),
+            SceneError::Reflect {
+                entity,
+                component,
+                error,
+            } => write!(
+                f,
+                "Component '{}' of entity #{} is invalid: {}",
+                component, entity, error
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::components::components::Component;
+    use crate::crowengine::components::transform::Transform;
+    use crate::crowengine::reflect::traits::Reflect;
+
+    #[derive(Reflect)]
+    struct Target(EntityId);
+    impl Component for Target {}
+
+    fn children(world: &World, parent: EntityId) -> Vec<EntityId> {
+        world
+            .get::<Children>(&parent)
+            .map_or_else(Vec::new, |children| children.iter().collect())
+    }
+
+    #[test]
+    fn hierarchy_is_rebuilt_when_spawning() {
+        let mut world = World::empty();
+        let ship = world.spawn(Transform::from_xyz(1.0, 0.0, 0.0));
+        let cannon = world.spawn_empty();
+        world.set_parent(cannon, ship);
+
+        let text = Scene::from_world(&world).unwrap().to_text();
+        assert!(text.contains("parent: #0"));
+
+        let mut loaded = World::empty();
+        let spawned = Scene::from_text(&text).unwrap().spawn(&mut loaded).unwrap();
+        assert_eq!(spawned.len(), 2);
+        assert_eq!(
+            loaded.get::<Parent>(&spawned[1]).map(Parent::get),
+            Some(spawned[0])
+        );
+        assert_eq!(children(&loaded, spawned[0]), vec![spawned[1]]);
+    }
+
+    #[test]
+    fn parents_outside_the_selection_are_rejected() {
+        let mut world = World::empty();
+        let ship = world.spawn(Transform::from_xyz(0.0, 0.0, 0.0));
+        let cannon = world.spawn(Transform::from_xyz(0.0, 0.0, 0.0));
+        world.set_parent(cannon, ship);
+
+        assert!(matches!(
+            Scene::from_entities(&world, &[cannon]),
+            Err(SceneError::EntityOutsideScene { .. })
+        ));
+    }
+
+    #[test]
+    fn broken_hierarchies_spawn_nothing() {
+        for text in [
+            "(entities: [(id: 0, parent: #4)])",
+            "(entities: [(id: 0, parent: #1), (id: 1, parent: #0)])",
+            "(entities: [(id: 0, parent: #0)])",
+        ] {
+            let mut world = World::empty();
+            let result = Scene::from_text(text).unwrap().spawn(&mut world);
+            assert!(matches!(result, Err(SceneError::Format(_))), "{}", text);
+            assert_eq!(world.query::<(EntityId,)>().count(), 0);
+        }
+    }
+
+    #[test]
+    fn saved_scenes_load_back() {
+        use crate::crowengine::components::use_shader::UseShader;
+
+        let mut world = World::empty();
+        let mut far_away = Transform::from_xyz(f32::INFINITY, -0.1, 3.5);
+        far_away.position.y = f32::NEG_INFINITY;
+        world.spawn((far_away, UseShader::new("\"quoted\"".to_string())));
+        world.spawn(Transform::from_xyz(f32::NAN, 0.0, 0.0));
+
+        let path = std::env::temp_dir().join(format!("scene_test_{}.scn", std::process::id()));
+        let saved = Scene::from_world(&world).unwrap();
+        saved.save(&path).unwrap();
+        let loaded = Scene::load(&path);
+        fs::remove_file(&path).unwrap();
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.to_text(), saved.to_text());
+
+        let mut world = World::empty();
+        let spawned = loaded.spawn(&mut world).unwrap();
+        let position = world.get::<Transform>(&spawned[0]).unwrap().position;
+        assert_eq!(
+            (position.x, position.y, position.z),
+            (f32::INFINITY, f32::NEG_INFINITY, 3.5)
+        );
+        assert_eq!(
+            world.get::<UseShader>(&spawned[0]).unwrap().shader_name,
+            "\"quoted\""
+        );
+        assert!(world
+            .get::<Transform>(&spawned[1])
+            .unwrap()
+            .position
+            .x
+            .is_nan());
+    }
+
+    #[test]
+    fn invalid_scenes_spawn_nothing() {
+        let mut world = World::empty();
+
+        let result = Scene::from_text("(entities: [(id: 0, components: (Bogus: ()))])")
+            .unwrap()
+            .spawn(&mut world);
+        assert!(matches!(
+            result,
+            Err(SceneError::UnknownComponent { entity: 0, ref component }) if component == "Bogus"
+        ));
+
+        let result = Scene::from_text(
+            "(entities: [(id: 0), (id: 1, components: (Transform: (position: 1)))])",
+        )
+        .unwrap()
+        .spawn(&mut world);
+        assert!(matches!(result, Err(SceneError::Reflect { entity: 1, .. })));
+
+        assert_eq!(world.query::<(EntityId,)>().count(), 0);
+        assert!(matches!(
+            Scene::from_text("(entities: [(id: 0), (id: 0)])"),
+            Err(SceneError::Format(_))
+        ));
+        assert!(matches!(
+            Scene::from_text("(entities: [(id: 0,"),
+            Err(SceneError::Parse(_))
+        ));
+    }
+
+    #[test]
+    fn entity_references_are_remapped() {
+        let mut world = World::empty();
+        world.register_type::<Target>();
+        world.spawn_empty();
+        let archer = world.spawn(Transform::from_xyz(0.0, 0.0, 0.0));
+        let scout = world.spawn(Target(archer));
+        let text = Scene::from_entities(&world, &[archer, scout])
+            .unwrap()
+            .to_text();
+
+        let mut loaded = World::empty();
+        loaded.register_type::<Target>();
+        let spawned = Scene::from_text(&text).unwrap().spawn(&mut loaded).unwrap();
+        assert_eq!(loaded.get::<Target>(&spawned[1]).unwrap().0, spawned[0]);
+
+        let result = Scene::from_text("(entities: [(id: 0, components: (Target: (0: #4)))])")
+            .unwrap()
+            .spawn(&mut loaded);
+        assert!(matches!(
+            result,
+            Err(SceneError::UnknownEntity {
+                entity: 0,
+                reference: 4,
+                ..
+            })
+        ));
+        assert_eq!(loaded.query::<(EntityId,)>().count(), 2);
+    }
+}
@@ -0,0 +1,412 @@
+use super::super::entity::EntityId;
+use super::super::reflect::value::Value;
+
+use std::fmt;
+
+// A RON-like text form of `Value`:
+//
+//     (
+//         name: "Warrior",
+//         position: (x: 1.0, y: 0.0, z: -2.5),
+//         children: [#1, #2],
+//         visible: true,
+//     )
+//
+// Structs use parentheses and may have numeric field names for tuple structs,
+// lists use brackets and `#n` refers to entity `n` of the same file. Floats
+// always have a `.` or an exponent so they read back as floats, except for
+// `inf`, `-inf` and `nan`. Trailing commas and `//` comments are allowed.
+pub fn write(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(value, &mut out, 0);
+    out.push('\n');
+    out
+}
+
+fn write_value(value: &Value, out: &mut String, indent: usize) {
+    match value {
+        Value::Bool(bool) => out.push_str(&bool.to_string()),
+        Value::Int(int) => out.push_str(&int.to_string()),
+        Value::Float(float) => write_float(*float, out),
+        Value::String(string) => write_string(string, out),
+        Value::Entity(entity) => out.push_str(&format!("#{}", entity.index)),
+        Value::List(items) => {
+            let items: Vec<(Option<&str>, &Value)> =
+                items.iter().map(|item| (None, item)).collect();
+            write_group(&items, ('[', ']'), out, indent);
+        }
+        Value::Struct(fields) => {
+            let fields: Vec<(Option<&str>, &Value)> = fields
+                .iter()
+                .map(|(name, value)| (Some(name.as_str()), value))
+                .collect();
+            write_group(&fields, ('(', ')'), out, indent);
+        }
+    }
+}
+
+// Groups of plain values stay on one line, anything nested gets a line per item.
+fn write_group(
+    items: &[(Option<&str>, &Value)],
+    (open, close): (char, char),
+    out: &mut String,
+    indent: usize,
+) {
+    let inline = items
+        .iter()
+        .all(|(_, value)| !matches!(value, Value::List(_) | Value::Struct(_)));
+
+    out.push(open);
+    for (index, (name, value)) in items.iter().enumerate() {
+        if inline {
+            if index > 0 {
+                out.push_str(", ");
+            }
+        } else {
+            out.push('\n');
+            out.push_str(&"    ".repeat(indent + 1));
+        }
+
+        if let Some(name) = name {
+            out.push_str(name);
+            out.push_str(": ");
+        }
+        write_value(value, out, indent + 1);
+
+        if !inline {
+            out.push(',');
+        }
+    }
+    if !inline {
+        out.push('\n');
+        out.push_str(&"    ".repeat(indent));
+    }
+    out.push(close);
+}
+
+// `{:?}` writes `NaN`, which would read back as an identifier.
+fn write_float(float: f64, out: &mut String) {
+    if float.is_nan() {
+        out.push_str("nan");
+    } else {
+        out.push_str(&format!("{:?}", float));
+    }
+}
+
+fn write_string(string: &str, out: &mut String) {
+    out.push('"');
+    for char in string.chars() {
+        match char {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            char => out.push(char),
+        }
+    }
+    out.push('"');
+}
+
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+pub fn parse(text: &str) -> Result<Value, ParseError> {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        position: 0,
+    };
+
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    if parser.peek().is_some() {
+        return Err(parser.error("Expected the end of the file"));
+    }
+    Ok(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn value(&mut self) -> Result<Value, ParseError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('(') => self.structure(),
+            Some('[') => self.list(),
+            Some('"') => self.string().map(Value::String),
+            Some('#') => {
+                self.position += 1;
+                let index = self.digits();
+                let index = index
+                    .parse()
+                    .map_err(|_| self.error("Expected an entity number after '#'"))?;
+                Ok(Value::Entity(EntityId {
+                    index,
+                    generation: 0,
+                }))
+            }
+            Some(char) if char == '-' || char.is_ascii_digit() => self.number(),
+            Some(char) if char.is_alphabetic() => match self.identifier().as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                "inf" => Ok(Value::Float(f64::INFINITY)),
+                "nan" => Ok(Value::Float(f64::NAN)),
+                other => Err(self.error(&format!("Unexpected '{}'", other))),
+            },
+            Some(char) => Err(self.error(&format!("Unexpected '{}'", char))),
+            None => Err(self.error("Unexpected end of file")),
+        }
+    }
+
+    fn structure(&mut self) -> Result<Value, ParseError> {
+        let mut fields = Vec::new();
+        self.group('(', ')', |parser| {
+            let name = match parser.peek() {
+                Some(char) if char.is_ascii_digit() => parser.digits(),
+                Some(char) if char.is_alphabetic() || char == '_' => parser.identifier(),
+                _ => return Err(parser.error("Expected a field name")),
+            };
+            parser.expect(':')?;
+            fields.push((name, parser.value()?));
+            Ok(())
+        })?;
+        Ok(Value::Struct(fields))
+    }
+
+    fn list(&mut self) -> Result<Value, ParseError> {
+        let mut items = Vec::new();
+        self.group('[', ']', |parser| {
+            items.push(parser.value()?);
+            Ok(())
+        })?;
+        Ok(Value::List(items))
+    }
+
+    // Comma-separated items between `open` and `close`, with an optional
+    // trailing comma.
+    fn group(
+        &mut self,
+        open: char,
+        close: char,
+        mut item: impl FnMut(&mut Self) -> Result<(), ParseError>,
+    ) -> Result<(), ParseError> {
+        self.expect(open)?;
+        loop {
+            self.skip_whitespace();
+            if self.peek() == Some(close) {
+                self.position += 1;
+                return Ok(());
+            }
+
+            item(self)?;
+
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => self.position += 1,
+                Some(char) if char == close => {}
+                _ => return Err(self.error(&format!("Expected ',' or '{}'", close))),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        self.expect('"')?;
+        let mut string = String::new();
+        loop {
+            let Some(char) = self.next() else {
+                return Err(self.error("Unterminated string"));
+            };
+            match char {
+                '"' => return Ok(string),
+                '\\' => match self.next() {
+                    Some('"') => string.push('"'),
+                    Some('\\') => string.push('\\'),
+                    Some('n') => string.push('\n'),
+                    Some('t') => string.push('\t'),
+                    _ => return Err(self.error("Unknown escape sequence")),
+                },
+                char => string.push(char),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, ParseError> {
+        let start = self.position;
+        if self.peek() == Some('-') {
+            self.position += 1;
+            if self.peek().is_some_and(char::is_alphabetic) {
+                return match self.identifier().as_str() {
+                    "inf" => Ok(Value::Float(f64::NEG_INFINITY)),
+                    other => Err(self.error(&format!("Invalid number '-{}'", other))),
+                };
+            }
+        }
+        self.digits();
+
+        let mut float = false;
+        if self.peek() == Some('.') {
+            float = true;
+            self.position += 1;
+            self.digits();
+        }
+        if let Some('e' | 'E') = self.peek() {
+            float = true;
+            self.position += 1;
+            if let Some('+' | '-') = self.peek() {
+                self.position += 1;
+            }
+            self.digits();
+        }
+
+        let number: String = self.chars[start..self.position].iter().collect();
+        let value = if float {
+            number.parse().map(Value::Float).ok()
+        } else {
+            number.parse().map(Value::Int).ok()
+        };
+        value.ok_or_else(|| self.error(&format!("Invalid number '{}'", number)))
+    }
+
+    fn digits(&mut self) -> String {
+        self.take_while(|char| char.is_ascii_digit())
+    }
+
+    fn identifier(&mut self) -> String {
+        self.take_while(|char| char.is_alphanumeric() || char == '_')
+    }
+
+    fn take_while(&mut self, f: impl Fn(char) -> bool) -> String {
+        let start = self.position;
+        while self.peek().is_some_and(&f) {
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), ParseError> {
+        self.skip_whitespace();
+        if self.peek() != Some(expected) {
+            return Err(self.error(&format!("Expected '{}'", expected)));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some(char) if char.is_whitespace() => self.position += 1,
+                Some('/') if self.chars.get(self.position + 1) == Some(&'/') => {
+                    while self.peek().is_some_and(|char| char != '\n') {
+                        self.position += 1;
+                    }
+                }
+                _ => return,
+            }
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let char = self.peek()?;
+        self.position += 1;
+        Some(char)
+    }
+
+    fn error(&self, message: &str) -> ParseError {
+        let before = &self.chars[..self.position.min(self.chars.len())];
+        let line = before.iter().filter(|&&char| char == '\n').count() + 1;
+        let column = before
+            .iter()
+            .rev()
+            .take_while(|&&char| char != '\n')
+            .count()
+            + 1;
+        ParseError {
+            line,
+            column,
+            message: message.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn float(value: &Value) -> f64 {
+        match value {
+            Value::Float(float) => *float,
+            other => panic!("Expected a float, got {:?}!", other),
+        }
+    }
+
+    #[test]
+    fn values_read_back_as_written() {
+        let value = Value::Struct(vec![
+            (
+                "name".to_string(),
+                Value::String("\"Red\" Baron\n".to_string()),
+            ),
+            ("turn".to_string(), Value::Int(-12)),
+            ("speed".to_string(), Value::Float(1.0)),
+            ("visible".to_string(), Value::Bool(true)),
+            (
+                "0".to_string(),
+                Value::List(vec![Value::Entity(EntityId {
+                    index: 3,
+                    generation: 0,
+                })]),
+            ),
+        ]);
+
+        assert_eq!(parse(&write(&value)).unwrap(), value);
+    }
+
+    #[test]
+    fn non_finite_floats_read_back_as_written() {
+        let value = Value::List(vec![
+            Value::Float(f64::INFINITY),
+            Value::Float(f64::NEG_INFINITY),
+            Value::Float(f64::NAN),
+        ]);
+        let text = write(&value);
+        assert_eq!(text, "[inf, -inf, nan]\n");
+
+        let Value::List(items) = parse(&text).unwrap() else {
+            panic!("Expected a list!");
+        };
+        assert_eq!(float(&items[0]), f64::INFINITY);
+        assert_eq!(float(&items[1]), f64::NEG_INFINITY);
+        assert!(float(&items[2]).is_nan());
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        let error = parse("(\n  speed: ?,\n)").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 10));
+        assert_eq!(error.message, "Unexpected '?'");
+
+        assert!(parse("-fast").is_err());
+
+        let error = parse("[1, 2").unwrap_err();
+        assert_eq!(error.message, "Expected ',' or ']'");
+    }
+}
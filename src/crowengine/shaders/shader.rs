@@ -1,101 +1,103 @@
-extern crate gl;
 use nalgebra_glm::{Mat4, Vec3};
 
+use crate::crowengine::backends::render_backend::{RenderId, Renderer};
+use crate::crowengine::resources::Resource;
+
+use std::collections::HashMap;
+
+// The sources of a shader program, compiled by the backend the first time the
+// program is used.
 pub struct Shader {
-    shader_program: u32,
+    vertex_shader_source: String,
+    fragment_shader_source: String,
+    id: RenderId,
 }
 
 impl Shader {
     pub fn new(vertex_shader_source: &str, fragment_shader_source: &str) -> Self {
-        let vertex_shader = unsafe { gl::CreateShader(gl::VERTEX_SHADER) };
-        unsafe {
-            gl::ShaderSource(
-                vertex_shader,
-                1,
-                &vertex_shader_source.as_bytes().as_ptr().cast(),
-                &(vertex_shader_source.len() as i32),
-            );
-            gl::CompileShader(vertex_shader);
-        }
-
-        let fragment_shader = unsafe { gl::CreateShader(gl::FRAGMENT_SHADER) };
-        unsafe {
-            gl::ShaderSource(
-                fragment_shader,
-                1,
-                &fragment_shader_source.as_bytes().as_ptr().cast(),
-                &(fragment_shader_source.len() as i32),
-            );
-            gl::CompileShader(fragment_shader);
+        Self {
+            vertex_shader_source: vertex_shader_source.to_string(),
+            fragment_shader_source: fragment_shader_source.to_string(),
+            id: RenderId::new(),
         }
+    }
 
-        let shader_program = unsafe { gl::CreateProgram() };
-        unsafe {
-            gl::AttachShader(shader_program, vertex_shader);
-            gl::AttachShader(shader_program, fragment_shader);
-            gl::LinkProgram(shader_program);
+    pub fn program(&self, renderer: &mut Renderer) -> u32 {
+        renderer.program(
+            self.id,
+            &self.vertex_shader_source,
+            &self.fragment_shader_source,
+        )
+    }
 
-            gl::DetachShader(shader_program, vertex_shader);
-            gl::DetachShader(shader_program, fragment_shader);
-            gl::DeleteShader(vertex_shader);
-            gl::DeleteShader(fragment_shader);
-        }
+    pub fn set_color_uniform(&self, renderer: &mut Renderer, color: &Vec3) {
+        let program = self.program(renderer);
+        renderer
+            .backend_mut()
+            .set_uniform_vec3(program, "color", color);
+    }
 
-        Shader { shader_program }
+    pub fn set_projection_uniform(&self, renderer: &mut Renderer, projection: &Mat4) {
+        let program = self.program(renderer);
+        renderer
+            .backend_mut()
+            .set_uniform_mat4(program, "projection", projection);
     }
 
-    pub fn set_color_uniform(&self, color: &Vec3) {
-        unsafe {
-            let location =
-                gl::GetUniformLocation(self.shader_program, b"color\0".as_ptr() as *const _);
+    pub fn set_model_uniform(&self, renderer: &mut Renderer, model: &Mat4) {
+        let program = self.program(renderer);
+        renderer
+            .backend_mut()
+            .set_uniform_mat4(program, "model", model);
+    }
 
-            if location != -1 {
-                gl::Uniform3f(location, color.x, color.y, color.z);
-            } else {
-                eprintln!("Uniform 'color' not found!");
-            }
-        }
+    pub fn set_view_uniform(&self, renderer: &mut Renderer, view: &Mat4) {
+        let program = self.program(renderer);
+        renderer
+            .backend_mut()
+            .set_uniform_mat4(program, "view", view);
     }
 
-    pub fn set_projection_uniform(&self, projection: &Mat4) {
-        unsafe {
-            let location =
-                gl::GetUniformLocation(self.shader_program, b"projection\0".as_ptr() as *const _);
+    pub fn set_texture_uniform(&self, renderer: &mut Renderer, name: &str, slot: u32) {
+        let program = self.program(renderer);
+        renderer
+            .backend_mut()
+            .set_uniform_int(program, name, slot as i32);
+    }
 
-            gl::UniformMatrix4fv(location, 1, gl::FALSE, projection.as_ptr());
-        }
+    pub fn use_program(&self, renderer: &mut Renderer) {
+        let program = self.program(renderer);
+        renderer.backend_mut().use_program(program);
     }
+}
+
+// Every compiled shader program, looked up by the name `UseShader` refers to.
+pub struct Shaders {
+    shaders: HashMap<String, Shader>,
+}
 
-    pub fn set_model_uniform(&self, model: &Mat4) {
-        unsafe {
-            let location =
-                gl::GetUniformLocation(self.shader_program, b"model\0".as_ptr() as *const _);
+impl Resource for Shaders {}
 
-            gl::UniformMatrix4fv(location, 1, gl::FALSE, model.as_ptr());
+impl Shaders {
+    pub fn new() -> Self {
+        Self {
+            shaders: HashMap::new(),
         }
     }
-    pub fn set_view_uniform(&self, view: &Mat4) {
-        unsafe {
-            let location =
-                gl::GetUniformLocation(self.shader_program, b"view\0".as_ptr() as *const _);
-            gl::UniformMatrix4fv(location, 1, gl::FALSE, view.as_ptr());
-        }
+
+    pub fn insert(&mut self, name: String, shader: Shader) {
+        self.shaders.insert(name, shader);
     }
 
-    pub fn set_texture_uniform(&self, name: &str, slot: u32) {
-        unsafe {
-            let cname = std::ffi::CString::new(name).unwrap();
-            let location = gl::GetUniformLocation(self.shader_program, cname.as_ptr());
-            if location != -1 {
-                gl::Uniform1i(location, slot as i32);
-            } else {
-                eprintln!("Uniform '{}' not found!", name);
-            }
-        }
+    pub fn get(&self, name: &str) -> Option<&Shader> {
+        self.shaders.get(name)
     }
-    pub fn use_program(&self) {
-        unsafe {
-            gl::UseProgram(self.shader_program);
-        }
+
+    // Also deletes the compiled program, if there is one.
+    #[allow(dead_code)]
+    pub fn remove(&mut self, renderer: &mut Renderer, name: &str) -> Option<Shader> {
+        let shader = self.shaders.remove(name)?;
+        renderer.free_program(shader.id);
+        Some(shader)
     }
 }
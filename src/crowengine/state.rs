@@ -0,0 +1,365 @@
+use super::components::components::Component;
+use super::entity::EntityId;
+use super::resources::Resource;
+use super::systems::schedule::{IntoSystemConfig, Schedule, Stage};
+use super::world::World;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+
+// A set of mutually exclusive states, usually a fieldless enum like
+// `enum GameState { MainMenu, MapGeneration, Playing, GameOver }`.
+pub trait States: Copy + Eq + Hash + Debug + Send + Sync + 'static {}
+
+// The current state. `set` only queues the transition, which is applied at the
+// start of the next frame by `apply_state_transition`, so every system in a
+// frame sees the same state.
+pub struct State<S: States> {
+    current: S,
+    next: Option<S>,
+}
+
+impl<S: States> Resource for State<S> {}
+
+#[allow(dead_code)]
+impl<S: States> State<S> {
+    pub fn new(initial: S) -> Self {
+        Self {
+            current: initial,
+            next: None,
+        }
+    }
+
+    pub fn get(&self) -> S {
+        self.current
+    }
+
+    pub fn set(&mut self, next: S) {
+        self.next = Some(next);
+    }
+
+    pub fn next(&self) -> Option<S> {
+        self.next
+    }
+}
+
+// Schedules that run once when a state is entered or left.
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub struct OnEnter<S: States>(pub S);
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub struct OnExit<S: States>(pub S);
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Transition {
+    Enter,
+    Exit,
+}
+
+pub trait StateSchedule<S: States> {
+    fn key(self) -> (S, Transition);
+}
+
+impl<S: States> StateSchedule<S> for OnEnter<S> {
+    fn key(self) -> (S, Transition) {
+        (self.0, Transition::Enter)
+    }
+}
+
+impl<S: States> StateSchedule<S> for OnExit<S> {
+    fn key(self) -> (S, Transition) {
+        (self.0, Transition::Exit)
+    }
+}
+
+// Despawned, together with its children, when the world leaves the state.
+pub struct StateScoped<S: States>(pub S);
+
+impl<S: States> Component for StateScoped<S> {}
+
+// The `OnEnter`/`OnExit` schedules of `S`. Schedules aren't `Sync`, so each
+// one sits behind a mutex, which is only ever accessed through `get_mut`.
+pub struct StateSchedules<S: States> {
+    schedules: HashMap<(S, Transition), Mutex<Schedule>>,
+    // Schedules taken out to run, see `run_state_schedule`.
+    running: HashSet<(S, Transition)>,
+    entered: bool,
+}
+
+impl<S: States> Resource for StateSchedules<S> {}
+
+#[allow(dead_code)]
+impl<S: States> StateSchedules<S> {
+    pub fn new() -> Self {
+        Self {
+            schedules: HashMap::new(),
+            running: HashSet::new(),
+            entered: false,
+        }
+    }
+
+    // State schedules only have the one stage, so their systems are ordered
+    // with labels like startup systems.
+    pub fn add_system<M>(
+        &mut self,
+        schedule: impl StateSchedule<S>,
+        system: impl IntoSystemConfig<M>,
+    ) -> &mut Self {
+        let key = schedule.key();
+        if self.running.contains(&key) {
+            panic!("Cannot add systems to {:?} while it runs!", key);
+        }
+
+        self.schedules
+            .entry(key)
+            .or_insert_with(|| Mutex::new(Schedule::new()))
+            .get_mut()
+            .unwrap()
+            .add_system(Stage::Update, system);
+        self
+    }
+}
+
+// Inserts the state, its schedules and the system applying its transitions.
+// `OnEnter(initial)` runs the first time the schedule does.
+#[allow(dead_code)]
+pub fn add_state<S: States>(world: &mut World, schedule: &mut Schedule, initial: S) {
+    if world.contains_resource::<State<S>>() {
+        panic!("State {} was already added!", std::any::type_name::<S>());
+    }
+
+    world.insert_resource(State::new(initial));
+    world.insert_resource(StateSchedules::<S>::new());
+    schedule.add_system(Stage::StateTransition, apply_state_transition::<S>);
+}
+
+// Runs `OnExit` of the current state, despawns the entities scoped to it,
+// switches to the queued state and runs its `OnEnter`. Transitions to the
+// current state are ignored.
+pub fn apply_state_transition<S: States>(world: &mut World) {
+    let schedules = world.resource_mut::<StateSchedules<S>>();
+    if !schedules.entered {
+        schedules.entered = true;
+        let current = world.resource::<State<S>>().get();
+        run_state_schedule(world, (current, Transition::Enter));
+    }
+
+    let state = world.resource_mut::<State<S>>();
+    let Some(next) = state.next.take() else {
+        return;
+    };
+    let current = state.current;
+    if next == current {
+        return;
+    }
+
+    run_state_schedule(world, (current, Transition::Exit));
+    despawn_scoped(world, current);
+
+    world.resource_mut::<State<S>>().current = next;
+    run_state_schedule(world, (next, Transition::Enter));
+}
+
+// The schedule is taken out of `StateSchedules` while it runs, so its systems
+// can use the resource too, and put back even if one of them panics.
+fn run_state_schedule<S: States>(world: &mut World, key: (S, Transition)) {
+    let schedules = world.resource_mut::<StateSchedules<S>>();
+    let Some(mut schedule) = schedules.schedules.remove(&key) else {
+        return;
+    };
+    schedules.running.insert(key);
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        schedule.get_mut().unwrap().run(world);
+    }));
+
+    let schedules = world.resource_mut::<StateSchedules<S>>();
+    schedules.running.remove(&key);
+    schedules.schedules.insert(key, schedule);
+    if let Err(payload) = result {
+        panic::resume_unwind(payload);
+    }
+}
+
+fn despawn_scoped<S: States>(world: &mut World, state: S) {
+    let scoped: Vec<EntityId> = world
+        .query::<(EntityId, &StateScoped<S>)>()
+        .filter(|(_, scoped)| scoped.0 == state)
+        .map(|(entity, _)| entity)
+        .collect();
+
+    for entity in scoped {
+        world.despawn_recursive(entity);
+    }
+}
+
+// A run condition for systems that only run in `state`.
+#[allow(dead_code)]
+pub fn in_state<S: States>(state: S) -> impl Fn(&World) -> bool + Send + Sync + 'static {
+    move |world| {
+        world
+            .get_resource::<State<S>>()
+            .is_some_and(|current| current.get() == state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::app::App;
+    use crate::crowengine::systems::system_param::{Query, ResMut};
+    use std::sync::Arc;
+
+    #[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+    enum GameState {
+        MainMenu,
+        Playing,
+    }
+
+    impl States for GameState {}
+
+    struct Button;
+    impl Component for Button {}
+
+    type Log = Arc<Mutex<Vec<&'static str>>>;
+
+    fn logger(log: &Log, message: &'static str) -> impl FnMut(&mut World) + Send + 'static {
+        let log = log.clone();
+        move |_| log.lock().unwrap().push(message)
+    }
+
+    fn state(app: &App) -> GameState {
+        app.world.resource::<State<GameState>>().get()
+    }
+
+    #[test]
+    fn transitions_run_exit_and_enter_schedules() {
+        let log = Log::default();
+        let mut app = App::new();
+        app.add_state(GameState::MainMenu)
+            .add_state_system(OnEnter(GameState::MainMenu), logger(&log, "enter menu"))
+            .add_state_system(OnExit(GameState::MainMenu), logger(&log, "exit menu"))
+            .add_state_system(OnEnter(GameState::Playing), logger(&log, "enter playing"));
+
+        app.update();
+        app.update();
+        assert_eq!(*log.lock().unwrap(), ["enter menu"]);
+
+        app.world
+            .resource_mut::<State<GameState>>()
+            .set(GameState::Playing);
+        assert_eq!(state(&app), GameState::MainMenu);
+        app.update();
+        assert_eq!(state(&app), GameState::Playing);
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["enter menu", "exit menu", "enter playing"]
+        );
+
+        app.world
+            .resource_mut::<State<GameState>>()
+            .set(GameState::Playing);
+        app.update();
+        assert_eq!(log.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn scoped_entities_are_despawned_with_their_children() {
+        let mut app = App::new();
+        app.add_state(GameState::MainMenu)
+            .add_state_system(OnEnter(GameState::MainMenu), |world: &mut World| {
+                let menu = world.spawn((StateScoped(GameState::MainMenu), Button));
+                let quit = world.spawn(Button);
+                world.set_parent(quit, menu);
+            })
+            .add_state_system(OnEnter(GameState::Playing), |world: &mut World| {
+                world.spawn((StateScoped(GameState::Playing), Button));
+            });
+
+        app.update();
+        assert_eq!(app.world.query::<(&Button,)>().count(), 2);
+
+        app.world
+            .resource_mut::<State<GameState>>()
+            .set(GameState::Playing);
+        app.update();
+        assert_eq!(app.world.query::<(&Button,)>().count(), 1);
+    }
+
+    #[test]
+    fn every_stage_sees_the_state_of_the_frame() {
+        let log = Log::default();
+        let (pre_update, update) = (log.clone(), log.clone());
+        let mut app = App::new();
+        app.add_state(GameState::MainMenu)
+            .add_system(
+                Stage::PreUpdate,
+                (move |_: Query<(&Button,)>| pre_update.lock().unwrap().push("pre update"))
+                    .run_if(in_state(GameState::Playing)),
+            )
+            .add_system(Stage::Update, |mut state: ResMut<State<GameState>>| {
+                state.set(GameState::Playing)
+            })
+            .add_system(
+                Stage::Update,
+                (move |_: Query<(&Button,)>| update.lock().unwrap().push("update"))
+                    .run_if(in_state(GameState::Playing)),
+            );
+
+        app.update();
+        assert!(log.lock().unwrap().is_empty());
+        app.update();
+        assert_eq!(*log.lock().unwrap(), ["pre update", "update"]);
+    }
+
+    #[test]
+    fn state_systems_can_use_the_state_schedules() {
+        let log = Log::default();
+        let menu_log = log.clone();
+        let mut app = App::new();
+        app.add_state(GameState::MainMenu).add_state_system(
+            OnEnter(GameState::MainMenu),
+            move |world: &mut World| {
+                world
+                    .resource_mut::<StateSchedules<GameState>>()
+                    .add_system(
+                        OnEnter(GameState::Playing),
+                        logger(&menu_log, "enter playing"),
+                    );
+            },
+        );
+
+        app.update();
+        app.world
+            .resource_mut::<State<GameState>>()
+            .set(GameState::Playing);
+        app.update();
+        assert_eq!(*log.lock().unwrap(), ["enter playing"]);
+    }
+
+    #[test]
+    fn panicking_state_systems_keep_their_schedule() {
+        let log = Log::default();
+        let mut app = App::new();
+        app.add_state(GameState::MainMenu)
+            .add_state_system(OnEnter(GameState::MainMenu), |_: &mut World| {
+                panic!("Menu failed to load!")
+            })
+            .add_state_system(OnEnter(GameState::Playing), logger(&log, "enter playing"));
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| app.update()));
+        assert!(result.is_err());
+
+        app.world
+            .resource_mut::<State<GameState>>()
+            .set(GameState::Playing);
+        app.update();
+        assert_eq!(*log.lock().unwrap(), ["enter playing"]);
+    }
+}
@@ -0,0 +1,245 @@
+use super::super::world::World;
+use super::schedule::Condition;
+use super::system::System;
+
+// How the systems of a stage are run. The single-threaded executor runs them
+// one by one in their sorted order, which makes it the one to use when
+// debugging or when a test needs reproducible entity ids from `Commands`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum ExecutorKind {
+    #[default]
+    MultiThreaded,
+    #[allow(dead_code)]
+    SingleThreaded,
+}
+
+fn conflicts(a: &dyn System, b: &dyn System) -> bool {
+    a.is_exclusive() || b.is_exclusive() || !a.access().is_compatible(b.access())
+}
+
+// Groups the stage's systems into batches that can each run in parallel. A
+// system goes into the batch after the last one holding a system it depends on
+// or conflicts with, so conflicting systems keep their sorted order and both
+// executors produce the same result.
+pub(super) fn batches(
+    order: &[usize],
+    dependencies: &[Vec<usize>],
+    systems: &[&mut dyn System],
+) -> Vec<Vec<usize>> {
+    let mut batch_of = vec![0; systems.len()];
+    let mut batches: Vec<Vec<usize>> = Vec::new();
+
+    for (position, &index) in order.iter().enumerate() {
+        let batch = order[..position]
+            .iter()
+            .filter(|&&earlier| {
+                dependencies[index].contains(&earlier)
+                    || conflicts(&*systems[earlier], &*systems[index])
+            })
+            .map(|&earlier| batch_of[earlier] + 1)
+            .max()
+            .unwrap_or(0);
+
+        if batch == batches.len() {
+            batches.push(Vec::new());
+        }
+        batches[batch].push(index);
+        batch_of[index] = batch;
+    }
+
+    batches
+}
+
+fn should_run(conditions: &[Condition], world: &World) -> bool {
+    conditions.iter().all(|condition| condition(world))
+}
+
+pub(super) fn run_single_threaded(
+    order: &[usize],
+    systems: &mut [&mut dyn System],
+    conditions: &[&[Condition]],
+    world: &mut World,
+) {
+    for &index in order {
+        if should_run(conditions[index], world) {
+            run_system(&mut *systems[index], world);
+        }
+    }
+}
+
+// Conditions of a batch are checked before any of its systems start, since a
+// system running alongside could be writing what a condition reads.
+pub(super) fn run_multi_threaded(
+    batches: &[Vec<usize>],
+    systems: &mut [&mut dyn System],
+    conditions: &[&[Condition]],
+    world: &mut World,
+) {
+    for batch in batches {
+        let batch: Vec<usize> = batch
+            .iter()
+            .copied()
+            .filter(|&index| should_run(conditions[index], world))
+            .collect();
+
+        if let [index] = batch[..] {
+            run_system(&mut *systems[index], world);
+            continue;
+        }
+
+        let world: &World = world;
+        rayon::scope(|scope| {
+            for (_, system) in systems
+                .iter_mut()
+                .enumerate()
+                .filter(|(index, _)| batch.contains(index))
+            {
+                // Safety: systems in a batch have compatible access and none of
+                // them is exclusive.
+                scope.spawn(move |_| unsafe { system.run_unsafe(world) });
+            }
+        });
+    }
+}
+
+fn run_system(system: &mut dyn System, world: &mut World) {
+    if system.is_exclusive() {
+        system.run(world);
+    } else {
+        // Safety: the world is borrowed mutably, so nothing else runs alongside.
+        unsafe { system.run_unsafe(world) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::schedule::{Schedule, Stage};
+    use super::*;
+    use crate::crowengine::commands::Commands;
+    use crate::crowengine::components::change_detection::Changed;
+    use crate::crowengine::components::components::Component;
+    use crate::crowengine::systems::system_param::Query;
+    use std::sync::mpsc::{self, Receiver, Sender};
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    #[derive(Debug, PartialEq)]
+    struct Gold(u32);
+    impl Component for Gold {}
+
+    #[derive(Debug, PartialEq)]
+    struct Food(u32);
+    impl Component for Food {}
+
+    struct Report(u32);
+    impl Component for Report {}
+
+    // Each system waits for the other one to start, so they only both finish
+    // if they run at the same time.
+    #[test]
+    fn compatible_systems_run_at_the_same_time() {
+        fn meet(
+            send: Sender<()>,
+            receive: Receiver<()>,
+            met: Arc<Mutex<u32>>,
+        ) -> impl FnMut(Query<(&Gold,)>) + Send + 'static {
+            let (send, receive) = (Mutex::new(send), Mutex::new(receive));
+            move |_| {
+                send.lock().unwrap().send(()).unwrap();
+                let receive = receive.lock().unwrap();
+                if receive.recv_timeout(Duration::from_secs(5)).is_ok() {
+                    *met.lock().unwrap() += 1;
+                }
+            }
+        }
+
+        let (send_a, receive_a) = mpsc::channel();
+        let (send_b, receive_b) = mpsc::channel();
+        let met = Arc::new(Mutex::new(0));
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Update, meet(send_a, receive_b, met.clone()))
+            .add_system(Stage::Update, meet(send_b, receive_a, met.clone()));
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        pool.install(|| schedule.run(&mut World::empty()));
+        assert_eq!(*met.lock().unwrap(), 2);
+    }
+
+    #[test]
+    fn both_executors_give_the_same_result() {
+        fn harvest(mut cities: Query<(&mut Food,)>) {
+            for (food,) in cities.iter_mut() {
+                food.0 += 1;
+            }
+        }
+
+        fn feast(mut cities: Query<(&mut Food,)>) {
+            for (food,) in cities.iter_mut() {
+                food.0 *= 2;
+            }
+        }
+
+        fn sell(mut cities: Query<(&Food, &mut Gold)>) {
+            for (food, gold) in cities.iter_mut() {
+                gold.0 = food.0;
+            }
+        }
+
+        fn report(cities: Query<(&Gold,), Changed<Food>>, mut commands: Commands) {
+            commands.spawn((Report(cities.iter().count() as u32),));
+        }
+
+        let run = |executor| {
+            let mut world = World::empty();
+            for i in 0..50 {
+                world.spawn((Food(i), Gold(0)));
+            }
+            let mut schedule = Schedule::new();
+            schedule
+                .set_executor(executor)
+                .add_system(Stage::Update, harvest)
+                .add_system(Stage::Update, feast)
+                .add_system(Stage::Update, sell)
+                .add_system(Stage::Update, report);
+            schedule.run(&mut world);
+            schedule.run(&mut world);
+
+            let gold: Vec<u32> = world.query::<(&Gold,)>().map(|(gold,)| gold.0).collect();
+            let reports: Vec<u32> = world
+                .query::<(&Report,)>()
+                .map(|(report,)| report.0)
+                .collect();
+            (gold, reports)
+        };
+
+        let (gold, reports) = run(ExecutorKind::SingleThreaded);
+        assert_eq!(gold[0], 6);
+        assert_eq!(reports, [50, 50]);
+        assert_eq!(run(ExecutorKind::MultiThreaded), (gold, reports));
+    }
+
+    #[test]
+    fn exclusive_systems_run_on_the_calling_thread() {
+        let threads = Arc::new(Mutex::new(Vec::new()));
+        let (first, second) = (threads.clone(), threads.clone());
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Update, |_: Query<(&Gold,)>| {})
+            .add_system(Stage::Update, |_: Query<(&Food,)>| {})
+            .add_system(Stage::Update, move |_: &mut World| {
+                first.lock().unwrap().push(thread::current().id())
+            })
+            .add_system(Stage::Render, move |_: &mut World| {
+                second.lock().unwrap().push(thread::current().id())
+            });
+
+        schedule.run(&mut World::empty());
+        let current = thread::current().id();
+        assert_eq!(*threads.lock().unwrap(), [current, current]);
+    }
+}
@@ -0,0 +1,4 @@
+pub mod executor;
+pub mod schedule;
+pub mod system;
+pub mod system_param;
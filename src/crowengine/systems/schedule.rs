@@ -0,0 +1,490 @@
+use super::super::world::World;
+use super::executor::{self, ExecutorKind};
+use super::system::{BoxedSystem, IntoSystem, System};
+
+use std::collections::BTreeSet;
+use std::fmt;
+
+// Stages run in declaration order. Commands queued by the systems of a stage
+// are applied before the next stage starts. `StateTransition` applies queued
+// state changes before anything else runs, so every system of a frame sees the
+// same states. `RunFixedUpdate` only runs the `FixedUpdate` schedule, after
+// input is read and before the frame's own update; add fixed systems with
+// `App::add_fixed_system` instead.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Stage {
+    StateTransition,
+    PreUpdate,
+    RunFixedUpdate,
+    Update,
+    PostUpdate,
+    Render,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::StateTransition,
+        Stage::PreUpdate,
+        Stage::RunFixedUpdate,
+        Stage::Update,
+        Stage::PostUpdate,
+        Stage::Render,
+    ];
+}
+
+// Decides whether a system runs this frame. Conditions only get shared access
+// to the world and are checked right before the system would run.
+pub type Condition = Box<dyn Fn(&World) -> bool + Send + Sync>;
+
+// A system together with its labels, ordering constraints and run conditions.
+// Every system is implicitly labelled with its function name.
+pub struct SystemConfig {
+    system: BoxedSystem,
+    labels: Vec<String>,
+    before: Vec<String>,
+    after: Vec<String>,
+    conditions: Vec<Condition>,
+}
+
+pub trait IntoSystemConfig<Marker>: Sized {
+    fn into_config(self) -> SystemConfig;
+
+    #[allow(dead_code)]
+    fn label(self, label: &str) -> SystemConfig {
+        let mut config = self.into_config();
+        config.labels.push(label.to_string());
+        config
+    }
+
+    // Runs this system before every system with `label` in the same stage.
+    fn before(self, label: &str) -> SystemConfig {
+        let mut config = self.into_config();
+        config.before.push(label.to_string());
+        config
+    }
+
+    // Runs this system after every system with `label` in the same stage.
+    #[allow(dead_code)]
+    fn after(self, label: &str) -> SystemConfig {
+        let mut config = self.into_config();
+        config.after.push(label.to_string());
+        config
+    }
+
+    // Skips the system whenever `condition` is false, e.g. `in_state(..)`.
+    // A system with several conditions only runs if all of them hold.
+    #[allow(dead_code)]
+    fn run_if(self, condition: impl Fn(&World) -> bool + Send + Sync + 'static) -> SystemConfig {
+        let mut config = self.into_config();
+        config.conditions.push(Box::new(condition));
+        config
+    }
+}
+
+impl IntoSystemConfig<()> for SystemConfig {
+    fn into_config(self) -> SystemConfig {
+        self
+    }
+}
+
+impl<Marker, S: IntoSystem<Marker>> IntoSystemConfig<Marker> for S {
+    fn into_config(self) -> SystemConfig {
+        let system = Box::new(self.into_system());
+        SystemConfig {
+            labels: vec![system.name().to_string()],
+            system,
+            before: Vec::new(),
+            after: Vec::new(),
+            conditions: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleError {
+    // `before`/`after` named a label no system in the stage carries.
+    UnknownLabel {
+        stage: Stage,
+        system: String,
+        label: String,
+    },
+    // The constraints between these systems can't all be satisfied.
+    Cycle {
+        stage: Stage,
+        systems: Vec<String>,
+    },
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::UnknownLabel {
+                stage,
+                system,
+                label,
+            } => write!(
+                f,
+                "System '{}' is ordered against unknown label '{}' in stage {:?}",
+                system, label, stage
+            ),
+            ScheduleError::Cycle { stage, systems } => write!(
+                f,
+                "Systems [{}] in stage {:?} have cyclic ordering constraints",
+                systems.join(", "),
+                stage
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+#[derive(Default)]
+struct SystemStage {
+    systems: Vec<SystemConfig>,
+    // Indices into `systems` in run order, and the systems each one has to wait
+    // for. Both are rebuilt whenever a system is added.
+    order: Option<Vec<usize>>,
+    dependencies: Vec<Vec<usize>>,
+    batches: Option<Vec<Vec<usize>>>,
+}
+
+impl SystemStage {
+    fn add(&mut self, config: SystemConfig) {
+        self.systems.push(config);
+        self.order = None;
+        self.batches = None;
+    }
+
+    fn build(&mut self, stage: Stage) -> Result<&[usize], ScheduleError> {
+        if self.order.is_none() {
+            let (order, dependencies) = self.sort(stage)?;
+            self.order = Some(order);
+            self.dependencies = dependencies;
+        }
+        Ok(self.order.as_deref().unwrap())
+    }
+
+    // Kahn's algorithm. Among the systems that are free to run, the one added
+    // first goes first, so unconstrained systems keep their insertion order.
+    fn sort(&self, stage: Stage) -> Result<(Vec<usize>, Vec<Vec<usize>>), ScheduleError> {
+        let count = self.systems.len();
+        let mut successors = vec![Vec::new(); count];
+        let mut dependencies = vec![Vec::new(); count];
+        let mut predecessors = vec![0usize; count];
+
+        let labelled = |label: &str| -> Vec<usize> {
+            (0..count)
+                .filter(|&index| self.systems[index].labels.iter().any(|l| l == label))
+                .collect()
+        };
+
+        for (index, config) in self.systems.iter().enumerate() {
+            let edges = config
+                .before
+                .iter()
+                .map(|label| (label, true))
+                .chain(config.after.iter().map(|label| (label, false)));
+
+            for (label, before) in edges {
+                let others = labelled(label);
+                if others.is_empty() {
+                    return Err(ScheduleError::UnknownLabel {
+                        stage,
+                        system: config.system.name().to_string(),
+                        label: label.clone(),
+                    });
+                }
+
+                for other in others.into_iter().filter(|&other| other != index) {
+                    let (first, second) = if before {
+                        (index, other)
+                    } else {
+                        (other, index)
+                    };
+                    successors[first].push(second);
+                    dependencies[second].push(first);
+                    predecessors[second] += 1;
+                }
+            }
+        }
+
+        let mut ready: BTreeSet<usize> = (0..count)
+            .filter(|&index| predecessors[index] == 0)
+            .collect();
+        let mut order = Vec::with_capacity(count);
+
+        while let Some(index) = ready.pop_first() {
+            order.push(index);
+            for &next in &successors[index] {
+                predecessors[next] -= 1;
+                if predecessors[next] == 0 {
+                    ready.insert(next);
+                }
+            }
+        }
+
+        if order.len() != count {
+            let systems = (0..count)
+                .filter(|&index| predecessors[index] > 0)
+                .map(|index| self.systems[index].system.name().to_string())
+                .collect();
+            return Err(ScheduleError::Cycle { stage, systems });
+        }
+
+        Ok((order, dependencies))
+    }
+
+    fn run(&mut self, stage: Stage, executor: ExecutorKind, world: &mut World) {
+        let order = match self.build(stage) {
+            Ok(order) => order.to_vec(),
+            Err(error) => panic!("{}!", error),
+        };
+
+        // Access is only known once every system has been initialized.
+        for config in self.systems.iter_mut() {
+            config.system.initialize(world);
+        }
+
+        let (mut systems, conditions): (Vec<&mut dyn System>, Vec<&[Condition]>) = self
+            .systems
+            .iter_mut()
+            .map(|config| (config.system.as_mut(), &config.conditions[..]))
+            .unzip();
+
+        match executor {
+            ExecutorKind::SingleThreaded => {
+                executor::run_single_threaded(&order, &mut systems, &conditions, world);
+            }
+            ExecutorKind::MultiThreaded => {
+                let batches = self
+                    .batches
+                    .get_or_insert_with(|| executor::batches(&order, &self.dependencies, &systems));
+                executor::run_multi_threaded(batches, &mut systems, &conditions, world);
+            }
+        }
+
+        for &index in &order {
+            self.systems[index].system.apply_deferred(world);
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Schedule {
+    stages: [SystemStage; Stage::ALL.len()],
+    executor: ExecutorKind,
+}
+
+impl Schedule {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_system<M>(&mut self, stage: Stage, system: impl IntoSystemConfig<M>) -> &mut Self {
+        self.stages[stage as usize].add(system.into_config());
+        self
+    }
+
+    #[allow(dead_code)]
+    pub fn set_executor(&mut self, executor: ExecutorKind) -> &mut Self {
+        self.executor = executor;
+        self
+    }
+
+    // Resolves the ordering constraints of every stage without running anything.
+    #[allow(dead_code)]
+    pub fn build(&mut self) -> Result<(), ScheduleError> {
+        for stage in Stage::ALL {
+            self.stages[stage as usize].build(stage)?;
+        }
+        Ok(())
+    }
+
+    // The names of the systems in `stage`, in the order they will run.
+    #[allow(dead_code)]
+    pub fn system_order(&mut self, stage: Stage) -> Result<Vec<&str>, ScheduleError> {
+        let systems = &mut self.stages[stage as usize];
+        let order = systems.build(stage)?.to_vec();
+        Ok(order
+            .into_iter()
+            .map(|index| systems.systems[index].system.name())
+            .collect())
+    }
+
+    pub fn run(&mut self, world: &mut World) {
+        for stage in Stage::ALL {
+            self.stages[stage as usize].run(stage, self.executor, world);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::commands::Commands;
+    use crate::crowengine::components::change_detection::Changed;
+    use crate::crowengine::components::components::Component;
+    use crate::crowengine::components::filter::Without;
+    use crate::crowengine::entity::EntityId;
+    use crate::crowengine::systems::system_param::Query;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, PartialEq)]
+    struct Gold(u32);
+    impl Component for Gold {}
+
+    struct Market;
+    impl Component for Market {}
+
+    fn collect_taxes() {}
+    fn pay_armies() {}
+    fn end_turn() {}
+
+    #[test]
+    fn constraints_decide_the_order_within_a_stage() {
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Update, end_turn)
+            .add_system(Stage::Update, pay_armies)
+            .add_system(Stage::Update, collect_taxes.before("end_turn"));
+        assert_eq!(
+            schedule.system_order(Stage::Update).unwrap(),
+            ["pay_armies", "collect_taxes", "end_turn"]
+        );
+
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Update, end_turn.after("payday"))
+            .add_system(Stage::Update, pay_armies.label("payday"))
+            .add_system(Stage::Update, collect_taxes.label("payday"));
+        assert_eq!(
+            schedule.system_order(Stage::Update).unwrap(),
+            ["pay_armies", "collect_taxes", "end_turn"]
+        );
+    }
+
+    #[test]
+    fn cycles_and_unknown_labels_are_errors() {
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Update, collect_taxes.after("end_turn"))
+            .add_system(Stage::Update, pay_armies.after("collect_taxes"))
+            .add_system(Stage::Update, end_turn.after("pay_armies"));
+        assert_eq!(
+            schedule.build(),
+            Err(ScheduleError::Cycle {
+                stage: Stage::Update,
+                systems: vec![
+                    "collect_taxes".to_string(),
+                    "pay_armies".to_string(),
+                    "end_turn".to_string(),
+                ],
+            })
+        );
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::PostUpdate, end_turn.before("start_turn"));
+        assert_eq!(
+            schedule.build(),
+            Err(ScheduleError::UnknownLabel {
+                stage: Stage::PostUpdate,
+                system: "end_turn".to_string(),
+                label: "start_turn".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "cyclic ordering constraints")]
+    fn running_a_cyclic_schedule_panics() {
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Update, collect_taxes.after("end_turn"))
+            .add_system(Stage::Update, end_turn.after("collect_taxes"));
+        schedule.run(&mut World::empty());
+    }
+
+    #[test]
+    fn commands_are_applied_before_the_next_stage() {
+        fn found_markets(mut commands: Commands, cities: Query<(EntityId, &Gold)>) {
+            for (city, gold) in cities.iter() {
+                if gold.0 >= 5 {
+                    commands.entity(city).insert(Market);
+                }
+            }
+        }
+
+        fn trade(mut cities: Query<(&mut Gold,)>) {
+            for (gold,) in cities.iter_mut() {
+                gold.0 += 1;
+            }
+        }
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let (pre_update, post_update) = (log.clone(), log.clone());
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Update, trade.after("found_markets"))
+            .add_system(Stage::Update, found_markets)
+            .add_system(Stage::PreUpdate, move |world: &mut World| {
+                let markets = world.query::<(&Market,)>().count();
+                pre_update.lock().unwrap().push(("pre update", markets));
+            })
+            .add_system(Stage::PostUpdate, move |world: &mut World| {
+                let markets = world.query::<(&Market,)>().count();
+                post_update.lock().unwrap().push(("post update", markets));
+            });
+
+        let mut world = World::empty();
+        world.spawn((Gold(4),));
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        assert_eq!(
+            *log.lock().unwrap(),
+            [
+                ("pre update", 0),
+                ("post update", 0),
+                ("pre update", 0),
+                ("post update", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn systems_see_changes_made_since_they_last_ran() {
+        fn grow(mut cities: Query<(&mut Gold,), Without<Market>>) {
+            for (gold,) in cities.iter_mut() {
+                gold.0 += 1;
+            }
+        }
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::PreUpdate, grow).add_system(
+            Stage::Update,
+            move |cities: Query<(&Gold,), Changed<Gold>>| {
+                log.lock().unwrap().push(cities.iter().count());
+            },
+        );
+
+        let mut world = World::empty();
+        let city = world.spawn((Gold(0),));
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+        world.insert(city, Market);
+        schedule.run(&mut world);
+        assert_eq!(*seen.lock().unwrap(), [1, 1, 0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "already borrowed mutably")]
+    fn systems_with_aliasing_parameters_panic() {
+        fn pillage(_: Query<(&mut Gold,)>, _: Query<(&Gold,)>) {}
+
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Update, pillage);
+        schedule.run(&mut World::empty());
+    }
+}
@@ -0,0 +1,225 @@
+use super::super::components::change_detection::{Tick, Ticks};
+use super::super::components::query::Access;
+use super::super::world::World;
+use super::system_param::{SystemParam, SystemParamItem};
+
+use std::any::type_name;
+use std::marker::PhantomData;
+
+// Systems are `Send` so the multi-threaded executor can hand them to its
+// thread pool.
+pub trait System: Send + 'static {
+    fn name(&self) -> &str;
+
+    // Called once before the first run. Conflicting parameters panic here.
+    fn initialize(&mut self, world: &mut World);
+
+    fn access(&self) -> &Access;
+
+    // Exclusive systems take `&mut World` and can only run through `run`.
+    fn is_exclusive(&self) -> bool;
+
+    // Safety: the system must be initialized and nothing that conflicts with
+    // its `access` may touch the world while it runs.
+    unsafe fn run_unsafe(&mut self, world: &World);
+
+    fn run(&mut self, world: &mut World);
+
+    // Applies the commands queued during the previous run.
+    fn apply_deferred(&mut self, world: &mut World);
+}
+
+pub type BoxedSystem = Box<dyn System>;
+
+pub trait IntoSystem<Marker> {
+    type System: System;
+
+    fn into_system(self) -> Self::System;
+}
+
+// Type names of functions include their module path, which is noise in labels
+// and error messages. Closures are named after the function creating them, as
+// `{{closure}}` alone says nothing about where a failing system came from.
+fn short_name<T>() -> &'static str {
+    let name = type_name::<T>().trim_end_matches("::{{closure}}");
+    name.rsplit("::").next().unwrap_or(name)
+}
+
+// A plain function whose arguments are all `SystemParam`s. `Marker` is the
+// function's signature and only exists to keep the impls below apart.
+pub trait SystemParamFunction<Marker>: Send + 'static {
+    type Param: SystemParam;
+
+    fn run(&mut self, param: SystemParamItem<Self::Param>);
+}
+
+// The function has to be callable both with the parameter types as written
+// and with their re-borrowed items; `call_inner` picks the second.
+macro_rules! impl_system_param_function {
+    ($($name:ident),*) => {
+        impl<Func, $($name: SystemParam),*> SystemParamFunction<fn($($name,)*)> for Func
+        where
+            Func: Send + 'static,
+            for<'a> &'a mut Func: FnMut($($name),*) + FnMut($(SystemParamItem<$name>),*),
+        {
+            type Param = ($($name,)*);
+
+            #[allow(non_snake_case)]
+            fn run(&mut self, param: SystemParamItem<Self::Param>) {
+                #[allow(clippy::too_many_arguments)]
+                fn call_inner<$($name,)*>(mut f: impl FnMut($($name,)*), $($name: $name,)*) {
+                    f($($name,)*)
+                }
+                let ($($name,)*) = param;
+                call_inner(self, $($name),*)
+            }
+        }
+    };
+}
+
+impl_system_param_function!();
+all_tuples!(impl_system_param_function; P0, P1, P2, P3, P4, P5, P6, P7, P8, P9, P10, P11, P12, P13, P14, P15);
+
+pub struct FunctionSystem<Marker, F: SystemParamFunction<Marker>> {
+    func: F,
+    name: &'static str,
+    state: Option<<F::Param as SystemParam>::State>,
+    access: Access,
+    last_run: Tick,
+    marker: PhantomData<fn() -> Marker>,
+}
+
+pub struct IsFunctionSystem;
+
+impl<Marker: 'static, F: SystemParamFunction<Marker>> IntoSystem<(IsFunctionSystem, Marker)> for F {
+    type System = FunctionSystem<Marker, F>;
+
+    fn into_system(self) -> Self::System {
+        FunctionSystem {
+            func: self,
+            name: short_name::<F>(),
+            state: None,
+            access: Access::new(),
+            last_run: Tick(0),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<Marker: 'static, F: SystemParamFunction<Marker>> System for FunctionSystem<Marker, F> {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn initialize(&mut self, world: &mut World) {
+        if self.state.is_none() {
+            self.state = Some(F::Param::init_state(world, &mut self.access));
+        }
+    }
+
+    fn access(&self) -> &Access {
+        &self.access
+    }
+
+    fn is_exclusive(&self) -> bool {
+        false
+    }
+
+    unsafe fn run_unsafe(&mut self, world: &World) {
+        let state = self.state.as_mut().unwrap_or_else(|| {
+            panic!("System '{}' was run before being initialized!", self.name);
+        });
+
+        let this_run = world.increment_change_tick();
+        let ticks = Ticks {
+            last_run: self.last_run,
+            this_run,
+        };
+
+        let params = unsafe { F::Param::get_param(state, world, ticks) };
+        self.func.run(params);
+        self.last_run = this_run;
+    }
+
+    fn run(&mut self, world: &mut World) {
+        self.initialize(world);
+        // Safety: the world is borrowed mutably, so nothing else can touch it.
+        unsafe { self.run_unsafe(world) };
+        self.apply_deferred(world);
+    }
+
+    fn apply_deferred(&mut self, world: &mut World) {
+        if let Some(state) = self.state.as_mut() {
+            F::Param::apply(state, world);
+        }
+    }
+}
+
+// A function taking the whole world, e.g. rendering, which needs resources and
+// several queries at once. Exclusive systems always run on the thread that
+// runs the schedule, which is also the one owning the GL context.
+pub struct ExclusiveFunctionSystem<F> {
+    func: F,
+    name: &'static str,
+    access: Access,
+}
+
+pub struct IsExclusiveFunctionSystem;
+
+impl<F: FnMut(&mut World) + Send + 'static> IntoSystem<IsExclusiveFunctionSystem> for F {
+    type System = ExclusiveFunctionSystem<F>;
+
+    fn into_system(self) -> Self::System {
+        ExclusiveFunctionSystem {
+            func: self,
+            name: short_name::<F>(),
+            access: Access::new(),
+        }
+    }
+}
+
+impl<F: FnMut(&mut World) + Send + 'static> System for ExclusiveFunctionSystem<F> {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn initialize(&mut self, _world: &mut World) {}
+
+    fn access(&self) -> &Access {
+        &self.access
+    }
+
+    fn is_exclusive(&self) -> bool {
+        true
+    }
+
+    unsafe fn run_unsafe(&mut self, _world: &World) {
+        panic!(
+            "Exclusive system '{}' cannot run without mutable world access!",
+            self.name
+        );
+    }
+
+    fn run(&mut self, world: &mut World) {
+        world.increment_change_tick();
+        (self.func)(world);
+    }
+
+    fn apply_deferred(&mut self, _world: &mut World) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn end_turn() {}
+
+    #[test]
+    fn systems_are_named_after_their_function() {
+        assert_eq!(end_turn.into_system().name(), "end_turn");
+        assert_eq!(
+            (|_world: &mut World| {}).into_system().name(),
+            "systems_are_named_after_their_function"
+        );
+    }
+}
@@ -0,0 +1,346 @@
+use super::super::command_queue::CommandQueue;
+use super::super::commands::Commands;
+use super::super::components::change_detection::{RemovedComponents, Ticks};
+use super::super::components::components::Component;
+use super::super::components::filter::QueryFilter;
+use super::super::components::query::{Access, ReadOnlyWorldQuery, WorldQuery};
+use super::super::resources::Resource;
+use super::super::world::World;
+
+use std::any::type_name;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+
+// Something a system function can take as an argument. `State` lives inside
+// the system between runs, and `Item` is the same parameter re-borrowed for a
+// single run.
+pub trait SystemParam: Sized {
+    type State: Send + 'static;
+    type Item<'w, 's>: SystemParam<State = Self::State>;
+
+    // Registers everything the parameter touches so conflicting parameters of
+    // the same system panic once, when the system is initialized.
+    fn init_state(world: &mut World, access: &mut Access) -> Self::State;
+
+    // Safety: `access` must have been validated and no system whose access
+    // conflicts with it may run at the same time.
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut Self::State,
+        world: &'w World,
+        ticks: Ticks,
+    ) -> Self::Item<'w, 's>;
+
+    // Applies deferred work, such as queued commands, at the end of the stage.
+    fn apply(_state: &mut Self::State, _world: &mut World) {}
+}
+
+pub type SystemParamItem<'w, 's, P> = <P as SystemParam>::Item<'w, 's>;
+
+// A query over the world, seen through the change ticks of the system it is
+// passed to.
+pub struct Query<'w, Q: WorldQuery, F: QueryFilter = ()> {
+    world: &'w World,
+    ticks: Ticks,
+    marker: PhantomData<(Q, F)>,
+}
+
+#[allow(dead_code)]
+impl<Q: WorldQuery, F: QueryFilter> Query<'_, Q, F> {
+    pub fn iter(&self) -> impl Iterator<Item = Q::Item<'_>>
+    where
+        Q: ReadOnlyWorldQuery,
+    {
+        // Safety: read-only queries only ever create shared references.
+        unsafe { self.world.query_unchecked::<Q, F>(self.ticks) }
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = Q::Item<'_>> {
+        // Safety: the system's access was validated and borrowing `self`
+        // mutably keeps a second iterator from aliasing this one.
+        unsafe { self.world.query_unchecked::<Q, F>(self.ticks) }
+    }
+
+    // Collects the items and sorts them by `key`, e.g. by `EntityId` for code
+    // that has to behave the same regardless of archetype layout. The sort is
+    // stable, so items with equal keys keep the query's own order.
+    pub fn iter_sorted_by_key<'a, K: Ord>(
+        &'a self,
+        key: impl FnMut(&Q::Item<'a>) -> K,
+    ) -> impl Iterator<Item = Q::Item<'a>>
+    where
+        Q: ReadOnlyWorldQuery,
+    {
+        let mut items: Vec<_> = self.iter().collect();
+        items.sort_by_key(key);
+        items.into_iter()
+    }
+
+    pub fn iter_mut_sorted_by_key<'a, K: Ord>(
+        &'a mut self,
+        key: impl FnMut(&Q::Item<'a>) -> K,
+    ) -> impl Iterator<Item = Q::Item<'a>> {
+        let mut items: Vec<_> = self.iter_mut().collect();
+        items.sort_by_key(key);
+        items.into_iter()
+    }
+}
+
+impl<Q: WorldQuery + 'static, F: QueryFilter + 'static> SystemParam for Query<'_, Q, F> {
+    type State = ();
+    type Item<'w, 's> = Query<'w, Q, F>;
+
+    fn init_state(_world: &mut World, access: &mut Access) -> Self::State {
+        Q::access(access);
+        F::access(access);
+    }
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        world: &'w World,
+        ticks: Ticks,
+    ) -> Self::Item<'w, 's> {
+        Query {
+            world,
+            ticks,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl SystemParam for Commands<'_, '_> {
+    type State = CommandQueue;
+    type Item<'w, 's> = Commands<'w, 's>;
+
+    fn init_state(_world: &mut World, _access: &mut Access) -> Self::State {
+        CommandQueue::new()
+    }
+
+    unsafe fn get_param<'w, 's>(
+        state: &'s mut Self::State,
+        world: &'w World,
+        _ticks: Ticks,
+    ) -> Self::Item<'w, 's> {
+        Commands::new(state, world)
+    }
+
+    fn apply(state: &mut Self::State, world: &mut World) {
+        world.apply(state);
+    }
+}
+
+// Shared access to a resource. Systems taking `Res<T>` panic when run if the
+// resource hasn't been inserted.
+#[allow(dead_code)]
+pub struct Res<'w, R: Resource> {
+    value: &'w R,
+}
+
+impl<R: Resource> Deref for Res<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        self.value
+    }
+}
+
+impl<R: Resource> SystemParam for Res<'_, R> {
+    type State = ();
+    type Item<'w, 's> = Res<'w, R>;
+
+    fn init_state(_world: &mut World, access: &mut Access) -> Self::State {
+        access.add_resource_read::<R>();
+    }
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        world: &'w World,
+        _ticks: Ticks,
+    ) -> Self::Item<'w, 's> {
+        Res {
+            value: world.resource::<R>(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub struct ResMut<'w, R: Resource> {
+    value: &'w mut R,
+}
+
+impl<R: Resource> Deref for ResMut<'_, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        self.value
+    }
+}
+
+impl<R: Resource> DerefMut for ResMut<'_, R> {
+    fn deref_mut(&mut self) -> &mut R {
+        self.value
+    }
+}
+
+impl<R: Resource> SystemParam for ResMut<'_, R> {
+    type State = ();
+    type Item<'w, 's> = ResMut<'w, R>;
+
+    fn init_state(_world: &mut World, access: &mut Access) -> Self::State {
+        access.add_resource_write::<R>();
+    }
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        world: &'w World,
+        _ticks: Ticks,
+    ) -> Self::Item<'w, 's> {
+        // Safety: the write was registered in the system's access.
+        let value = unsafe { world.get_resource_unchecked_mut::<R>() };
+        ResMut {
+            value: value.unwrap_or_else(|| {
+                panic!("Resource {} does not exist!", type_name::<R>());
+            }),
+        }
+    }
+}
+
+impl<T: Component> SystemParam for RemovedComponents<'_, T> {
+    type State = ();
+    type Item<'w, 's> = RemovedComponents<'w, T>;
+
+    fn init_state(_world: &mut World, _access: &mut Access) -> Self::State {}
+
+    unsafe fn get_param<'w, 's>(
+        _state: &'s mut Self::State,
+        world: &'w World,
+        ticks: Ticks,
+    ) -> Self::Item<'w, 's> {
+        world.removed_with_ticks(ticks)
+    }
+}
+
+macro_rules! impl_system_param {
+    ($($name:ident),*) => {
+        #[allow(non_snake_case, unused_variables, clippy::unused_unit)]
+        impl<$($name: SystemParam),*> SystemParam for ($($name,)*) {
+            type State = ($($name::State,)*);
+            type Item<'w, 's> = ($($name::Item<'w, 's>,)*);
+
+            fn init_state(world: &mut World, access: &mut Access) -> Self::State {
+                ($($name::init_state(world, access),)*)
+            }
+
+            unsafe fn get_param<'w, 's>(
+                state: &'s mut Self::State,
+                world: &'w World,
+                ticks: Ticks,
+            ) -> Self::Item<'w, 's> {
+                let ($($name,)*) = state;
+                ($(unsafe { $name::get_param($name, world, ticks) },)*)
+            }
+
+            fn apply(state: &mut Self::State, world: &mut World) {
+                let ($($name,)*) = state;
+                $($name::apply($name, world);)*
+            }
+        }
+    };
+}
+
+impl_system_param!();
+all_tuples!(impl_system_param; P0, P1, P2, P3, P4, P5, P6, P7, P8, P9, P10, P11, P12, P13, P14, P15);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::systems::schedule::{Schedule, Stage};
+
+    #[derive(Debug, PartialEq)]
+    struct Score(u32);
+    impl Resource for Score {}
+
+    #[derive(Debug, PartialEq)]
+    struct Turn(u32);
+    impl Resource for Turn {}
+
+    #[derive(Debug, PartialEq)]
+    struct Gold(u32);
+    impl Component for Gold {}
+    impl Resource for Gold {}
+
+    #[test]
+    fn systems_read_and_write_resources() {
+        fn tally(mut score: ResMut<Score>, turn: Res<Turn>, cities: Query<(&Gold,)>) {
+            for (gold,) in cities.iter() {
+                score.0 += gold.0 + turn.0;
+            }
+        }
+
+        fn next_turn(mut turn: ResMut<Turn>) {
+            turn.0 += 1;
+        }
+
+        let mut world = World::empty();
+        world.insert_resource(Score(0));
+        world.insert_resource(Turn(0));
+        world.spawn((Gold(5),));
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(Stage::Update, tally)
+            .add_system(Stage::PostUpdate, next_turn);
+        schedule.run(&mut world);
+        schedule.run(&mut world);
+
+        assert_eq!(world.resource::<Score>(), &Score(11));
+        assert_eq!(world.resource::<Turn>(), &Turn(2));
+    }
+
+    #[test]
+    fn resource_scope_allows_world_access_alongside() {
+        let mut world = World::empty();
+        world.insert_resource(Score(1));
+        world.spawn((Gold(5),));
+        world.resource_scope(|world, score: &mut Score| {
+            for (gold,) in world.query_mut::<(&mut Gold,)>() {
+                gold.0 += score.0;
+                score.0 += 1;
+            }
+            assert!(!world.contains_resource::<Score>());
+        });
+
+        assert_eq!(world.resource::<Score>(), &Score(2));
+        assert_eq!(world.query::<(&Gold,)>().next().unwrap().0, &Gold(6));
+    }
+
+    #[test]
+    fn resources_and_components_are_accessed_apart() {
+        let mut component = Access::new();
+        Query::<(&mut Gold,)>::init_state(&mut World::empty(), &mut component);
+        let mut resource = Access::new();
+        ResMut::<Gold>::init_state(&mut World::empty(), &mut resource);
+        assert!(component.is_compatible(&resource));
+
+        // Nor do they alias within one system.
+        resource.add_write::<Gold>();
+    }
+
+    #[test]
+    #[should_panic(expected = "already borrowed mutably")]
+    fn aliasing_resource_parameters_panic() {
+        fn bad(_: ResMut<Score>, _: Res<Score>) {}
+
+        let mut world = World::empty();
+        world.insert_resource(Score(0));
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Update, bad);
+        schedule.run(&mut world);
+    }
+
+    #[test]
+    #[should_panic(expected = "does not exist")]
+    fn missing_resources_panic() {
+        let mut schedule = Schedule::new();
+        schedule.add_system(Stage::Update, |_: Res<Score>| {});
+        schedule.run(&mut World::empty());
+    }
+}
@@ -0,0 +1,461 @@
+use super::app::{App, Plugin};
+use super::resources::Resource;
+use super::systems::schedule::{IntoSystemConfig, Schedule, Stage};
+use super::world::World;
+
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Where the time of a frame comes from. Tests use `Manual` to get the same
+// delta every frame regardless of how fast they run.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum TimeSource {
+    Real,
+    Manual(Duration),
+}
+
+// The time of the current frame, advanced by `App::update` before any system
+// runs. `delta` and `elapsed` are scaled and stop while paused, the `raw_`
+// versions always follow the clock. Multiply movement by `delta_seconds` to
+// make it independent of the frame rate.
+pub struct Time {
+    source: TimeSource,
+    last_instant: Option<Instant>,
+    delta: Duration,
+    elapsed: Duration,
+    raw_delta: Duration,
+    raw_elapsed: Duration,
+    frame_count: u64,
+    scale: f32,
+    paused: bool,
+}
+
+impl Resource for Time {}
+
+#[allow(dead_code)]
+impl Time {
+    pub fn new() -> Self {
+        Self::with_source(TimeSource::Real)
+    }
+
+    // Every update advances the clock by exactly `step`.
+    pub fn manual(step: Duration) -> Self {
+        Self::with_source(TimeSource::Manual(step))
+    }
+
+    pub fn with_source(source: TimeSource) -> Self {
+        Self {
+            source,
+            last_instant: None,
+            delta: Duration::ZERO,
+            elapsed: Duration::ZERO,
+            raw_delta: Duration::ZERO,
+            raw_elapsed: Duration::ZERO,
+            frame_count: 0,
+            scale: 1.0,
+            paused: false,
+        }
+    }
+
+    // The first real update only starts the clock, so the first frame has no
+    // delta.
+    pub fn update(&mut self) {
+        let raw_delta = match self.source {
+            TimeSource::Real => {
+                let now = Instant::now();
+                let last_instant = self.last_instant.replace(now);
+                last_instant.map_or(Duration::ZERO, |last| now.saturating_duration_since(last))
+            }
+            TimeSource::Manual(step) => step,
+        };
+        self.advance_by(raw_delta);
+    }
+
+    // Starts a new frame that took `raw_delta`.
+    pub fn advance_by(&mut self, raw_delta: Duration) {
+        self.frame_count += 1;
+        self.raw_delta = raw_delta;
+        self.raw_elapsed += raw_delta;
+
+        self.delta = if self.paused {
+            Duration::ZERO
+        } else {
+            raw_delta.mul_f64(self.scale as f64)
+        };
+        self.elapsed += self.delta;
+    }
+
+    pub fn source(&self) -> TimeSource {
+        self.source
+    }
+
+    pub fn set_source(&mut self, source: TimeSource) {
+        self.source = source;
+        self.last_instant = None;
+    }
+
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    pub fn delta_seconds(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    pub fn elapsed_seconds(&self) -> f32 {
+        self.elapsed.as_secs_f32()
+    }
+
+    pub fn raw_delta(&self) -> Duration {
+        self.raw_delta
+    }
+
+    pub fn raw_elapsed(&self) -> Duration {
+        self.raw_elapsed
+    }
+
+    // The number of updates so far, counting the current one.
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    // Takes effect from the next update. A scale of 0.5 runs the game at half
+    // speed.
+    pub fn set_scale(&mut self, scale: f32) {
+        if scale < 0.0 || !scale.is_finite() {
+            panic!("Time scale must be finite and not negative, got {}!", scale);
+        }
+        self.scale = scale;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn unpause(&mut self) {
+        self.paused = false;
+    }
+}
+
+// The rate of the `FixedUpdate` schedule and the scaled time it still owes.
+// Every frame adds `Time::delta` to the accumulator and runs the schedule once
+// per whole step in it, so simulation advances the same amount per second at
+// any frame rate, and not at all while paused.
+//
+// A frame never owes more than `max_steps_per_frame` steps. After a long stall,
+// e.g. loading or a breakpoint, the rest of the time is dropped instead of
+// being caught up in one go, which would only make the next frame slower.
+pub struct FixedTime {
+    step: Duration,
+    accumulator: Duration,
+    max_steps_per_frame: u32,
+    steps: u64,
+}
+
+impl Resource for FixedTime {}
+
+#[allow(dead_code)]
+impl FixedTime {
+    pub fn new(step: Duration) -> Self {
+        let mut fixed_time = Self {
+            step: Duration::ZERO,
+            accumulator: Duration::ZERO,
+            max_steps_per_frame: 8,
+            steps: 0,
+        };
+        fixed_time.set_step(step);
+        fixed_time
+    }
+
+    pub fn from_hz(hz: f64) -> Self {
+        Self::new(Duration::from_secs_f64(1.0 / hz))
+    }
+
+    // Use this rather than `Time::delta` in fixed systems.
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    pub fn step_seconds(&self) -> f32 {
+        self.step.as_secs_f32()
+    }
+
+    pub fn set_step(&mut self, step: Duration) {
+        if step.is_zero() {
+            panic!("Fixed timestep must be longer than zero!");
+        }
+        self.step = step;
+    }
+
+    pub fn max_steps_per_frame(&self) -> u32 {
+        self.max_steps_per_frame
+    }
+
+    pub fn set_max_steps_per_frame(&mut self, max_steps: u32) {
+        if max_steps == 0 {
+            panic!("Fixed update must be allowed at least one step per frame!");
+        }
+        self.max_steps_per_frame = max_steps;
+    }
+
+    // How far into the next step the frame is, from 0 to 1. Useful to
+    // interpolate what is drawn between the last two fixed states.
+    pub fn overstep_fraction(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+
+    // The number of fixed steps run so far.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn accumulate(&mut self, delta: Duration) {
+        self.accumulator = (self.accumulator + delta).min(self.step * self.max_steps_per_frame);
+    }
+
+    // Takes one step out of the accumulator if there is one.
+    pub fn expend(&mut self) -> bool {
+        if self.accumulator < self.step {
+            return false;
+        }
+
+        self.accumulator -= self.step;
+        self.steps += 1;
+        true
+    }
+}
+
+// Schedules aren't `Sync`, so the schedule sits behind a mutex that is only
+// ever accessed through `get_mut`.
+pub struct FixedUpdateSchedule(Mutex<Schedule>);
+
+impl Resource for FixedUpdateSchedule {}
+
+impl FixedUpdateSchedule {
+    pub fn new() -> Self {
+        Self(Mutex::new(Schedule::new()))
+    }
+
+    // Fixed systems all share one stage, so they are ordered with labels.
+    pub fn add_system<M>(&mut self, system: impl IntoSystemConfig<M>) -> &mut Self {
+        self.0.get_mut().unwrap().add_system(Stage::Update, system);
+        self
+    }
+}
+
+// Runs the `FixedUpdate` schedule as many times as the frame owes steps,
+// possibly zero. The schedule is taken out of `FixedUpdateSchedule` while it
+// runs, so its systems can use the resource too, and put back even if one of
+// them panics.
+pub fn run_fixed_update(world: &mut World) {
+    let delta = world.resource::<Time>().delta();
+    world.resource_mut::<FixedTime>().accumulate(delta);
+
+    let mut schedule = mem::take(
+        world
+            .resource_mut::<FixedUpdateSchedule>()
+            .0
+            .get_mut()
+            .unwrap(),
+    );
+
+    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+        while world.resource_mut::<FixedTime>().expend() {
+            schedule.run(world);
+        }
+    }));
+
+    *world
+        .resource_mut::<FixedUpdateSchedule>()
+        .0
+        .get_mut()
+        .unwrap() = schedule;
+    if let Err(payload) = result {
+        panic::resume_unwind(payload);
+    }
+}
+
+// Adds `Time` and a 60 Hz `FixedUpdate` schedule that runs in `RunFixedUpdate`.
+// Insert another `Time` or `FixedTime` after adding the plugin to change the
+// clock or the rate.
+pub struct TimePlugin;
+
+impl Plugin for TimePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Time::new())
+            .insert_resource(FixedTime::from_hz(60.0))
+            .insert_resource(FixedUpdateSchedule::new())
+            .add_system(Stage::RunFixedUpdate, run_fixed_update);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::systems::system_param::{Res, ResMut};
+    use crate::window::input::{InputPlugin, Keys};
+    use crate::window::window_events::KeyboardInput;
+    use glfw::{Action, Key, Modifiers};
+
+    #[derive(Default)]
+    struct Ships {
+        distance: f32,
+        moves: u32,
+    }
+
+    impl Resource for Ships {}
+
+    fn sail(fixed_time: Res<FixedTime>, mut ships: ResMut<Ships>) {
+        ships.distance += fixed_time.step_seconds();
+        ships.moves += 1;
+    }
+
+    fn app(frame_time: u64, step: u64) -> App {
+        let mut app = App::new();
+        app.add_plugin(TimePlugin)
+            .insert_resource(Time::manual(Duration::from_millis(frame_time)))
+            .insert_resource(FixedTime::new(Duration::from_millis(step)))
+            .insert_resource(Ships::default())
+            .add_fixed_system(sail);
+        app
+    }
+
+    fn moves(app: &App) -> u32 {
+        app.world.resource::<Ships>().moves
+    }
+
+    #[test]
+    fn manual_time_advances_by_its_step() {
+        let mut app = app(25, 10);
+        app.update();
+        app.update();
+
+        let time = app.world.resource::<Time>();
+        assert_eq!(time.frame_count(), 2);
+        assert_eq!(time.delta(), Duration::from_millis(25));
+        assert_eq!(time.elapsed(), Duration::from_millis(50));
+    }
+
+    #[test]
+    fn real_time_starts_without_a_delta() {
+        let mut time = Time::new();
+        time.update();
+        assert_eq!(time.delta(), Duration::ZERO);
+        std::thread::sleep(Duration::from_millis(2));
+        time.update();
+        assert!(time.delta() >= Duration::from_millis(2));
+    }
+
+    #[test]
+    fn fixed_update_runs_once_per_whole_step() {
+        let mut app = app(25, 10);
+        app.update();
+        assert_eq!(moves(&app), 2);
+        app.update();
+        assert_eq!(moves(&app), 5);
+        assert_eq!(app.world.resource::<FixedTime>().steps(), 5);
+        assert!(app.world.resource::<FixedTime>().overstep_fraction() < 1e-5);
+        assert!((app.world.resource::<Ships>().distance - 0.05).abs() < 1e-5);
+    }
+
+    #[test]
+    fn pausing_and_scaling_affect_fixed_update() {
+        let mut app = app(25, 10);
+        app.update();
+        app.update();
+
+        app.world.resource_mut::<Time>().pause();
+        app.update();
+        let time = app.world.resource::<Time>();
+        assert_eq!(time.delta(), Duration::ZERO);
+        assert_eq!(time.raw_elapsed(), Duration::from_millis(75));
+        assert_eq!(moves(&app), 5);
+
+        app.world.resource_mut::<Time>().unpause();
+        app.world.resource_mut::<Time>().set_scale(2.0);
+        app.update();
+        assert_eq!(
+            app.world.resource::<Time>().delta(),
+            Duration::from_millis(50)
+        );
+        assert_eq!(moves(&app), 10);
+    }
+
+    #[test]
+    fn stalls_are_not_caught_up() {
+        let mut app = app(10, 10);
+        app.world
+            .resource_mut::<Time>()
+            .set_source(TimeSource::Manual(Duration::from_secs(60)));
+        app.update();
+        assert_eq!(moves(&app), 8);
+
+        app.world
+            .resource_mut::<Time>()
+            .set_source(TimeSource::Manual(Duration::from_millis(10)));
+        app.update();
+        assert_eq!(moves(&app), 9);
+
+        app.world
+            .resource_mut::<FixedTime>()
+            .set_max_steps_per_frame(2);
+        app.world
+            .resource_mut::<Time>()
+            .set_source(TimeSource::Manual(Duration::from_secs(1)));
+        app.update();
+        assert_eq!(moves(&app), 11);
+    }
+
+    #[test]
+    fn fixed_systems_see_the_input_of_the_frame() {
+        let mut app = app(10, 10);
+        app.add_plugin(InputPlugin).add_fixed_system(
+            |keys: Res<Keys>, mut ships: ResMut<Ships>| {
+                if keys.just_pressed(Key::W) {
+                    ships.distance = 100.0;
+                }
+            },
+        );
+
+        app.world.send_event(KeyboardInput {
+            key: Key::W,
+            action: Action::Press,
+            modifiers: Modifiers::empty(),
+        });
+        app.update();
+        assert!(app.world.resource::<Ships>().distance >= 100.0);
+    }
+
+    #[test]
+    fn panicking_fixed_systems_keep_their_schedule() {
+        let mut app = app(10, 10);
+        let mut storm = true;
+        app.add_fixed_system(move |_: Res<FixedUpdateSchedule>| {
+            if mem::take(&mut storm) {
+                panic!("The fleet sank in a storm!")
+            }
+        });
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| app.update()));
+        assert!(result.is_err());
+        assert!(app.world.get_resource::<FixedUpdateSchedule>().is_some());
+
+        let before = moves(&app);
+        app.update();
+        assert_eq!(moves(&app), before + 1);
+    }
+}
@@ -0,0 +1,95 @@
+use super::app::{App, Plugin};
+use super::components::filter::Without;
+use super::components::global_transform::GlobalTransform;
+use super::components::hierarchy::{Children, Parent};
+use super::components::transform::Transform;
+use super::entity::EntityId;
+use super::systems::schedule::Stage;
+use super::world::World;
+
+use nalgebra_glm as glm;
+
+// Recomputes every `GlobalTransform` from the roots of the hierarchy down, so
+// run it after anything that moves entities and before rendering. Entities
+// with a `Transform` get a `GlobalTransform` inserted the first time around.
+pub fn propagate_transforms(world: &mut World) {
+    let roots: Vec<EntityId> = world
+        .query_filtered::<(EntityId, &Transform), Without<Parent>>()
+        .map(|(entity, _)| entity)
+        .collect();
+
+    for root in roots {
+        propagate(world, root, &glm::identity());
+    }
+}
+
+fn propagate(world: &mut World, entity: EntityId, parent: &glm::Mat4) {
+    // A child without a transform cuts its subtree off from the hierarchy.
+    let Some(transform) = world.get::<Transform>(&entity) else {
+        return;
+    };
+    let matrix = parent * transform.model_matrix();
+
+    // Only write when the matrix moved, so `Changed<GlobalTransform>` stays useful.
+    match world.get::<GlobalTransform>(&entity) {
+        Some(global) if global.matrix == matrix => {}
+        Some(_) => world.get_mut::<GlobalTransform>(&entity).unwrap().matrix = matrix,
+        None => world.insert(entity, GlobalTransform::from_matrix(matrix)),
+    }
+
+    let children: Vec<EntityId> = world
+        .get::<Children>(&entity)
+        .map_or_else(Vec::new, |children| children.iter().collect());
+    for child in children {
+        propagate(world, child, &matrix);
+    }
+}
+
+pub struct TransformPlugin;
+
+impl Plugin for TransformPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(Stage::PostUpdate, propagate_transforms);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(world: &World, entity: EntityId) -> (f32, f32, f32) {
+        let position = world.get::<GlobalTransform>(&entity).unwrap().position();
+        (position.x, position.y, position.z)
+    }
+
+    #[test]
+    fn children_are_placed_relative_to_their_parent() {
+        let mut world = World::empty();
+        let ship = world.spawn(Transform::from_xyz(1.0, 0.0, 0.0));
+        let deck = world.spawn(Transform::from_xyz(0.0, 2.0, 0.0));
+        let cannon = world.spawn(Transform::from_xyz(0.0, 0.0, 3.0));
+        world.set_parent(deck, ship);
+        world.set_parent(cannon, deck);
+
+        propagate_transforms(&mut world);
+        assert_eq!(position(&world, cannon), (1.0, 2.0, 3.0));
+
+        world.remove_parent(deck);
+        propagate_transforms(&mut world);
+        assert_eq!(position(&world, cannon), (0.0, 2.0, 3.0));
+    }
+
+    #[test]
+    fn children_of_despawned_parents_keep_being_updated() {
+        let mut world = World::empty();
+        let ship = world.spawn(Transform::from_xyz(1.0, 0.0, 0.0));
+        let cannon = world.spawn(Transform::from_xyz(0.0, 0.0, 3.0));
+        world.set_parent(cannon, ship);
+        propagate_transforms(&mut world);
+
+        world.despawn(ship);
+        world.get_mut::<Transform>(&cannon).unwrap().position.z = 4.0;
+        propagate_transforms(&mut world);
+        assert_eq!(position(&world, cannon), (0.0, 0.0, 4.0));
+    }
+}
@@ -1,48 +1,824 @@
+use super::bundle::Bundle;
+use super::command_queue::CommandQueue;
+use super::components::camera::Camera;
+use super::components::change_detection::{RemovedComponents, Tick, Ticks};
+use super::components::color::Color;
 use super::components::components::Component;
 use super::components::components::ComponentStorage;
-use super::components::query::Query;
-use super::entity::EntityId;
-use super::shaders::shader::Shader;
+use super::components::filter::QueryFilter;
+use super::components::hierarchy::{Children, Parent};
+use super::components::hooks::{ComponentHooks, Trigger};
+use super::components::query::{Access, ReadOnlyWorldQuery, WorldQuery};
+use super::components::transform::Transform;
+use super::components::use_shader::UseShader;
+use super::entity::{Entities, EntityId};
+use super::events::{Event, Events};
+use super::observer::Observers;
+use super::prefab::{self, PrefabError, Prefabs};
+use super::reflect::traits::{FromReflect, Reflect};
+use super::reflect::type_registry::TypeRegistry;
+use super::resources::{Resource, Resources};
+use super::shaders::shader::{Shader, Shaders};
+use std::any::{type_name, TypeId};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
 
 pub struct World {
-    #[allow(dead_code)]
-    pub resources: Resources,
+    resources: Resources,
+    entities: Entities,
     components: ComponentStorage,
-}
-
-pub struct Resources {
-    pub shaders: HashMap<String, Shader>,
+    change_tick: AtomicU32,
+    last_change_tick: Tick,
+    removed: HashMap<TypeId, Vec<(EntityId, Tick)>>,
+    event_updaters: Vec<fn(&mut World)>,
+    observers: Observers,
 }
 
 impl World {
+    #[allow(dead_code)]
     pub fn new(default_shader: Shader) -> Self {
-        let mut shaders = HashMap::new();
+        let mut shaders = Shaders::new();
         shaders.insert("default".to_string(), default_shader);
 
-        Self {
-            resources: Resources { shaders },
+        let mut world = Self::empty();
+        world.insert_resource(shaders);
+        world
+    }
+
+    // A world without shaders, which doesn't need a GL context.
+    pub fn empty() -> Self {
+        let mut world = Self {
+            resources: Resources::new(),
+            entities: Entities::new(),
             components: ComponentStorage::new(),
+            change_tick: AtomicU32::new(1),
+            last_change_tick: Tick(0),
+            removed: HashMap::new(),
+            event_updaters: Vec::new(),
+            observers: Observers::new(),
+        };
+
+        world.insert_resource(TypeRegistry::new());
+        world.insert_resource(Prefabs::new());
+        world.register_type::<Transform>();
+        world.register_type::<Color>();
+        world.register_type::<Camera>();
+        world.register_type::<UseShader>();
+        world
+    }
+
+    pub fn spawn_empty(&mut self) -> EntityId {
+        self.entities.alloc()
+    }
+
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> EntityId {
+        let entity = self.spawn_empty();
+        bundle.insert_all(self, entity);
+        entity
+    }
+
+    // Spawns an entity, and its children, from the prefab registered under
+    // `name` in the `Prefabs` resource.
+    #[allow(dead_code)]
+    pub fn spawn_prefab(&mut self, name: &str) -> Result<EntityId, PrefabError> {
+        let entity = self.spawn_empty();
+        prefab::spawn_prefab(self, entity, name)?;
+        Ok(entity)
+    }
+
+    #[allow(dead_code)]
+    pub fn entity_mut(&mut self, entity: EntityId) -> EntityWorldMut<'_> {
+        if !self.contains(entity) {
+            panic!("Entity {:?} does not exist!", entity);
+        }
+
+        EntityWorldMut {
+            entity,
+            world: self,
+        }
+    }
+
+    pub fn reserve_entity(&self) -> EntityId {
+        self.entities.reserve()
+    }
+
+    #[allow(dead_code)]
+    pub fn apply(&mut self, queue: &mut CommandQueue) {
+        self.entities.flush();
+        queue.apply(self);
+    }
+
+    pub fn contains(&self, entity: EntityId) -> bool {
+        self.entities.contains(entity)
+    }
+
+    pub fn despawn(&mut self, entity: EntityId) -> bool {
+        if !self.contains(entity) {
+            return false;
+        }
+
+        // Children outliving their parent become roots, so transform
+        // propagation still reaches them.
+        self.remove_parent(entity);
+        if let Some(children) = self.remove::<Children>(entity) {
+            for child in children.0 {
+                self.remove::<Parent>(child);
+            }
+        }
+
+        for type_id in self.components.component_types(&entity) {
+            self.trigger(Trigger::Remove, entity, type_id);
+        }
+        self.observers.remove_entity(entity);
+
+        // A hook or observer may have despawned the entity already.
+        if !self.entities.free(entity) {
+            return false;
+        }
+
+        let change_tick = self.change_tick();
+        for type_id in self.components.remove_entity(&entity) {
+            self.removed
+                .entry(type_id)
+                .or_default()
+                .push((entity, change_tick));
+        }
+        true
+    }
+
+    // Despawns the entity along with all of its descendants.
+    pub fn despawn_recursive(&mut self, entity: EntityId) -> bool {
+        if !self.contains(entity) {
+            return false;
+        }
+
+        self.remove_parent(entity);
+        self.despawn_descendants(entity);
+        true
+    }
+
+    fn despawn_descendants(&mut self, entity: EntityId) {
+        if let Some(children) = self.remove::<Children>(entity) {
+            for child in children.0 {
+                self.despawn_descendants(child);
+            }
+        }
+        self.despawn(entity);
+    }
+
+    // Attaches `child` to `parent`, detaching it from its previous parent first.
+    pub fn set_parent(&mut self, child: EntityId, parent: EntityId) {
+        if !self.contains(parent) {
+            panic!("Cannot attach {:?} to dead entity {:?}!", child, parent);
+        }
+
+        let mut ancestor = Some(parent);
+        while let Some(entity) = ancestor {
+            if entity == child {
+                panic!(
+                    "Cannot attach {:?} to {:?}, which is one of its descendants!",
+                    child, parent
+                );
+            }
+            ancestor = self.get::<Parent>(&entity).map(Parent::get);
+        }
+
+        self.remove_parent(child);
+        self.insert(child, Parent(parent));
+        match self.get_mut::<Children>(&parent) {
+            Some(children) => children.0.push(child),
+            None => self.insert(parent, Children(vec![child])),
+        }
+    }
+
+    // Detaches `child` from its parent, making it a root again.
+    pub fn remove_parent(&mut self, child: EntityId) {
+        let Some(Parent(parent)) = self.remove::<Parent>(child) else {
+            return;
+        };
+
+        if let Some(children) = self.get_mut::<Children>(&parent) {
+            children.0.retain(|entity| *entity != child);
+            if children.0.is_empty() {
+                self.remove::<Children>(parent);
+            }
         }
     }
 
     #[allow(dead_code)]
     pub fn get<T: Component>(&self, entity: &EntityId) -> Option<&T> {
+        if !self.contains(*entity) {
+            return None;
+        }
+
         self.components.get::<T>(entity)
     }
 
+    #[allow(dead_code)]
+    pub fn get_mut<T: Component>(&mut self, entity: &EntityId) -> Option<&mut T> {
+        if !self.contains(*entity) {
+            return None;
+        }
+
+        let change_tick = self.change_tick();
+        self.components.get_mut::<T>(entity, change_tick)
+    }
+
     pub fn insert<T: Component>(&mut self, entity: EntityId, component: T) {
-        self.components.insert(entity, component);
+        if !self.contains(entity) {
+            panic!("Cannot insert component into dead entity {:?}!", entity);
+        }
+
+        let added = !self.components.contains::<T>(&entity);
+        let change_tick = self.change_tick();
+        self.components.insert(entity, component, change_tick);
+
+        if added {
+            self.trigger(Trigger::Add, entity, TypeId::of::<T>());
+        }
+        self.trigger(Trigger::Insert, entity, TypeId::of::<T>());
+    }
+
+    pub fn remove<T: Component>(&mut self, entity: EntityId) -> Option<T> {
+        if !self.contains(entity) || !self.components.contains::<T>(&entity) {
+            return None;
+        }
+
+        self.trigger(Trigger::Remove, entity, TypeId::of::<T>());
+        let component = self.components.remove::<T>(&entity)?;
+        let change_tick = self.change_tick();
+        self.removed
+            .entry(TypeId::of::<T>())
+            .or_default()
+            .push((entity, change_tick));
+        Some(component)
+    }
+
+    // Panics if a hook of the same kind was already registered for `T`.
+    #[allow(dead_code)]
+    pub fn register_component_hooks<T: Component>(&mut self) -> &mut ComponentHooks {
+        self.components.hooks_mut::<T>()
+    }
+
+    // Runs `observer` every time `trigger` happens to the `T` component of `entity`.
+    pub fn observe<T: Component>(
+        &mut self,
+        entity: EntityId,
+        trigger: Trigger,
+        observer: impl FnMut(&mut World, EntityId) + Send + Sync + 'static,
+    ) {
+        if !self.contains(entity) {
+            panic!("Cannot observe dead entity {:?}!", entity);
+        }
+
+        self.observers
+            .add(entity, TypeId::of::<T>(), trigger, Box::new(observer));
+    }
+
+    // Runs the component type's hook first, then the entity's observers.
+    fn trigger(&mut self, trigger: Trigger, entity: EntityId, type_id: TypeId) {
+        let hook = self
+            .components
+            .hooks(type_id)
+            .and_then(|hooks| hooks.get(trigger));
+        if let Some(hook) = hook {
+            hook(self, entity);
+        }
+
+        if let Some(mut observers) = self.observers.take(entity, type_id, trigger) {
+            for observer in observers.iter_mut() {
+                observer(self, entity);
+            }
+            if self.contains(entity) {
+                self.observers.restore(entity, type_id, trigger, observers);
+            }
+        }
+    }
+
+    // Makes `T` known to reflection by its name, and through that to tools
+    // and files that don't know the Rust type.
+    pub fn register_type<T: Component + FromReflect>(&mut self) {
+        if !self.contains_resource::<TypeRegistry>() {
+            self.insert_resource(TypeRegistry::new());
+        }
+        self.resource_mut::<TypeRegistry>().register::<T>();
+    }
+
+    pub fn type_registry(&self) -> &TypeRegistry {
+        self.resource::<TypeRegistry>()
+    }
+
+    // Every registered component of the entity in storage order. Components of
+    // unregistered types are left out.
+    #[allow(dead_code)]
+    pub fn reflect_components(&self, entity: EntityId) -> Vec<&dyn Reflect> {
+        let registry = self.type_registry();
+        self.components
+            .component_types(&entity)
+            .into_iter()
+            .filter_map(|type_id| registry.get(type_id))
+            .filter_map(|registration| registration.component.get(self, entity))
+            .collect()
+    }
+
+    #[allow(dead_code)]
+    pub fn reflect_component(&self, entity: EntityId, name: &str) -> Option<&dyn Reflect> {
+        let registration = self.type_registry().get_by_name(name)?;
+        registration.component.get(self, entity)
+    }
+
+    #[allow(dead_code)]
+    pub fn reflect_component_mut(
+        &mut self,
+        entity: EntityId,
+        name: &str,
+    ) -> Option<&mut dyn Reflect> {
+        let component = self.type_registry().get_by_name(name)?.component;
+        component.get_mut(self, entity)
+    }
+
+    // Replaces and returns any previous value of the same type.
+    pub fn insert_resource<R: Resource>(&mut self, resource: R) -> Option<R> {
+        self.resources.insert(resource)
+    }
+
+    #[allow(dead_code)]
+    pub fn remove_resource<R: Resource>(&mut self) -> Option<R> {
+        self.resources.remove::<R>()
+    }
+
+    pub fn contains_resource<R: Resource>(&self) -> bool {
+        self.resources.contains::<R>()
+    }
+
+    pub fn resource<R: Resource>(&self) -> &R {
+        self.get_resource::<R>().unwrap_or_else(|| {
+            panic!("Resource {} does not exist!", type_name::<R>());
+        })
+    }
+
+    pub fn resource_mut<R: Resource>(&mut self) -> &mut R {
+        self.get_resource_mut::<R>().unwrap_or_else(|| {
+            panic!("Resource {} does not exist!", type_name::<R>());
+        })
+    }
+
+    pub fn get_resource<R: Resource>(&self) -> Option<&R> {
+        self.resources.get::<R>()
+    }
+
+    pub fn get_resource_mut<R: Resource>(&mut self) -> Option<&mut R> {
+        self.resources.get_mut::<R>()
+    }
+
+    // Safety: the caller must make sure nothing else borrows the resource for `'a`.
+    #[allow(dead_code, clippy::mut_from_ref)]
+    pub unsafe fn get_resource_unchecked_mut<R: Resource>(&self) -> Option<&mut R> {
+        unsafe { self.resources.get_unchecked_mut::<R>() }
+    }
+
+    // Takes the resource out of the world for the duration of `f`, so it can be
+    // used alongside queries and other mutable world access.
+    #[allow(dead_code)]
+    pub fn resource_scope<R: Resource, U>(&mut self, f: impl FnOnce(&mut World, &mut R) -> U) -> U {
+        let mut resource = self.remove_resource::<R>().unwrap_or_else(|| {
+            panic!("Resource {} does not exist!", type_name::<R>());
+        });
+        let result = f(self, &mut resource);
+        self.insert_resource(resource);
+        result
+    }
+
+    // Registers an event type. Its buffers are swapped by `update_events`.
+    pub fn add_event<E: Event>(&mut self) {
+        if self.contains_resource::<Events<E>>() {
+            return;
+        }
+
+        self.insert_resource(Events::<E>::new());
+        self.event_updaters
+            .push(|world| world.resource_mut::<Events<E>>().update());
+    }
+
+    pub fn send_event<E: Event>(&mut self, event: E) {
+        self.get_resource_mut::<Events<E>>()
+            .unwrap_or_else(|| {
+                panic!(
+                    "Event {} has not been added with World::add_event!",
+                    type_name::<E>()
+                );
+            })
+            .send(event);
+    }
+
+    // Advances every event type by one frame. Call once per frame, before the
+    // frame's events are sent.
+    pub fn update_events(&mut self) {
+        for update in self.event_updaters.clone() {
+            update(self);
+        }
+    }
+
+    pub fn change_tick(&self) -> Tick {
+        Tick(self.change_tick.load(Ordering::Relaxed))
+    }
+
+    // Every system run gets its own tick so it can tell the changes made by
+    // other systems apart from its own.
+    pub fn increment_change_tick(&self) -> Tick {
+        Tick(
+            self.change_tick
+                .fetch_add(1, Ordering::Relaxed)
+                .wrapping_add(1),
+        )
     }
 
-    pub fn query<'a, Q>(&'a self) -> impl Iterator<Item = Q::Item> + 'a
+    #[allow(dead_code)]
+    pub fn last_change_tick(&self) -> Tick {
+        self.last_change_tick
+    }
+
+    // The tick window used by `World::query`: everything since the last
+    // `clear_trackers` call.
+    pub fn ticks(&self) -> Ticks {
+        Ticks {
+            last_run: self.last_change_tick,
+            this_run: self.change_tick(),
+        }
+    }
+
+    // Ends the current change-detection frame. Removals are kept for one more
+    // frame so readers that ran before the removal still get to see it.
+    pub fn clear_trackers(&mut self) {
+        let ticks = self.ticks();
+        for removed in self.removed.values_mut() {
+            removed.retain(|(_, tick)| tick.is_newer_than(ticks.last_run, ticks.this_run));
+        }
+
+        self.last_change_tick = ticks.this_run;
+        self.increment_change_tick();
+    }
+
+    #[allow(dead_code)]
+    pub fn removed<T: Component>(&self) -> RemovedComponents<'_, T> {
+        self.removed_with_ticks(self.ticks())
+    }
+
+    pub fn removed_with_ticks<T: Component>(&self, ticks: Ticks) -> RemovedComponents<'_, T> {
+        let removed = self
+            .removed
+            .get(&TypeId::of::<T>())
+            .map_or(&[][..], |removed| removed.as_slice());
+        RemovedComponents::new(removed, ticks)
+    }
+
+    pub fn query<'a, Q>(&'a self) -> impl Iterator<Item = Q::Item<'a>> + 'a
     where
-        Q: Query<'a> + 'a,
+        Q: ReadOnlyWorldQuery + 'a,
     {
-        Q::fetch(&self.components)
+        self.query_filtered::<Q, ()>()
     }
 
-    pub fn query_mut<T: Component>(&mut self) -> impl Iterator<Item = (EntityId, &mut T)> {
-        self.components.query_mut::<T>()
+    pub fn query_filtered<'a, Q, F>(&'a self) -> impl Iterator<Item = Q::Item<'a>> + 'a
+    where
+        Q: ReadOnlyWorldQuery + 'a,
+        F: QueryFilter + 'a,
+    {
+        // Safety: read-only queries only ever create shared references.
+        unsafe { self.query_unchecked::<Q, F>(self.ticks()) }
+    }
+
+    #[allow(dead_code)]
+    pub fn query_mut<'a, Q>(&'a mut self) -> impl Iterator<Item = Q::Item<'a>> + 'a
+    where
+        Q: WorldQuery + 'a,
+    {
+        self.query_filtered_mut::<Q, ()>()
+    }
+
+    pub fn query_filtered_mut<'a, Q, F>(&'a mut self) -> impl Iterator<Item = Q::Item<'a>> + 'a
+    where
+        Q: WorldQuery + 'a,
+        F: QueryFilter + 'a,
+    {
+        let mut access = Access::new();
+        Q::access(&mut access);
+        F::access(&mut access);
+
+        // Safety: `access` panicked on any aliasing and `self` is borrowed mutably.
+        unsafe { self.query_unchecked::<Q, F>(self.ticks()) }
+    }
+
+    // Safety: the caller must have validated the query's `Access` and make sure
+    // nothing else touches the components it writes while the iterator is alive.
+    pub unsafe fn query_unchecked<'a, Q, F>(
+        &'a self,
+        ticks: Ticks,
+    ) -> Box<dyn Iterator<Item = Q::Item<'a>> + 'a>
+    where
+        Q: WorldQuery + 'a,
+        F: QueryFilter + 'a,
+    {
+        unsafe { Q::fetch::<F>(&self.components, ticks) }
+    }
+}
+
+// Immediate access to a single entity. Use `Commands::entity` to queue the same
+// operations from inside a system instead.
+pub struct EntityWorldMut<'w> {
+    entity: EntityId,
+    world: &'w mut World,
+}
+
+#[allow(dead_code)]
+impl EntityWorldMut<'_> {
+    pub fn id(&self) -> EntityId {
+        self.entity
+    }
+
+    pub fn get<T: Component>(&self) -> Option<&T> {
+        self.world.get::<T>(&self.entity)
+    }
+
+    pub fn get_mut<T: Component>(&mut self) -> Option<&mut T> {
+        self.world.get_mut::<T>(&self.entity)
+    }
+
+    pub fn insert<T: Component>(&mut self, component: T) -> &mut Self {
+        self.world.insert(self.entity, component);
+        self
+    }
+
+    pub fn remove<T: Component>(&mut self) -> &mut Self {
+        self.world.remove::<T>(self.entity);
+        self
+    }
+
+    pub fn take<T: Component>(&mut self) -> Option<T> {
+        self.world.remove::<T>(self.entity)
+    }
+
+    pub fn insert_bundle<B: Bundle>(&mut self, bundle: B) -> &mut Self {
+        bundle.insert_all(self.world, self.entity);
+        self
+    }
+
+    pub fn remove_bundle<B: Bundle>(&mut self) -> &mut Self {
+        B::remove_all(self.world, self.entity);
+        self
+    }
+
+    pub fn set_parent(&mut self, parent: EntityId) -> &mut Self {
+        self.world.set_parent(self.entity, parent);
+        self
+    }
+
+    pub fn remove_parent(&mut self) -> &mut Self {
+        self.world.remove_parent(self.entity);
+        self
+    }
+
+    pub fn observe<T: Component>(
+        &mut self,
+        trigger: Trigger,
+        observer: impl FnMut(&mut World, EntityId) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.world.observe::<T>(self.entity, trigger, observer);
+        self
+    }
+
+    pub fn despawn(self) {
+        self.world.despawn(self.entity);
+    }
+
+    pub fn despawn_recursive(self) {
+        self.world.despawn_recursive(self.entity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::components::components::StorageType;
+
+    #[derive(Debug, PartialEq)]
+    struct Unit(u32);
+    impl Component for Unit {}
+
+    #[derive(Debug, PartialEq)]
+    struct Wounded(u32);
+    impl Component for Wounded {}
+
+    #[derive(Debug, PartialEq)]
+    struct Selected;
+    impl Component for Selected {
+        const STORAGE: StorageType = StorageType::SparseSet;
+    }
+
+    #[test]
+    fn stale_ids_do_not_reach_reused_entities() {
+        let mut world = World::empty();
+        let fallen = world.spawn_empty();
+        world.insert(fallen, Unit(1));
+        world.insert(fallen, Wounded(5));
+        assert!(world.despawn(fallen));
+        assert!(!world.despawn(fallen));
+
+        let recruit = world.spawn_empty();
+        assert_eq!(recruit.index, fallen.index);
+        assert!(world.get::<Unit>(&fallen).is_none());
+        assert!(world.get::<Unit>(&recruit).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "dead entity")]
+    fn inserting_into_despawned_entities_panics() {
+        let mut world = World::empty();
+        let fallen = world.spawn_empty();
+        world.despawn(fallen);
+        world.spawn_empty();
+        world.insert(fallen, Unit(2));
+    }
+
+    #[test]
+    fn removing_from_dead_entities_does_nothing() {
+        let mut world = World::empty();
+        let entity = world.spawn_empty();
+        world.insert(entity, Unit(1));
+        assert_eq!(world.remove::<Unit>(entity), Some(Unit(1)));
+        assert_eq!(world.remove::<Unit>(entity), None);
+
+        world.insert(entity, Unit(2));
+        world.despawn(entity);
+        assert_eq!(world.remove::<Unit>(entity), None);
+    }
+
+    #[test]
+    fn entity_mut_chains_inserts_and_removals() {
+        let mut world = World::empty();
+        let entity = world.spawn((Unit(1), Wounded(3)));
+        world
+            .entity_mut(entity)
+            .remove::<Unit>()
+            .insert_bundle((Unit(2),));
+
+        assert_eq!(world.get::<Unit>(&entity), Some(&Unit(2)));
+        assert_eq!(world.entity_mut(entity).take::<Wounded>(), Some(Wounded(3)));
+        assert_eq!(world.entity_mut(entity).take::<Wounded>(), None);
+
+        world.entity_mut(entity).remove_bundle::<(Unit,)>();
+        assert!(world.contains(entity));
+        assert!(world.get::<Unit>(&entity).is_none());
+    }
+
+    // Runs the same mix of spawns, inserts, removals and despawns that moves
+    // entities between archetypes.
+    fn build() -> World {
+        let mut world = World::empty();
+        let mut entities = Vec::new();
+        for i in 0..64 {
+            let entity = world.spawn(Unit(i));
+            if i % 3 == 0 {
+                world.insert(entity, Wounded(i));
+            }
+            if i % 5 == 0 {
+                world.insert(entity, Selected);
+            }
+            entities.push(entity);
+        }
+        for (i, entity) in entities.iter().enumerate() {
+            if i % 7 == 0 {
+                world.despawn(*entity);
+            } else if i % 4 == 0 {
+                world.remove::<Wounded>(*entity);
+            }
+        }
+        for i in 64..80 {
+            world.spawn((Unit(i), Wounded(i)));
+        }
+        world
+    }
+
+    fn units(world: &World) -> Vec<(EntityId, u32, bool)> {
+        world
+            .query::<(EntityId, &Unit, Option<&Wounded>)>()
+            .map(|(entity, unit, wounded)| (entity, unit.0, wounded.is_some()))
+            .collect()
+    }
+
+    #[test]
+    fn identical_worlds_iterate_identically() {
+        let (a, b) = (build(), build());
+
+        assert_eq!(units(&a), units(&b));
+        assert_eq!(
+            a.query::<(EntityId, &Selected)>()
+                .map(|(entity, _)| entity)
+                .collect::<Vec<_>>(),
+            b.query::<(EntityId, &Selected)>()
+                .map(|(entity, _)| entity)
+                .collect::<Vec<_>>(),
+        );
+    }
+
+    #[test]
+    fn sorted_queries_follow_the_key() {
+        use crate::crowengine::systems::system::{IntoSystem, System};
+        use crate::crowengine::systems::system_param::Query;
+
+        fn check(query: Query<(EntityId, &Unit)>) {
+            let entities: Vec<EntityId> = query
+                .iter_sorted_by_key(|(entity, _)| *entity)
+                .map(|(entity, _)| entity)
+                .collect();
+            assert!(entities.windows(2).all(|pair| pair[0] < pair[1]));
+            assert_eq!(entities.len(), query.iter().count());
+        }
+
+        let mut world = build();
+        let mut system = check.into_system();
+        system.initialize(&mut world);
+        system.run(&mut world);
+    }
+
+    fn children(world: &World, parent: EntityId) -> Vec<EntityId> {
+        world
+            .get::<Children>(&parent)
+            .map_or_else(Vec::new, |children| children.iter().collect())
+    }
+
+    #[test]
+    fn set_parent_moves_children_between_parents() {
+        let mut world = World::empty();
+        let (army, fleet) = (world.spawn(Unit(0)), world.spawn(Unit(1)));
+        let scout = world.spawn(Unit(2));
+
+        world.set_parent(scout, army);
+        assert_eq!(children(&world, army), vec![scout]);
+
+        world.entity_mut(scout).set_parent(fleet);
+        assert_eq!(world.get::<Parent>(&scout).map(Parent::get), Some(fleet));
+        assert_eq!(children(&world, fleet), vec![scout]);
+        assert!(world.get::<Children>(&army).is_none());
+
+        world.remove_parent(scout);
+        assert!(world.get::<Parent>(&scout).is_none());
+        assert!(world.get::<Children>(&fleet).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "descendants")]
+    fn set_parent_rejects_cycles() {
+        let mut world = World::empty();
+        let (army, scout) = (world.spawn(Unit(0)), world.spawn(Unit(1)));
+        world.set_parent(scout, army);
+        world.set_parent(army, scout);
+    }
+
+    #[test]
+    fn despawning_a_child_detaches_it_from_its_parent() {
+        let mut world = World::empty();
+        let army = world.spawn(Unit(0));
+        let (scout, archer) = (world.spawn(Unit(1)), world.spawn(Unit(2)));
+        world.set_parent(scout, army);
+        world.set_parent(archer, army);
+
+        world.despawn(scout);
+        assert_eq!(children(&world, army), vec![archer]);
+
+        world.despawn(archer);
+        assert!(world.get::<Children>(&army).is_none());
+    }
+
+    #[test]
+    fn despawning_a_parent_turns_its_children_into_roots() {
+        let mut world = World::empty();
+        let army = world.spawn(Unit(0));
+        let (scout, archer) = (world.spawn(Unit(1)), world.spawn(Unit(2)));
+        world.set_parent(scout, army);
+        world.set_parent(archer, scout);
+
+        world.despawn(scout);
+        assert!(world.contains(archer));
+        assert!(world.get::<Parent>(&archer).is_none());
+        assert!(world.get::<Children>(&army).is_none());
+    }
+
+    #[test]
+    fn despawn_recursive_takes_the_subtree_along() {
+        let mut world = World::empty();
+        let army = world.spawn(Unit(0));
+        let (scout, archer) = (world.spawn(Unit(1)), world.spawn(Unit(2)));
+        world.set_parent(scout, army);
+        world.set_parent(archer, scout);
+
+        let mut queue = CommandQueue::new();
+        crate::crowengine::commands::Commands::new(&mut queue, &world)
+            .entity(scout)
+            .despawn_recursive();
+        world.apply(&mut queue);
+
+        assert!(!world.contains(scout) && !world.contains(archer));
+        assert!(world.get::<Children>(&army).is_none());
     }
 }
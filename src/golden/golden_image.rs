@@ -0,0 +1,142 @@
+use crate::crowengine::app::App;
+use crate::crowengine::default_plugins::offscreen_plugins;
+use crate::crowengine::world::World;
+use crate::window::offscreen::OffscreenContext;
+
+use image::{Rgba, RgbaImage};
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+// Set to regenerate every reference image instead of comparing against it.
+pub const UPDATE_VARIABLE: &str = "UPDATE_GOLDEN";
+
+// How far a rendered image may stray from its reference. Software rasterizers
+// differ slightly between Mesa versions and CPUs, mostly along edges, so a few
+// pixels are allowed to be off by more than the per-channel tolerance.
+#[derive(Copy, Clone, Debug)]
+pub struct Tolerance {
+    pub per_channel: u8,
+    pub mismatched_pixels: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            per_channel: 2,
+            mismatched_pixels: 0.005,
+        }
+    }
+}
+
+// Renders a single frame of whatever `setup` spawns, with the default plugins
+// drawing into an offscreen framebuffer instead of a window.
+pub fn render_scene(width: i32, height: i32, setup: impl FnOnce(&mut World)) -> RgbaImage {
+    let mut app = App::new();
+    app.add_plugins(offscreen_plugins(width, height));
+    setup(&mut app.world);
+    app.update();
+
+    app.world.resource::<OffscreenContext>().read_pixels()
+}
+
+fn reference_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name))
+}
+
+fn output_path(name: &str, kind: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("target/golden")
+        .join(format!("{}.{}.png", name, kind))
+}
+
+// Compares `image` with `tests/golden/<name>.png`. On failure the rendered
+// image and a diff, with mismatched pixels in red over a faded copy of the
+// reference, are written to `target/golden/` before panicking.
+pub fn assert_golden(name: &str, image: &RgbaImage, tolerance: Tolerance) {
+    let reference_path = reference_path(name);
+
+    if env::var_os(UPDATE_VARIABLE).is_some() {
+        fs::create_dir_all(reference_path.parent().unwrap()).unwrap();
+        image.save(&reference_path).unwrap_or_else(|error| {
+            panic!(
+                "Could not write reference image {}: {}!",
+                reference_path.display(),
+                error
+            );
+        });
+        return;
+    }
+
+    let reference = match image::open(&reference_path) {
+        Ok(reference) => reference.to_rgba8(),
+        Err(error) => panic!(
+            "Could not open reference image {}: {}! Run with {}=1 to create it.",
+            reference_path.display(),
+            error,
+            UPDATE_VARIABLE
+        ),
+    };
+
+    if reference.dimensions() != image.dimensions() {
+        let actual_path = save_output(name, "actual", image);
+        panic!(
+            "Image '{}' is {:?} but its reference is {:?}! Rendered image written to {}.",
+            name,
+            image.dimensions(),
+            reference.dimensions(),
+            actual_path.display()
+        );
+    }
+
+    let mut diff = RgbaImage::new(image.width(), image.height());
+    let mut mismatched = 0;
+    for ((expected, actual), diff) in reference
+        .pixels()
+        .zip(image.pixels())
+        .zip(diff.pixels_mut())
+    {
+        let difference = expected
+            .0
+            .iter()
+            .zip(actual.0.iter())
+            .map(|(expected, actual)| expected.abs_diff(*actual))
+            .max()
+            .unwrap();
+
+        *diff = if difference > tolerance.per_channel {
+            mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            let [red, green, blue, _] = expected.0;
+            let luma = (red as u32 + green as u32 + blue as u32) / 3;
+            let faded = (luma / 4 + 192) as u8;
+            Rgba([faded, faded, faded, 255])
+        };
+    }
+
+    let allowed = (tolerance.mismatched_pixels * (image.width() * image.height()) as f32) as u32;
+    if mismatched > allowed {
+        let actual_path = save_output(name, "actual", image);
+        let diff_path = save_output(name, "diff", &diff);
+        panic!(
+            "Image '{}' differs from its reference in {} pixels, {} are allowed! \
+             Rendered image written to {} and diff to {}.",
+            name,
+            mismatched,
+            allowed,
+            actual_path.display(),
+            diff_path.display()
+        );
+    }
+}
+
+fn save_output(name: &str, kind: &str, image: &RgbaImage) -> PathBuf {
+    let path = output_path(name, kind);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    image.save(&path).unwrap();
+    path
+}
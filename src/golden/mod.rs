@@ -0,0 +1,2 @@
+pub mod golden_image;
+mod render_tests;
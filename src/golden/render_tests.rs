@@ -0,0 +1,32 @@
+use super::golden_image::{assert_golden, render_scene, Tolerance};
+
+use crate::crowengine::backends::render_backend::{PolygonMode, Renderer};
+
+const WIDTH: i32 = 160;
+const HEIGHT: i32 = 120;
+
+// The scene `main` starts with: three cubes sharing one texture, each drawn
+// with a different color and through the default shader.
+#[test]
+fn three_textured_cubes() {
+    let image = render_scene(WIDTH, HEIGHT, crate::setup);
+
+    assert_golden("three_textured_cubes", &image, Tolerance::default());
+}
+
+#[test]
+fn three_textured_cubes_wireframe() {
+    let image = render_scene(WIDTH, HEIGHT, |world| {
+        crate::setup(world);
+        world
+            .resource_mut::<Renderer>()
+            .backend_mut()
+            .set_polygon_mode(PolygonMode::Line);
+    });
+
+    assert_golden(
+        "three_textured_cubes_wireframe",
+        &image,
+        Tolerance::default(),
+    );
+}
@@ -1,28 +1,33 @@
-extern crate gl;
 extern crate glfw;
-use glfw::Context;
+use glfw::Key;
 
 use nalgebra_glm as glm;
 
 extern crate image;
 
 mod window;
-use window::create_window::create_window;
+use window::input::Keys;
+use window::window_plugin::WindowSize;
 
 mod crowengine;
-use crate::crowengine::shaders::{
-    fragment_shader::FRAGMENT_SHADER_SOURCE, shader::Shader, vertex_shader::VERTEX_SHADER_SOURCE,
-};
 
-use crate::crowengine::loaders::load_texture::load_texture;
+#[cfg(all(test, feature = "offscreen"))]
+mod golden;
+use crate::crowengine::app::App;
+use crate::crowengine::assets::Textures;
+use crate::crowengine::backends::render_backend::{PolygonMode, Renderer};
+use crate::crowengine::default_plugins::default_plugins;
+use crate::crowengine::time::Time;
+
 use crate::crowengine::meshes::cube::Cube;
 use crate::crowengine::meshes::mesh::Mesh;
-
-use crate::crowengine::commands::Commands;
+use crate::crowengine::meshes::mesh_bundle::MeshBundle;
 
 use crate::crowengine::world::World;
 
 use crate::crowengine::components::color::Color;
+use crate::crowengine::components::filter::Without;
+use crate::crowengine::components::hierarchy::Parent;
 use crate::crowengine::components::texture::Texture;
 use crate::crowengine::components::transform::Transform;
 
@@ -30,106 +35,89 @@ use crate::crowengine::components::use_shader::UseShader;
 
 use crate::crowengine::components::camera::Camera;
 
-use crate::crowengine::render::render;
+use crate::crowengine::systems::schedule::Stage;
+use crate::crowengine::systems::system_param::{Query, Res};
 
 fn main() {
-    let (mut glfw, mut window, events) = create_window();
-
-    let (width, height) = window.get_framebuffer_size();
-
-    let texture = load_texture("src/assets/brick_texture.jpg");
-
-    let mut world = World::new(Shader::new(VERTEX_SHADER_SOURCE, FRAGMENT_SHADER_SOURCE));
-
-    let mut commands = Commands::new(&mut world);
+    App::new()
+        .add_plugins(default_plugins())
+        .add_startup_system(setup)
+        .add_system(Stage::Update, move_forward)
+        .add_system(Stage::Update, toggle_wireframe)
+        .run();
+}
 
-    commands.spawn((
+// Exclusive, since loading textures needs the `Renderer`.
+fn setup(world: &mut World) {
+    let aspect_ratio = world.resource::<WindowSize>().aspect_ratio();
+    let texture = world.resource_scope(|world, textures: &mut Textures| {
+        textures.load(
+            world.resource_mut::<Renderer>(),
+            "src/assets/brick_texture.jpg",
+        )
+    });
+
+    world.spawn((
         Camera {
             fov_y: glm::radians(&glm::vec1(45.0)).x,
             near: 0.1,
             far: 100.0,
-            aspect_ratio: width as f32 / height as f32,
+            aspect_ratio,
         },
         Transform::from_xyz(0.0, 0.0, 3.0),
     ));
 
-    commands.spawn((
-        Transform::from_xyz(0.0, 0.0, 0.0).with_euler_rotation(20., 20., 20.),
-        Mesh::new(Cube::new(0.5, 0.5, 0.5)),
-        Texture::new(texture),
+    world.spawn((
+        MeshBundle {
+            transform: Transform::from_xyz(0.0, 0.0, 0.0).with_euler_rotation(20., 20., 20.),
+            mesh: Mesh::new(Cube::new(0.5, 0.5, 0.5)),
+            texture: Texture::new(texture),
+        },
         UseShader::new("default".to_string()),
         Color::new(1., 1., 1.),
     ));
 
-    commands.spawn((
+    world.spawn((
         Transform::from_xyz(0.5, 0.5, 0.0).with_euler_rotation(20., 20., 20.),
         Mesh::new(Cube::new(0.5, 0.5, 0.5)),
         Texture::new(texture),
         Color::new(1., 0., 0.),
     ));
 
-    commands.spawn((
-        Transform::from_xyz(-0.5, 0.5, 0.0).with_euler_rotation(20., 20., 20.),
-        Mesh::new(Cube::new(0.5, 0.5, 0.5)),
-        Texture::new(texture),
-    ));
-
-    while !window.should_close() {
-        glfw.poll_events();
-
-        for (_, event) in glfw::flush_messages(&events) {
-            use glfw::Action;
-            use glfw::Key;
-            use glfw::WindowEvent as Event;
-
-            match event {
-                Event::Key(Key::Escape, _, Action::Press, _) => {
-                    window.set_should_close(true);
-                }
-                Event::FramebufferSize(width, height) => unsafe {
-                    gl::Viewport(0, 0, width, height);
-                },
-                _ => {}
-            }
-        }
-
-        process_input(&mut window, &mut world);
-
-        unsafe {
-            gl::ClearColor(0.2, 0.3, 0.3, 1.0);
-
-            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
-        }
-
-        render(&mut world);
-
-        window.swap_buffers();
-    }
+    world.spawn(MeshBundle {
+        transform: Transform::from_xyz(-0.5, 0.5, 0.0).with_euler_rotation(20., 20., 20.),
+        mesh: Mesh::new(Cube::new(0.5, 0.5, 0.5)),
+        texture: Texture::new(texture),
+    });
 }
 
-fn process_input(window: &mut glfw::Window, world: &mut World) {
-    use glfw::Action;
-    use glfw::Key;
+const SPEED: f32 = 6.0;
 
-    if window.get_key(Key::W) == Action::Press {
-        for (_, transform) in world.query_mut::<Transform>() {
-            transform.position.z += 0.1;
-        }
-    }
-
-    if window.get_key(Key::Escape) == Action::Press {
-        window.set_should_close(true);
-    }
-
-    if window.get_key(Key::G) == Action::Press {
-        unsafe {
-            gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
+fn move_forward(
+    keys: Res<Keys>,
+    time: Res<Time>,
+    mut transforms: Query<(&mut Transform,), Without<Parent>>,
+) {
+    if keys.pressed(Key::W) {
+        for (transform,) in transforms.iter_mut() {
+            transform.position.z += SPEED * time.delta_seconds();
         }
     }
+}
 
-    if window.get_key(Key::F) == Action::Press {
-        unsafe {
-            gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
-        }
-    }
+// Exclusive, since it uses the `Renderer`.
+fn toggle_wireframe(world: &mut World) {
+    let keys = world.resource::<Keys>();
+    let mode = if keys.just_pressed(Key::G) {
+        PolygonMode::Line
+    } else if keys.just_pressed(Key::F) {
+        PolygonMode::Fill
+    } else {
+        return;
+    };
+
+    world
+        .resource_mut::<Renderer>()
+        .backend_mut()
+        .set_polygon_mode(mode);
 }
@@ -0,0 +1,60 @@
+use crate::crowengine::app::{App, Plugin};
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Runs the schedule without opening a window, until something sends
+// `AppExit`. With a `frame_time` every frame is padded to at least that long,
+// e.g. to tick a simulation server at a fixed rate instead of spinning.
+pub struct HeadlessPlugin {
+    pub frame_time: Option<Duration>,
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        let frame_time = self.frame_time;
+
+        app.set_runner(move |mut app| loop {
+            let start = Instant::now();
+
+            app.update();
+            if app.should_exit() {
+                return;
+            }
+
+            if let Some(remaining) = frame_time.and_then(|time| time.checked_sub(start.elapsed())) {
+                thread::sleep(remaining);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::crowengine::app::{App, AppExit};
+    use crate::crowengine::default_plugins::headless_plugins;
+    use crate::crowengine::events::EventWriter;
+    use crate::crowengine::systems::schedule::Stage;
+    use crate::crowengine::systems::system_param::Res;
+    use crate::crowengine::time::Time;
+
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn runs_padded_frames_until_exit() {
+        let mut app = App::new();
+        app.add_plugins(headless_plugins(Some(Duration::from_millis(2))))
+            .add_system(
+                Stage::Update,
+                |time: Res<Time>, mut exit: EventWriter<AppExit>| {
+                    if time.frame_count() == 5 {
+                        exit.send(AppExit);
+                    }
+                },
+            );
+
+        let start = Instant::now();
+        app.run();
+        assert!(start.elapsed() >= Duration::from_millis(8));
+    }
+}
@@ -0,0 +1,82 @@
+extern crate glfw;
+use glfw::{Action, Key};
+
+use crate::crowengine::app::{App, Plugin};
+use crate::crowengine::events::EventReader;
+use crate::crowengine::resources::Resource;
+use crate::crowengine::systems::schedule::Stage;
+use crate::crowengine::systems::system_param::ResMut;
+
+use super::window_events::KeyboardInput;
+
+use std::collections::HashSet;
+
+// The keyboard state of the current frame, built from `KeyboardInput` events.
+pub struct Keys {
+    pressed: HashSet<Key>,
+    just_pressed: HashSet<Key>,
+    just_released: HashSet<Key>,
+}
+
+impl Resource for Keys {}
+
+#[allow(dead_code)]
+impl Keys {
+    pub fn new() -> Self {
+        Self {
+            pressed: HashSet::new(),
+            just_pressed: HashSet::new(),
+            just_released: HashSet::new(),
+        }
+    }
+
+    pub fn pressed(&self, key: Key) -> bool {
+        self.pressed.contains(&key)
+    }
+
+    pub fn just_pressed(&self, key: Key) -> bool {
+        self.just_pressed.contains(&key)
+    }
+
+    pub fn just_released(&self, key: Key) -> bool {
+        self.just_released.contains(&key)
+    }
+
+    pub fn press(&mut self, key: Key) {
+        if self.pressed.insert(key) {
+            self.just_pressed.insert(key);
+        }
+    }
+
+    pub fn release(&mut self, key: Key) {
+        if self.pressed.remove(&key) {
+            self.just_released.insert(key);
+        }
+    }
+
+    pub fn clear_just(&mut self) {
+        self.just_pressed.clear();
+        self.just_released.clear();
+    }
+}
+
+fn update_keys(mut input: EventReader<KeyboardInput>, mut keys: ResMut<Keys>) {
+    keys.clear_just();
+    for input in input.read() {
+        match input.action {
+            Action::Press => keys.press(input.key),
+            Action::Release => keys.release(input.key),
+            Action::Repeat => {}
+        }
+    }
+}
+
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<KeyboardInput>()
+            .insert_resource(Keys::new())
+            .add_system(Stage::PreUpdate, update_keys);
+    }
+}
@@ -1 +1,7 @@
 pub mod create_window;
+pub mod headless;
+pub mod input;
+#[cfg(feature = "offscreen")]
+pub mod offscreen;
+pub mod window_events;
+pub mod window_plugin;
@@ -0,0 +1,348 @@
+extern crate gl;
+
+use crate::crowengine::app::{App, Plugin};
+use crate::crowengine::resources::Resource;
+
+use super::window_plugin::WindowSize;
+
+use image::RgbaImage;
+
+use std::ffi::{c_char, c_void, CString};
+use std::ptr;
+use std::sync::Once;
+use std::thread::{self, ThreadId};
+
+type EGLDisplay = *mut c_void;
+type EGLConfig = *mut c_void;
+type EGLContext = *mut c_void;
+type EGLSurface = *mut c_void;
+type EGLAttrib = isize;
+type EGLint = i32;
+type EGLBoolean = u32;
+type EGLenum = u32;
+
+const EGL_PLATFORM_SURFACELESS_MESA: EGLenum = 0x31DD;
+const EGL_OPENGL_API: EGLenum = 0x30A2;
+const EGL_NONE: EGLint = 0x3038;
+const EGL_SURFACE_TYPE: EGLint = 0x3033;
+const EGL_PBUFFER_BIT: EGLint = 0x0001;
+const EGL_RENDERABLE_TYPE: EGLint = 0x3040;
+const EGL_OPENGL_BIT: EGLint = 0x0008;
+const EGL_CONTEXT_MAJOR_VERSION: EGLint = 0x3098;
+const EGL_CONTEXT_MINOR_VERSION: EGLint = 0x30FB;
+const EGL_CONTEXT_OPENGL_PROFILE_MASK: EGLint = 0x30FD;
+const EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT: EGLint = 0x0001;
+
+#[link(name = "EGL")]
+unsafe extern "C" {
+    fn eglGetPlatformDisplay(
+        platform: EGLenum,
+        native_display: *mut c_void,
+        attrib_list: *const EGLAttrib,
+    ) -> EGLDisplay;
+    fn eglInitialize(display: EGLDisplay, major: *mut EGLint, minor: *mut EGLint) -> EGLBoolean;
+    fn eglChooseConfig(
+        display: EGLDisplay,
+        attrib_list: *const EGLint,
+        configs: *mut EGLConfig,
+        config_size: EGLint,
+        num_config: *mut EGLint,
+    ) -> EGLBoolean;
+    fn eglBindAPI(api: EGLenum) -> EGLBoolean;
+    fn eglCreateContext(
+        display: EGLDisplay,
+        config: EGLConfig,
+        share_context: EGLContext,
+        attrib_list: *const EGLint,
+    ) -> EGLContext;
+    fn eglMakeCurrent(
+        display: EGLDisplay,
+        draw: EGLSurface,
+        read: EGLSurface,
+        context: EGLContext,
+    ) -> EGLBoolean;
+    fn eglDestroyContext(display: EGLDisplay, context: EGLContext) -> EGLBoolean;
+    fn eglGetProcAddress(name: *const c_char) -> *const c_void;
+    fn eglGetError() -> EGLint;
+}
+
+// A GL 3.3 core context without a window or display, rendering into a
+// framebuffer object that can be read back as an image. It runs on Mesa's
+// surfaceless EGL platform, which falls back to the llvmpipe software
+// rasterizer when there is no GPU, so CI machines can render frames too.
+//
+// The context is current on the thread that created it, and all rendering has
+// to happen on that thread. Reading pixels from any other thread panics.
+pub struct OffscreenContext {
+    display: EGLDisplay,
+    context: EGLContext,
+    framebuffer: u32,
+    renderbuffers: [u32; 2],
+    width: i32,
+    height: i32,
+    owner: ThreadId,
+}
+
+// Safety: every method making GL calls checks that it runs on `owner`, so
+// sharing the context only ever shares plain handles. Resources have to be
+// `Send + Sync`, since systems may read them from any thread.
+unsafe impl Send for OffscreenContext {}
+unsafe impl Sync for OffscreenContext {}
+
+impl Resource for OffscreenContext {}
+
+#[allow(dead_code)]
+impl OffscreenContext {
+    pub fn new(width: i32, height: i32) -> Self {
+        if width <= 0 || height <= 0 {
+            panic!(
+                "Offscreen framebuffer must not be empty, got {}x{}!",
+                width, height
+            );
+        }
+
+        let (display, context) = unsafe { create_context() };
+        load_gl();
+
+        let mut framebuffer = 0;
+        let mut renderbuffers = [0; 2];
+        unsafe {
+            gl::GenFramebuffers(1, &mut framebuffer);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, framebuffer);
+
+            gl::GenRenderbuffers(2, renderbuffers.as_mut_ptr());
+            gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffers[0]);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::RGBA8, width, height);
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::RENDERBUFFER,
+                renderbuffers[0],
+            );
+
+            gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffers[1]);
+            gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width, height);
+            gl::FramebufferRenderbuffer(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_STENCIL_ATTACHMENT,
+                gl::RENDERBUFFER,
+                renderbuffers[1],
+            );
+
+            if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+                panic!("Offscreen framebuffer is incomplete!");
+            }
+
+            // The same state `create_window` sets up for the window.
+            gl::Viewport(0, 0, width, height);
+            gl::Enable(gl::DEPTH_TEST);
+            gl::ClearColor(0.2, 0.3, 0.3, 1.0);
+        }
+
+        Self {
+            display,
+            context,
+            framebuffer,
+            renderbuffers,
+            width,
+            height,
+            owner: thread::current().id(),
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    // Waits for rendering to finish and copies the framebuffer, top row first.
+    pub fn read_pixels(&self) -> RgbaImage {
+        if !self.is_owning_thread() {
+            panic!("Offscreen pixels can only be read on the thread that created the context!");
+        }
+
+        let mut pixels = vec![0u8; (self.width * self.height * 4) as usize];
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.framebuffer);
+            gl::PixelStorei(gl::PACK_ALIGNMENT, 1);
+            gl::ReadPixels(
+                0,
+                0,
+                self.width,
+                self.height,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr().cast(),
+            );
+        }
+
+        let image = RgbaImage::from_raw(self.width as u32, self.height as u32, pixels).unwrap();
+        image::imageops::flip_vertical(&image)
+    }
+
+    fn is_owning_thread(&self) -> bool {
+        thread::current().id() == self.owner
+    }
+}
+
+impl Drop for OffscreenContext {
+    // Off the owning thread the GL objects can't be deleted, but destroying the
+    // context frees them as well.
+    fn drop(&mut self) {
+        unsafe {
+            if self.is_owning_thread() {
+                gl::DeleteRenderbuffers(2, self.renderbuffers.as_ptr());
+                gl::DeleteFramebuffers(1, &self.framebuffer);
+            }
+            eglMakeCurrent(
+                self.display,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            );
+            eglDestroyContext(self.display, self.context);
+        }
+    }
+}
+
+// The function pointers are global and the same for every context of the
+// display, so they are loaded once rather than swapped under running tests.
+fn load_gl() {
+    static LOAD: Once = Once::new();
+    LOAD.call_once(|| {
+        gl::load_with(|name| {
+            let name = CString::new(name).unwrap();
+            unsafe { eglGetProcAddress(name.as_ptr()) }
+        });
+    });
+}
+
+// Makes a new context current on this thread. The display is shared by every
+// context of the process, so it is never terminated.
+unsafe fn create_context() -> (EGLDisplay, EGLContext) {
+    unsafe {
+        let display =
+            eglGetPlatformDisplay(EGL_PLATFORM_SURFACELESS_MESA, ptr::null_mut(), ptr::null());
+        if display.is_null() || eglInitialize(display, ptr::null_mut(), ptr::null_mut()) == 0 {
+            panic!(
+                "Failed to initialize a surfaceless EGL display: {:#x}!",
+                eglGetError()
+            );
+        }
+
+        let config_attributes = [
+            EGL_SURFACE_TYPE,
+            EGL_PBUFFER_BIT,
+            EGL_RENDERABLE_TYPE,
+            EGL_OPENGL_BIT,
+            EGL_NONE,
+        ];
+        let mut config = ptr::null_mut();
+        let mut config_count = 0;
+        if eglChooseConfig(
+            display,
+            config_attributes.as_ptr(),
+            &mut config,
+            1,
+            &mut config_count,
+        ) == 0
+            || config_count == 0
+        {
+            panic!(
+                "No EGL config supports desktop OpenGL: {:#x}!",
+                eglGetError()
+            );
+        }
+
+        eglBindAPI(EGL_OPENGL_API);
+        let context_attributes = [
+            EGL_CONTEXT_MAJOR_VERSION,
+            3,
+            EGL_CONTEXT_MINOR_VERSION,
+            3,
+            EGL_CONTEXT_OPENGL_PROFILE_MASK,
+            EGL_CONTEXT_OPENGL_CORE_PROFILE_BIT,
+            EGL_NONE,
+        ];
+        let context = eglCreateContext(
+            display,
+            config,
+            ptr::null_mut(),
+            context_attributes.as_ptr(),
+        );
+        if context.is_null() {
+            panic!(
+                "Failed to create an OpenGL 3.3 context: {:#x}!",
+                eglGetError()
+            );
+        }
+
+        if eglMakeCurrent(display, ptr::null_mut(), ptr::null_mut(), context) == 0 {
+            panic!(
+                "Failed to make the offscreen context current: {:#x}!",
+                eglGetError()
+            );
+        }
+
+        (display, context)
+    }
+}
+
+// Creates an offscreen context in place of a window, for rendering in tests and
+// on machines without a display. Pair it with the `HeadlessPlugin` runner.
+pub struct OffscreenPlugin {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Plugin for OffscreenPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(OffscreenContext::new(self.width, self.height))
+            .insert_resource(WindowSize {
+                width: self.width,
+                height: self.height,
+            });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fresh_contexts_read_back_the_clear_color() {
+        let context = OffscreenContext::new(8, 4);
+        unsafe { gl::Clear(gl::COLOR_BUFFER_BIT) };
+
+        let image = context.read_pixels();
+        assert_eq!(image.dimensions(), (8, 4));
+        assert!(image.pixels().all(|pixel| pixel.0 == [51, 76, 76, 255]));
+    }
+
+    #[test]
+    fn contexts_can_be_created_on_several_threads() {
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                thread::spawn(|| {
+                    let context = OffscreenContext::new(2, 2);
+                    unsafe { gl::Clear(gl::COLOR_BUFFER_BIT) };
+                    context.read_pixels().get_pixel(0, 0).0
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            assert_eq!(thread.join().unwrap(), [51, 76, 76, 255]);
+        }
+    }
+
+    #[test]
+    fn pixels_are_only_read_on_the_owning_thread() {
+        let context = OffscreenContext::new(2, 2);
+        let result = thread::scope(|scope| scope.spawn(|| context.read_pixels()).join());
+        assert!(result.is_err());
+    }
+}
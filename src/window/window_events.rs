@@ -0,0 +1,112 @@
+extern crate glfw;
+use glfw::{Action, Key, Modifiers, WindowEvent};
+
+use crate::crowengine::app::AppExit;
+use crate::crowengine::backends::render_backend::Renderer;
+use crate::crowengine::events::{Event, EventReader, EventWriter, Events, ManualEventReader};
+use crate::crowengine::world::World;
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug)]
+pub struct KeyboardInput {
+    pub key: Key,
+    pub action: Action,
+    pub modifiers: Modifiers,
+}
+
+impl Event for KeyboardInput {}
+
+#[derive(Copy, Clone, Debug)]
+pub struct WindowResized {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Event for WindowResized {}
+
+pub fn add_window_events(world: &mut World) {
+    world.add_event::<KeyboardInput>();
+    world.add_event::<WindowResized>();
+}
+
+pub fn send_window_event(world: &mut World, event: WindowEvent) {
+    match event {
+        WindowEvent::Key(key, _, action, modifiers) => world.send_event(KeyboardInput {
+            key,
+            action,
+            modifiers,
+        }),
+        WindowEvent::FramebufferSize(width, height) => {
+            world.send_event(WindowResized { width, height })
+        }
+        _ => {}
+    }
+}
+
+pub fn exit_on_escape(mut keys: EventReader<KeyboardInput>, mut exit: EventWriter<AppExit>) {
+    if keys
+        .read()
+        .any(|input| input.key == Key::Escape && input.action == Action::Press)
+    {
+        exit.send(AppExit);
+    }
+}
+
+// An exclusive system, since it uses the `Renderer`. Does nothing without one,
+// e.g. when the `RenderPlugin` is disabled.
+pub fn resize_viewport() -> impl FnMut(&mut World) + Send {
+    let mut reader = ManualEventReader::<WindowResized>::new();
+    move |world| {
+        let resized = reader
+            .read(world.resource::<Events<WindowResized>>())
+            .last()
+            .copied();
+        if let (Some(resized), Some(renderer)) = (resized, world.get_resource_mut::<Renderer>()) {
+            renderer
+                .backend_mut()
+                .set_viewport(0, 0, resized.width, resized.height);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crowengine::backends::recording_backend::{RecordingBackend, RenderCommand};
+
+    fn resize(world: &mut World, system: &mut impl FnMut(&mut World)) {
+        world.send_event(WindowResized {
+            width: 640,
+            height: 480,
+        });
+        system(world);
+    }
+
+    #[test]
+    fn resizing_sets_the_viewport() {
+        let mut world = World::empty();
+        add_window_events(&mut world);
+        world.insert_resource(Renderer::new(RecordingBackend::new()));
+
+        resize(&mut world, &mut resize_viewport());
+        let renderer = world.resource::<Renderer>();
+        let backend = renderer.backend().downcast_ref::<RecordingBackend>();
+        assert_eq!(
+            backend.unwrap().commands(),
+            [RenderCommand::SetViewport {
+                x: 0,
+                y: 0,
+                width: 640,
+                height: 480,
+            }]
+        );
+    }
+
+    #[test]
+    fn resizing_without_a_renderer_does_nothing() {
+        let mut world = World::empty();
+        add_window_events(&mut world);
+
+        resize(&mut world, &mut resize_viewport());
+    }
+}
@@ -0,0 +1,69 @@
+extern crate glfw;
+use glfw::Context;
+
+use crate::crowengine::app::{App, Plugin};
+use crate::crowengine::events::EventReader;
+use crate::crowengine::resources::Resource;
+use crate::crowengine::systems::schedule::Stage;
+use crate::crowengine::systems::system_param::ResMut;
+
+use super::create_window::create_window;
+use super::window_events::{
+    add_window_events, exit_on_escape, resize_viewport, send_window_event, WindowResized,
+};
+
+// The framebuffer size of the window, kept up to date on resize.
+#[derive(Copy, Clone, Debug)]
+pub struct WindowSize {
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Resource for WindowSize {}
+
+impl WindowSize {
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+}
+
+fn update_window_size(mut resized: EventReader<WindowResized>, mut size: ResMut<WindowSize>) {
+    if let Some(last) = resized.read().last() {
+        size.width = last.width;
+        size.height = last.height;
+    }
+}
+
+// Opens the window, makes its GL context current and installs the runner that
+// polls events, updates the app and swaps buffers until the window closes.
+// Has to be added before anything that talks to GL.
+pub struct WindowPlugin;
+
+impl Plugin for WindowPlugin {
+    fn build(&self, app: &mut App) {
+        let (mut glfw, mut window, events) = create_window();
+        let (width, height) = window.get_framebuffer_size();
+
+        add_window_events(&mut app.world);
+        app.insert_resource(WindowSize { width, height })
+            .add_system(Stage::PreUpdate, update_window_size)
+            .add_system(Stage::PreUpdate, resize_viewport())
+            .add_system(Stage::PreUpdate, exit_on_escape)
+            .set_runner(move |mut app| {
+                while !window.should_close() {
+                    glfw.poll_events();
+                    for (_, event) in glfw::flush_messages(&events) {
+                        send_window_event(&mut app.world, event);
+                    }
+
+                    app.update();
+
+                    if app.should_exit() {
+                        window.set_should_close(true);
+                    }
+
+                    window.swap_buffers();
+                }
+            });
+    }
+}